@@ -0,0 +1,405 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+
+use crate::core::{
+    disk_table::{
+        disk_table::get_disk_table_id,
+        disk_tables_shard::{Levels, SEGMENTS_MAX_LEVEL, SEGMENTS_MIN_LEVEL},
+        local::reader_local_disk_table::ReaderDiskTablePtr,
+    },
+    field::FlexibleField,
+    storage::config::{StorageConfig, DEFAULT_LEVEL_SIZE_MULTIPLIER},
+};
+
+// Tables which are merged together into tables of the output level.
+pub struct Compaction {
+    pub level: Levels,
+    pub output_level: Levels,
+    // tables of level and overlapping tables of the output level
+    pub inputs: Vec<ReaderDiskTablePtr>,
+    // merged tables are split at this size of data blocks, None merges into one table
+    pub target_size: Option<u64>,
+    // inputs are removed without merge, their entries are dropped
+    pub delete_inputs: bool,
+    // deleted keys are dropped by merge, it's only set when no table outside inputs
+    // can hold an older version of a key of inputs
+    pub drop_tombstones: bool,
+}
+
+impl Compaction {
+    pub fn new(level: Levels, output_level: Levels, inputs: Vec<ReaderDiskTablePtr>) -> Self {
+        Self {
+            level,
+            output_level,
+            inputs,
+            target_size: None,
+            delete_inputs: false,
+            drop_tombstones: false,
+        }
+    }
+
+    pub fn input_names(&self) -> Vec<String> {
+        self.inputs
+            .iter()
+            .map(|disk_table| disk_table.get_name().to_string())
+            .collect()
+    }
+
+    // there are no older tables below the last level
+    pub fn is_last_level(&self) -> bool {
+        self.output_level == SEGMENTS_MAX_LEVEL
+    }
+}
+
+// Sizes of data blocks of tables which compactions read and wrote.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CompactionSummary {
+    pub tables_read: usize,
+    pub tables_written: usize,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl CompactionSummary {
+    pub fn add(&mut self, compaction: &Compaction, merged: &[ReaderDiskTablePtr]) {
+        self.tables_read += compaction.inputs.len();
+        self.tables_written += merged.len();
+        self.bytes_read += compaction
+            .inputs
+            .iter()
+            .map(|disk_table| disk_table.data_size())
+            .sum::<u64>();
+        self.bytes_written += merged
+            .iter()
+            .map(|disk_table| disk_table.data_size())
+            .sum::<u64>();
+    }
+}
+
+// Tables of a level as compaction strategies see them.
+pub struct LevelTables {
+    pub level: Levels,
+    // from the newest table, disjoint levels are sorted by keys
+    pub disk_tables: Vec<ReaderDiskTablePtr>,
+    // key ranges of tables don't overlap
+    pub disjoint: bool,
+    // names of tables which are inputs of running compactions
+    pub compacting: HashSet<String>,
+}
+
+impl LevelTables {
+    pub fn is_compacting(&self, disk_table: &ReaderDiskTablePtr) -> bool {
+        self.compacting.contains(disk_table.get_name())
+    }
+
+    pub fn has_compacting(&self) -> bool {
+        !self.compacting.is_empty()
+    }
+
+    // size of data blocks of all tables
+    pub fn size(&self) -> u64 {
+        self.disk_tables
+            .iter()
+            .map(|disk_table| disk_table.data_size())
+            .sum()
+    }
+
+    // tables which can have keys in [smallest, largest], tables without properties can have any
+    pub fn overlapping(
+        &self,
+        smallest: Option<&FlexibleField>,
+        largest: Option<&FlexibleField>,
+    ) -> Vec<ReaderDiskTablePtr> {
+        self.disk_tables
+            .iter()
+            .filter(|disk_table| {
+                let Some(properties) = disk_table.properties() else {
+                    return true;
+                };
+                smallest.is_none_or(|smallest| *smallest <= properties.largest_key)
+                    && largest.is_none_or(|largest| properties.smallest_key <= *largest)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+// Policy which decides what tables are compacted.
+// It's called after every flush and compaction until it returns None.
+// Compactions run in parallel, a compaction with inputs of a running one
+// or with the same output level is rejected, so strategies skip such tables.
+pub trait CompactionStrategy: Send + Sync {
+    // `levels` are sorted from the first level, empty levels are skipped
+    fn pick(&self, levels: &[LevelTables], config: &StorageConfig) -> Option<Compaction>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompactionStyle {
+    SizeTiered,
+    Leveled,
+    Fifo,
+}
+
+impl CompactionStyle {
+    pub fn new_strategy(&self) -> Box<dyn CompactionStrategy> {
+        match self {
+            CompactionStyle::SizeTiered => Box::new(SizeTieredStrategy),
+            CompactionStyle::Leveled => Box::new(LeveledStrategy::default()),
+            CompactionStyle::Fifo => Box::new(FifoStrategy),
+        }
+    }
+}
+
+// bytes of data blocks which level holds before it's compacted, the last level is unbounded
+pub fn max_level_size(level: Levels, config: &StorageConfig) -> Option<u64> {
+    if level == SEGMENTS_MIN_LEVEL || level == SEGMENTS_MAX_LEVEL {
+        return None;
+    }
+
+    let multiplier = DEFAULT_LEVEL_SIZE_MULTIPLIER.pow((level - SEGMENTS_MIN_LEVEL - 1) as u32);
+    Some((config.level_base_size * multiplier) as u64)
+}
+
+fn output_level(level: Levels) -> Levels {
+    (level + 1).min(SEGMENTS_MAX_LEVEL)
+}
+
+// some input of compaction is merged by a running compaction
+fn has_compacting_inputs(levels: &[LevelTables], compaction: &Compaction) -> bool {
+    compaction
+        .inputs
+        .iter()
+        .any(|disk_table| levels.iter().any(|tables| tables.is_compacting(disk_table)))
+}
+
+// key range of tables, None is an open bound for tables without properties
+fn key_range(disk_tables: &[ReaderDiskTablePtr]) -> (Option<FlexibleField>, Option<FlexibleField>) {
+    let properties = disk_tables
+        .iter()
+        .map(|disk_table| disk_table.properties())
+        .collect::<Option<Vec<_>>>();
+    let Some(properties) = properties else {
+        return (None, None);
+    };
+
+    (
+        properties
+            .iter()
+            .map(|properties| properties.smallest_key.clone())
+            .min(),
+        properties
+            .iter()
+            .map(|properties| properties.largest_key.clone())
+            .max(),
+    )
+}
+
+// Manual compaction of tables of level which can have keys in [start, end]
+// into the next level, the last level is merged into itself except `skipped` tables.
+// Levels with overlapping tables are taken fully, so no older version of key
+// is left above a newer one. None if level has no such tables.
+pub fn range_compaction(
+    levels: &[LevelTables],
+    level: Levels,
+    start: &FlexibleField,
+    end: &FlexibleField,
+    skipped: &[String],
+    config: &StorageConfig,
+) -> Option<Compaction> {
+    let tables = levels.iter().find(|tables| tables.level == level)?;
+    let mut inputs = tables.overlapping(Some(start), Some(end));
+    inputs.retain(|disk_table| !skipped.iter().any(|name| name == disk_table.get_name()));
+    if inputs.is_empty() {
+        return None;
+    }
+    if !tables.disjoint {
+        inputs = tables.disk_tables.clone();
+    }
+
+    let output_level = output_level(level);
+    if output_level != level {
+        if let Some(output) = levels.iter().find(|tables| tables.level == output_level) {
+            // the whole output level is merged when its tables overlap
+            let (smallest, largest) = if output.disjoint {
+                key_range(&inputs)
+            } else {
+                (None, None)
+            };
+            inputs.extend(output.overlapping(smallest.as_ref(), largest.as_ref()));
+        }
+    }
+
+    // overlapping tables of the last level are inputs
+    let mut compaction = Compaction::new(level, output_level, inputs);
+    compaction.target_size = Some(config.target_table_size as u64);
+    compaction.drop_tombstones = compaction.is_last_level();
+    Some(compaction)
+}
+
+// Size-tiered compaction: every level is a tier of overlapping tables,
+// the oldest `disk_tables_limit_by_level` tables of a tier are merged into one table
+// of the next tier, flushes don't wait for compactions, so a tier can have more tables.
+// The last tier is merged into itself fully, the merged table is read as the newest one.
+// Tables of the last tier aren't merged with the previous tier, so deleted keys are kept
+// until the last tier is merged, unless it has no tables which overlap the merged ones.
+pub struct SizeTieredStrategy;
+
+impl CompactionStrategy for SizeTieredStrategy {
+    fn pick(&self, levels: &[LevelTables], config: &StorageConfig) -> Option<Compaction> {
+        let limit = config.disk_tables_limit_by_level.max(1);
+        levels
+            .iter()
+            .find(|level| level.disk_tables.len() >= limit && !level.has_compacting())
+            .map(|level| {
+                let output_level = output_level(level.level);
+                let oldest = if output_level == level.level {
+                    0
+                } else {
+                    level.disk_tables.len() - limit
+                };
+                let mut compaction = Compaction::new(
+                    level.level,
+                    output_level,
+                    level.disk_tables[oldest..].to_vec(),
+                );
+                compaction.drop_tombstones = compaction.is_last_level()
+                    && (output_level == level.level
+                        || levels
+                            .iter()
+                            .find(|tables| tables.level == output_level)
+                            .is_none_or(|output| {
+                                let (smallest, largest) = key_range(&compaction.inputs);
+                                output
+                                    .overlapping(smallest.as_ref(), largest.as_ref())
+                                    .is_empty()
+                            }));
+                compaction
+            })
+    }
+}
+
+// Leveled compaction:
+// - the first level is merged with overlapping tables of the second one
+//   when it has `disk_tables_limit_by_level` tables,
+// - a level over its size gives one table after the last compacted key of level
+//   to the next level, it's merged with overlapping tables there,
+// - a level with overlapping tables is merged into the next level fully,
+//   the last level is merged into itself.
+#[derive(Default)]
+pub struct LeveledStrategy {
+    // the largest key of the last table compacted from level
+    compact_pointers: Mutex<BTreeMap<Levels, FlexibleField>>,
+}
+
+impl LeveledStrategy {
+    fn compaction(
+        levels: &[LevelTables],
+        level: Levels,
+        mut inputs: Vec<ReaderDiskTablePtr>,
+        config: &StorageConfig,
+    ) -> Compaction {
+        let output_level = output_level(level);
+        if output_level != level {
+            if let Some(output) = levels.iter().find(|tables| tables.level == output_level) {
+                let (smallest, largest) = key_range(&inputs);
+                inputs.extend(output.overlapping(smallest.as_ref(), largest.as_ref()));
+            }
+        }
+
+        // overlapping tables of the last level are inputs
+        let mut compaction = Compaction::new(level, output_level, inputs);
+        compaction.target_size = Some(config.target_table_size as u64);
+        compaction.drop_tombstones = compaction.is_last_level();
+        compaction
+    }
+}
+
+impl CompactionStrategy for LeveledStrategy {
+    fn pick(&self, levels: &[LevelTables], config: &StorageConfig) -> Option<Compaction> {
+        for tables in levels {
+            let (level, disk_tables) = (tables.level, &tables.disk_tables);
+
+            let whole_level = match level {
+                SEGMENTS_MIN_LEVEL => disk_tables.len() >= config.disk_tables_limit_by_level.max(1),
+                _ if !tables.disjoint => disk_tables.len() > 1 || level != output_level(level),
+                _ => false,
+            };
+            if whole_level {
+                let compaction = Self::compaction(levels, level, disk_tables.clone(), config);
+                if !has_compacting_inputs(levels, &compaction) {
+                    return Some(compaction);
+                }
+                continue;
+            }
+            if level == SEGMENTS_MIN_LEVEL || !tables.disjoint {
+                continue;
+            }
+
+            if max_level_size(level, config).is_none_or(|max_size| tables.size() <= max_size) {
+                continue;
+            }
+
+            // tables are sorted by keys, the level is compacted round by round
+            let mut compact_pointers = self.compact_pointers.lock().unwrap();
+            let start = compact_pointers.get(&level).map_or(0, |pointer| {
+                disk_tables.partition_point(|disk_table| {
+                    disk_table.properties().unwrap().smallest_key <= *pointer
+                })
+            });
+
+            // the next table which isn't merged with tables of running compactions
+            let candidates = disk_tables[start..].iter().chain(&disk_tables[..start]);
+            for disk_table in candidates {
+                let compaction = Self::compaction(levels, level, vec![disk_table.clone()], config);
+                if has_compacting_inputs(levels, &compaction) {
+                    continue;
+                }
+
+                compact_pointers
+                    .insert(level, disk_table.properties().unwrap().largest_key.clone());
+                return Some(compaction);
+            }
+        }
+
+        None
+    }
+}
+
+// FIFO compaction: tables are never merged, the oldest ones are deleted
+// while all tables are larger than `fifo_max_size`, but the newest one.
+// It suits data with limited lifetime.
+pub struct FifoStrategy;
+
+impl CompactionStrategy for FifoStrategy {
+    fn pick(&self, levels: &[LevelTables], config: &StorageConfig) -> Option<Compaction> {
+        if levels.iter().any(|level| level.has_compacting()) {
+            return None;
+        }
+
+        let mut disk_tables = levels
+            .iter()
+            .flat_map(|level| level.disk_tables.iter())
+            .collect::<Vec<_>>();
+        disk_tables.sort_by_key(|disk_table| get_disk_table_id(disk_table.get_name()));
+
+        // the newest table is kept
+        disk_tables.pop();
+
+        let mut size = levels.iter().map(|level| level.size()).sum::<u64>();
+        let mut inputs = Vec::new();
+        for disk_table in disk_tables {
+            if size <= config.fifo_max_size as u64 {
+                break;
+            }
+            size -= disk_table.data_size();
+            inputs.push(disk_table.clone());
+        }
+        if inputs.is_empty() {
+            return None;
+        }
+
+        let mut compaction = Compaction::new(SEGMENTS_MIN_LEVEL, SEGMENTS_MIN_LEVEL, inputs);
+        compaction.delete_inputs = true;
+        Some(compaction)
+    }
+}
@@ -3,10 +3,10 @@ use std::sync::Arc;
 
 use super::disk_tables_shard::Levels;
 use super::id::DiskTableID;
-use super::local::block::data_block;
-use crate::core::entry::user_entry::UserEntry;
+use super::local::block::{data_block, properties::TableProperties};
+use crate::core::entry::user_entry::{SequenceNumber, UserEntry};
 use crate::core::field::Field;
-use crate::errors::Result;
+use crate::errors::{Error, Result};
 
 pub type WriterDiskTablePtr<K, V> = Box<dyn WriterDiskTable<K, V>>;
 pub type ReaderDiskTablePtr<K, V> = Arc<dyn ReaderDiskTable<K, V>>;
@@ -18,9 +18,22 @@ pub trait Writer<K, V> {
 
 pub trait Reader<K, V> {
     fn read(&self, key: &K) -> Result<Option<V>>;
+    // the newest version of key, returns tombstones as well
+    fn read_entry(&self, key: &K) -> Result<Option<UserEntry<K, V>>>;
+    // the newest version of key with sequence number <= `sequence`
+    fn read_entry_at(&self, key: &K, sequence: SequenceNumber) -> Result<Option<UserEntry<K, V>>>;
+    // false if the table has no key for sure
+    fn may_contain(&self, key: &K) -> bool;
     fn read_entry_by_index(&self, index: u32) -> Result<Option<UserEntry<K, V>>>;
-    fn read_block(&self, index: usize) -> Option<data_block::DataBlock<K, V>>;
+    // None after the last block
+    fn read_block(&self, index: usize) -> Result<Option<Arc<data_block::DataBlock<K, V>>>>;
+    // index of the first block which may contain entries with keys >= `key`
+    fn seek_block(&self, key: &K) -> usize;
     fn count_entries(&self) -> u32;
+    // size of data blocks on disk
+    fn data_size(&self) -> u64;
+    // key range and statistics, None for tables before format version 4
+    fn properties(&self) -> Option<&TableProperties>;
 }
 
 // @todo
@@ -65,10 +78,93 @@ pub fn get_disk_table_name_by_level(disk_table_id: DiskTableID, level: Levels) -
     (disk_table_name, index_table_name)
 }
 
+// ids aren't padded in names, so tables are ordered by ids instead of names
+pub fn get_disk_table_id(disk_table_name: &str) -> Option<u64> {
+    // segment_123_4.bin
+    disk_table_name.split('_').nth(1)?.parse().ok()
+}
+
+enum DiskTableRef<'a, K, V> {
+    Borrowed(&'a dyn ReaderDiskTable<K, V>),
+    // keeps the disk table alive even if it was removed from shards by merge
+    Shared(ReaderDiskTablePtr<K, V>),
+}
+
+impl<'a, K, V> DiskTableRef<'a, K, V> {
+    fn get(&self) -> &dyn ReaderDiskTable<K, V> {
+        match self {
+            DiskTableRef::Borrowed(disk_table) => *disk_table,
+            DiskTableRef::Shared(disk_table) => disk_table.as_ref(),
+        }
+    }
+}
+
 pub struct ReaderDiskTableIterator<'a, K, V> {
-    disk_table: &'a dyn ReaderDiskTable<K, V>,
+    disk_table: DiskTableRef<'a, K, V>,
     index: usize,
     block_it: Option<Box<dyn Iterator<Item = UserEntry<K, V>> + 'a>>,
+    // the first entry after seek
+    pending: Option<UserEntry<K, V>>,
+    // a block which wasn't read ends iteration
+    error: Option<Error>,
+}
+
+impl<K, V> ReaderDiskTableIterator<'static, K, V>
+where
+    K: Field + Clone + Ord + 'static,
+    V: Field + Clone + 'static,
+{
+    pub fn new(disk_table: ReaderDiskTablePtr<K, V>) -> Self {
+        ReaderDiskTableIterator {
+            disk_table: DiskTableRef::Shared(disk_table),
+            index: 0,
+            block_it: None,
+            pending: None,
+            error: None,
+        }
+    }
+}
+
+impl<'a, K, V> ReaderDiskTableIterator<'a, K, V>
+where
+    K: Field + Clone + Ord,
+    V: Field + Clone,
+{
+    // the next entry will be the first one with key >= `key`
+    pub fn seek(&mut self, key: &K) {
+        self.index = self.disk_table.get().seek_block(key);
+        self.block_it = None;
+        self.pending = None;
+
+        while let Some(entry) = self.next() {
+            if entry.get_key() >= key {
+                self.pending = Some(entry);
+                break;
+            }
+        }
+    }
+
+    // the error which ended iteration, entries after it weren't returned
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
+    fn next_block(&mut self) -> Option<Arc<data_block::DataBlock<K, V>>> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        match self.disk_table.get().read_block(self.index) {
+            Ok(block) => {
+                self.index += 1;
+                block
+            }
+            Err(er) => {
+                self.error = Some(er);
+                None
+            }
+        }
+    }
 }
 
 impl<'a, K, V> Iterator for ReaderDiskTableIterator<'a, K, V>
@@ -79,12 +175,15 @@ where
     type Item = UserEntry<K, V>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(entry) = self.pending.take() {
+            return Some(entry);
+        }
+
         match &mut self.block_it {
             Some(it) => {
                 let res = it.next();
                 if res.is_none() {
-                    let next_block = self.disk_table.read_block(self.index)?;
-                    self.index += 1;
+                    let next_block = self.next_block()?;
 
                     let it = next_block.into_iter();
                     let it = self.block_it.insert(Box::new(it));
@@ -96,8 +195,7 @@ where
                 }
             }
             None => {
-                let next_block = self.disk_table.read_block(self.index)?;
-                self.index += 1;
+                let next_block = self.next_block()?;
 
                 let it = next_block.into_iter();
                 let it = self.block_it.insert(Box::new(it));
@@ -119,9 +217,11 @@ where
 
     fn into_iter(self) -> Self::IntoIter {
         ReaderDiskTableIterator {
-            disk_table: self,
+            disk_table: DiskTableRef::Borrowed(self),
             index: 0,
             block_it: None,
+            pending: None,
+            error: None,
         }
     }
 }
@@ -1,20 +1,36 @@
 use std::{
-    collections::BTreeMap,
-    path::Path,
-    sync::{Arc, RwLock},
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::Duration,
 };
 
 use log::trace;
 
-use crate::errors::Result;
+use crate::errors::{Error, Result};
 
 use crate::core::{
     disk_table::{
-        disk_table::ReaderDiskTableIterator, local::disk_table_builder::DiskTableBuilder,
-        local::reader_local_disk_table::ReaderDiskTablePtr, shard_level::ShardLevel,
+        compaction::{self, Compaction, CompactionStrategy, LevelTables},
+        disk_table::ReaderDiskTableIterator,
+        local::{
+            block::{block_cache::BlockCache, bloom_filter::BloomFilterStats},
+            disk_table_builder::DiskTableBuilder,
+            reader_local_disk_table::ReaderDiskTablePtr,
+        },
+        shard_level::ShardLevel,
+    },
+    entry::{
+        flexible_user_entry::FlexibleUserEntry,
+        user_entry::{EntryKind, SequenceNumber},
+    },
+    field::{Field, FlexibleField},
+    storage::{
+        config::StorageConfig,
+        snapshot::{SnapshotList, VersionFilter},
+        value_log::{ValueLog, ValueLogFiles},
     },
-    field::FlexibleField,
-    storage::config,
 };
 
 use super::disk_table::ReaderDiskTable;
@@ -24,22 +40,158 @@ pub const SEGMENTS_MAX_LEVEL: Levels = 3;
 
 pub type Levels = u8;
 
+// level of the table merged from tables of `level`, the last level is merged into itself
+pub fn merged_level(level: Levels) -> Levels {
+    (level + 1).min(SEGMENTS_MAX_LEVEL)
+}
+
+// Compactions which are picked and not applied yet.
+#[derive(Default)]
+struct RunningCompactions {
+    inputs: HashSet<String>,
+    output_levels: HashSet<Levels>,
+}
+
+impl RunningCompactions {
+    // false if compaction conflicts with running ones
+    fn start(&mut self, compaction: &Compaction) -> bool {
+        let names = compaction.input_names();
+        if names.iter().any(|name| self.inputs.contains(name))
+            || self.output_levels.contains(&compaction.output_level)
+        {
+            return false;
+        }
+
+        self.inputs.extend(names);
+        self.output_levels.insert(compaction.output_level);
+        true
+    }
+
+    fn finish(&mut self, compaction: &Compaction) {
+        for name in compaction.input_names() {
+            self.inputs.remove(&name);
+        }
+        self.output_levels.remove(&compaction.output_level);
+    }
+}
+
 pub struct DiskTablesShards {
     shards: RwLock<BTreeMap<Levels, ShardLevel>>,
+    // merges keep versions which are read by live snapshots
+    snapshots: SnapshotList,
+    bloom_filter_stats: BloomFilterStats,
+    // data blocks of all tables
+    block_cache: Arc<BlockCache>,
+    // large values which disk tables point to, shards without storage have no log
+    value_log: Option<Arc<ValueLog>>,
+    // picks tables which are merged after flushes
+    compaction_strategy: Box<dyn CompactionStrategy>,
+    // compactions run in parallel, it's locked before shards
+    running_compactions: Mutex<RunningCompactions>,
+    config: StorageConfig,
 }
 
 impl DiskTablesShards {
     pub fn new() -> Self {
+        DiskTablesShards::from_config(StorageConfig::default_config())
+    }
+
+    pub fn from_config(config: StorageConfig) -> Self {
         Self {
             shards: RwLock::new(BTreeMap::new()),
+            snapshots: SnapshotList::new(),
+            bloom_filter_stats: BloomFilterStats::new(),
+            block_cache: Arc::new(BlockCache::new(config.block_cache_size)),
+            value_log: None,
+            compaction_strategy: config.compaction_style.new_strategy(),
+            running_compactions: Mutex::new(RunningCompactions::default()),
+            config,
         }
     }
 
+    pub fn bloom_filter_stats(&self) -> &BloomFilterStats {
+        &self.bloom_filter_stats
+    }
+
+    pub fn block_cache(&self) -> &Arc<BlockCache> {
+        &self.block_cache
+    }
+
+    pub fn set_value_log(&mut self, value_log: ValueLog) -> &mut Self {
+        self.value_log = Some(Arc::new(value_log));
+        self
+    }
+
+    // replaces the strategy of config with a custom one
+    pub fn set_compaction_strategy(
+        &mut self,
+        compaction_strategy: Box<dyn CompactionStrategy>,
+    ) -> &mut Self {
+        self.compaction_strategy = compaction_strategy;
+        self
+    }
+
+    pub fn value_log(&self) -> Option<&Arc<ValueLog>> {
+        self.value_log.as_ref()
+    }
+
+    // files to resolve pointers of entries, they must be taken before entries are read
+    pub fn value_log_files(&self) -> Arc<ValueLogFiles> {
+        self.value_log
+            .as_ref()
+            .map_or_else(Default::default, |value_log| value_log.files())
+    }
+
+    // a large value of flushed entry goes to the value log, the disk table keeps a pointer to it
+    pub fn separate_value(&self, entry: &FlexibleUserEntry) -> Result<Option<FlexibleUserEntry>> {
+        let Some(value_log) = &self.value_log else {
+            return Ok(None);
+        };
+        if self.config.value_threshold == 0
+            || entry.get_kind() != EntryKind::Value
+            || entry.get_value().size() < self.config.value_threshold
+        {
+            return Ok(None);
+        }
+
+        let pointer = value_log.append(entry.get_key(), entry.get_value())?;
+
+        Ok(Some(FlexibleUserEntry::from_parts(
+            entry.get_key().clone(),
+            FlexibleField::new(pointer.serialize()?),
+            EntryKind::ValuePointer,
+            entry.get_sequence(),
+        )))
+    }
+
+    // builder of disk table with options from config
+    pub fn new_disk_table_builder(
+        &self,
+        disk_table_path: &Path,
+        index_table_path: &Path,
+    ) -> DiskTableBuilder {
+        let mut builder = DiskTableBuilder::new(disk_table_path, index_table_path);
+        builder
+            .set_bloom_bits_per_key(self.config.bloom_bits_per_key)
+            .set_block_cache(self.block_cache.clone())
+            .set_data_block_size(self.config.aligned_data_block_size())
+            .set_compression(self.config.compression);
+        builder
+    }
+
+    pub fn snapshots(&self) -> &SnapshotList {
+        &self.snapshots
+    }
+
+    fn new_shard_level(level: Levels) -> ShardLevel {
+        ShardLevel::new(level != SEGMENTS_MIN_LEVEL)
+    }
+
     pub fn remove_level_and_put(
         &self,
         removing_level: Levels,
         merged_level: Levels,
-        disk_table: ReaderDiskTablePtr,
+        disk_table: Option<ReaderDiskTablePtr>,
     ) -> Result<()> {
         let mut lock = self.shards.write().unwrap();
 
@@ -48,6 +200,11 @@ impl DiskTablesShards {
         let removing_tables = lock.get(&removing_level).unwrap();
         removing_tables.clear()?;
 
+        // all merged entries were deleted
+        let Some(disk_table) = disk_table else {
+            return Ok(());
+        };
+
         let mut no_level = false;
         {
             // let lock = self.shards.read().unwrap();
@@ -58,7 +215,7 @@ impl DiskTablesShards {
 
         // let mut lock = self.shards.write().unwrap();
         if no_level {
-            lock.insert(merged_level, ShardLevel::new());
+            lock.insert(merged_level, Self::new_shard_level(merged_level));
         }
 
         let shard = lock.get(&merged_level).expect("we checked key early");
@@ -81,7 +238,7 @@ impl DiskTablesShards {
 
         let mut lock = self.shards.write().unwrap();
         if no_level {
-            lock.insert(level, ShardLevel::new());
+            lock.insert(level, Self::new_shard_level(level));
         }
 
         let shard = lock.get(&level).expect("we checked key early");
@@ -97,12 +254,13 @@ impl DiskTablesShards {
         // @todo return status?
     }
 
+    // merges all tables of level into one table of the next level
     pub fn merge_level(
         &self,
         level: Levels,
         disk_table_path: &Path,
         index_table_path: &Path,
-    ) -> Arc<dyn ReaderDiskTable<FlexibleField, FlexibleField>> {
+    ) -> Result<Option<Arc<dyn ReaderDiskTable<FlexibleField, FlexibleField>>>> {
         let lock = self.shards.read().unwrap();
 
         assert!(lock.contains_key(&level));
@@ -112,13 +270,183 @@ impl DiskTablesShards {
         // @todo
         let disk_tables = merging_tables.disk_tables.read().unwrap();
 
+        let mut paths = Some((
+            disk_table_path.to_path_buf(),
+            index_table_path.to_path_buf(),
+        ));
+        self.merge_tables(
+            &disk_tables,
+            merged_level(level),
+            level == SEGMENTS_MAX_LEVEL,
+            None,
+            || paths.take().expect("merged table isn't split"),
+        )
+        .map(|mut merged| merged.pop())
+    }
+
+    // tables of non-empty levels from the first one
+    pub fn level_tables(&self) -> Vec<LevelTables> {
+        let running = self.running_compactions.lock().unwrap();
+        self.level_tables_with(&running.inputs)
+    }
+
+    fn level_tables_with(&self, compacting: &HashSet<String>) -> Vec<LevelTables> {
+        let lock = self.shards.read().unwrap();
+
+        lock.iter()
+            .filter(|(_level, shard)| shard.len() != 0)
+            .map(|(level, shard)| LevelTables {
+                level: *level,
+                disk_tables: shard.iter().collect(),
+                disjoint: shard.is_disjoint(),
+                compacting: shard
+                    .iter()
+                    .map(|disk_table| disk_table.get_name().to_string())
+                    .filter(|name| compacting.contains(name))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    // the next compaction of the strategy, None if levels are in shape
+    // or the compaction conflicts with running ones.
+    // The picked compaction runs until it's applied by `apply_compaction`.
+    pub fn pick_compaction(&self) -> Option<Compaction> {
+        let mut running = self.running_compactions.lock().unwrap();
+
+        let compaction = self
+            .compaction_strategy
+            .pick(&self.level_tables_with(&running.inputs), &self.config)?;
+        if !running.start(&compaction) {
+            trace!(
+                "compaction of level {} conflicts with running ones",
+                compaction.level
+            );
+            return None;
+        }
+
+        Some(compaction)
+    }
+
+    // compaction of tables of level which overlap [start, end], see `compaction::range_compaction`.
+    // It waits for running compactions of these tables, None if level has no such tables
+    pub fn pick_range_compaction(
+        &self,
+        level: Levels,
+        start: &FlexibleField,
+        end: &FlexibleField,
+        skipped: &[String],
+    ) -> Option<Compaction> {
+        loop {
+            {
+                let mut running = self.running_compactions.lock().unwrap();
+
+                let compaction = compaction::range_compaction(
+                    &self.level_tables_with(&running.inputs),
+                    level,
+                    start,
+                    end,
+                    skipped,
+                    &self.config,
+                )?;
+                if running.start(&compaction) {
+                    return Some(compaction);
+                }
+            }
+
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+
+    // merged tables of compaction, `new_paths` gives paths of the next table
+    // when the merged one reaches the target size
+    pub fn compact(
+        &self,
+        compaction: &Compaction,
+        new_paths: impl FnMut() -> (PathBuf, PathBuf),
+    ) -> Result<Vec<ReaderDiskTablePtr>> {
+        if compaction.delete_inputs {
+            return Ok(Vec::new());
+        }
+
+        self.merge_tables(
+            &compaction.inputs,
+            compaction.output_level,
+            compaction.drop_tombstones,
+            compaction.target_size,
+            new_paths,
+        )
+    }
+
+    // ends a failed compaction, its tables are kept
+    pub fn abort_compaction(&self, compaction: &Compaction) {
+        self.running_compactions.lock().unwrap().finish(compaction);
+    }
+
+    // replaces input tables of compaction with merged ones and removes their files
+    pub fn apply_compaction(
+        &self,
+        compaction: &Compaction,
+        merged: Vec<ReaderDiskTablePtr>,
+    ) -> Result<()> {
+        let mut running = self.running_compactions.lock().unwrap();
+        running.finish(compaction);
+
+        let mut lock = self.shards.write().unwrap();
+
+        let names = compaction.input_names();
+        for shard in lock.values() {
+            shard.remove(&names)?;
+        }
+        if merged.is_empty() {
+            return Ok(());
+        }
+
+        let shard = lock
+            .entry(compaction.output_level)
+            .or_insert_with(|| Self::new_shard_level(compaction.output_level));
+        for disk_table in merged {
+            shard.push(disk_table);
+        }
+
+        Ok(())
+    }
+
+    // versions of key go from the newest one, they aren't split between merged tables,
+    // so tables of sorted levels don't overlap.
+    // Merged tables are removed if a table isn't read
+    fn merge_tables(
+        &self,
+        disk_tables: &[ReaderDiskTablePtr],
+        output_level: Levels,
+        drop_tombstones: bool,
+        target_size: Option<u64>,
+        mut new_paths: impl FnMut() -> (PathBuf, PathBuf),
+    ) -> Result<Vec<ReaderDiskTablePtr>> {
         let mut its = disk_tables
             .iter()
             .map(|disk_table| disk_table.into_iter())
             .collect::<Vec<ReaderDiskTableIterator<FlexibleField, FlexibleField>>>();
 
         let mut entries = its.iter_mut().map(|it| it.next()).collect::<Vec<_>>();
-        let mut builder = DiskTableBuilder::new(disk_table_path, index_table_path);
+        let source_tables = disk_tables
+            .iter()
+            .map(|disk_table| disk_table.get_name().to_string())
+            .collect::<Vec<_>>();
+        let new_builder = |(disk_table_path, index_table_path): (PathBuf, PathBuf)| {
+            let mut builder =
+                self.new_disk_table_builder(disk_table_path.as_path(), index_table_path.as_path());
+            builder
+                .set_level(output_level)
+                .set_source_tables(source_tables.clone());
+            builder
+        };
+
+        let mut merged = Vec::new();
+        let mut builder = new_builder(new_paths());
+        let mut last_key: Option<FlexibleField> = None;
+
+        let mut filter = VersionFilter::new(self.snapshots.sequences(), drop_tombstones);
 
         while entries.iter().any(|v| v.is_some()) {
             let (index, entry) = entries
@@ -132,42 +460,125 @@ impl DiskTablesShards {
                 .collect::<Vec<_>>()
                 // @todo iter vs into_iter
                 .into_iter()
-                .min_by(|lhs, rhs| lhs.1.get_key().cmp(rhs.1.get_key()))
+                .min_by(|lhs, rhs| lhs.1.cmp(rhs.1))
                 .unwrap();
 
-            builder.append_entry(entry);
+            if filter.keep(entry) {
+                let is_full = builder.is_full()
+                    || target_size.is_some_and(|target_size| builder.data_size() >= target_size);
+                if is_full && last_key.as_ref() != Some(entry.get_key()) {
+                    match builder.build() {
+                        Ok(disk_table) => merged.push(disk_table),
+                        Err(er) => return Self::discard_merged(builder, merged, er),
+                    }
+                    builder = new_builder(new_paths());
+                }
+
+                builder.append_entry(entry);
+                last_key = Some(entry.get_key().clone());
+            }
 
             if let Some(it) = its.get_mut(index) {
                 entries[index] = it.next();
             }
         }
 
-        let Ok(merged_disk_table) = builder.build() else {
-            panic!("Failed create disk table for merge_disk_tables")
-        };
+        if let Some(er) = its.iter_mut().find_map(|it| it.take_error()) {
+            return Self::discard_merged(builder, merged, er);
+        }
 
-        merged_disk_table
-    }
+        if builder.is_empty() {
+            if let Err(er) = builder.discard() {
+                panic!(
+                    "Failed discard empty disk table for merge_disk_tables: {}",
+                    er
+                )
+            }
+            return Ok(merged);
+        }
 
-    pub fn is_ready_to_merge(&self, level: Levels) -> bool {
-        let lock = self.shards.read().unwrap();
+        match builder.build() {
+            Ok(merged_disk_table) => merged.push(merged_disk_table),
+            Err(er) => return Self::discard_merged(builder, merged, er),
+        }
 
-        if !lock.contains_key(&level) {
-            return false;
+        Ok(merged)
+    }
+
+    // removes tables of failed merge, the error of merge is returned
+    fn discard_merged(
+        mut builder: DiskTableBuilder,
+        merged: Vec<ReaderDiskTablePtr>,
+        er: Error,
+    ) -> Result<Vec<ReaderDiskTablePtr>> {
+        builder.discard()?;
+        for disk_table in merged {
+            disk_table.remove()?;
         }
 
-        lock[&level].len() >= config::DEFAULT_DISK_TABLES_LIMIT_BY_LEVEL
+        Err(er)
     }
 
-    // workaround
+    pub fn level_disk_table_names(&self, level: Levels) -> Vec<String> {
+        let shards = self.shards.read().unwrap();
+
+        shards.get(&level).map_or(Vec::new(), |shard| {
+            shard
+                .iter()
+                .map(|disk_table| disk_table.get_name().to_string())
+                .collect()
+        })
+    }
+
+    // all disk tables in the order of reading: from the newest one
+    pub fn disk_tables(&self) -> Vec<ReaderDiskTablePtr> {
+        let shards = self.shards.read().unwrap();
+
+        shards.values().flat_map(|shard| shard.iter()).collect()
+    }
+
+    // levels and tables in level are probed from the newest one,
+    // so the first found version of key is the most recent write
     pub fn get(&self, key: &FlexibleField) -> Result<Option<FlexibleField>> {
+        self.get_at(key, SequenceNumber::MAX)
+    }
+
+    // the newest version of key with sequence number <= `sequence`
+    pub fn get_at(
+        &self,
+        key: &FlexibleField,
+        sequence: SequenceNumber,
+    ) -> Result<Option<FlexibleField>> {
+        let value_log_files = self.value_log_files();
+
+        match self.get_entry_at(key, sequence)? {
+            // the newest version of key was deleted
+            Some(entry) if entry.is_tombstone() => Ok(None),
+            Some(entry) => Ok(Some(value_log_files.resolve(entry)?.get_value().clone())),
+            None => Ok(None),
+        }
+    }
+
+    // the newest version of key as it's stored, a large value is a pointer to the value log
+    pub fn get_entry_at(
+        &self,
+        key: &FlexibleField,
+        sequence: SequenceNumber,
+    ) -> Result<Option<FlexibleUserEntry>> {
         let shards = self.shards.read().unwrap();
 
         for (_level, shard) in shards.iter() {
-            for (_index, disk_table) in shard.iter().enumerate() {
-                match disk_table.read(key) {
+            // a key is in one table of sorted level at most
+            for disk_table in shard.find(key) {
+                let may_contain = disk_table.may_contain(key);
+                self.bloom_filter_stats.record(may_contain);
+                if !may_contain {
+                    continue;
+                }
+
+                match disk_table.read_entry_at(key, sequence) {
                     Ok(v) => match v {
-                        Some(v) => return Ok(Some(v)),
+                        Some(entry) => return Ok(Some(entry)),
                         None => continue,
                     },
                     Err(e) => return Err(e),
@@ -1,5 +1,6 @@
 use crate::errors::Result;
 
 pub trait WriteToTable {
-    fn write_to(&self, ptr: &mut Box<dyn std::io::Write>) -> Result<()>;
+    // returns count of written bytes
+    fn write_to(&self, ptr: &mut Box<dyn std::io::Write>) -> Result<usize>;
 }
@@ -0,0 +1,194 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::core::{disk_table::local::block::data_block::DataBlock, field::FlexibleField};
+
+pub type CachedBlock = Arc<DataBlock<FlexibleField, FlexibleField>>;
+
+// (table id, block offset)
+pub type BlockCacheKey = (u64, u64);
+
+struct CacheEntry {
+    block: CachedBlock,
+    charge: usize,
+    // position in the recency order
+    tick: u64,
+}
+
+struct LruBlocks {
+    entries: HashMap<BlockCacheKey, CacheEntry>,
+    // tick -> key, the first one is the least recently used
+    recency: BTreeMap<u64, BlockCacheKey>,
+    next_tick: u64,
+    usage: usize,
+}
+
+impl LruBlocks {
+    fn touch(&mut self, key: &BlockCacheKey) -> Option<CachedBlock> {
+        let entry = self.entries.get_mut(key)?;
+
+        self.recency.remove(&entry.tick);
+        entry.tick = self.next_tick;
+        self.recency.insert(entry.tick, *key);
+        self.next_tick += 1;
+
+        Some(entry.block.clone())
+    }
+
+    fn remove(&mut self, key: &BlockCacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.tick);
+            self.usage -= entry.charge;
+        }
+    }
+}
+
+// Decoded data blocks shared by all disk tables of storage.
+// Capacity is the sum of block sizes in bytes, 0 disables caching.
+pub struct BlockCache {
+    capacity: usize,
+    blocks: Mutex<LruBlocks>,
+    next_table_id: AtomicU64,
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blocks: Mutex::new(LruBlocks {
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                next_tick: 0,
+                usage: 0,
+            }),
+            next_table_id: AtomicU64::new(0),
+        }
+    }
+
+    // unique id of table in this cache
+    pub fn new_table_id(&self) -> u64 {
+        self.next_table_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn usage(&self) -> usize {
+        self.blocks.lock().unwrap().usage
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, key: &BlockCacheKey) -> Option<CachedBlock> {
+        self.blocks.lock().unwrap().touch(key)
+    }
+
+    pub fn insert(&self, key: BlockCacheKey, block: CachedBlock, charge: usize) {
+        if charge > self.capacity {
+            return;
+        }
+
+        let mut blocks = self.blocks.lock().unwrap();
+        blocks.remove(&key);
+
+        while blocks.usage + charge > self.capacity {
+            let Some((_tick, oldest)) = blocks.recency.pop_first() else {
+                break;
+            };
+            let entry = blocks.entries.remove(&oldest).expect("key is in recency");
+            blocks.usage -= entry.charge;
+        }
+
+        let tick = blocks.next_tick;
+        blocks.next_tick += 1;
+        blocks.recency.insert(tick, key);
+        blocks.entries.insert(
+            key,
+            CacheEntry {
+                block,
+                charge,
+                tick,
+            },
+        );
+        blocks.usage += charge;
+    }
+
+    // drops blocks of removed table
+    pub fn erase_table(&self, table_id: u64) {
+        let mut blocks = self.blocks.lock().unwrap();
+
+        let keys = blocks
+            .entries
+            .keys()
+            .filter(|(id, _offset)| *id == table_id)
+            .copied()
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            blocks.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_block() -> CachedBlock {
+        Arc::new(DataBlock::from_entries(Vec::new()))
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = BlockCache::new(300);
+
+        cache.insert((0, 0), make_block(), 100);
+        cache.insert((0, 100), make_block(), 100);
+        cache.insert((1, 0), make_block(), 100);
+
+        // (0, 100) is the oldest one after get
+        assert!(cache.get(&(0, 0)).is_some());
+        cache.insert((1, 100), make_block(), 100);
+
+        assert!(cache.get(&(0, 100)).is_none());
+        assert!(cache.get(&(0, 0)).is_some());
+        assert!(cache.get(&(1, 0)).is_some());
+        assert!(cache.get(&(1, 100)).is_some());
+        assert_eq!(cache.usage(), 300);
+    }
+
+    #[test]
+    fn test_erase_table() {
+        let cache = BlockCache::new(1000);
+
+        cache.insert((0, 0), make_block(), 100);
+        cache.insert((1, 0), make_block(), 100);
+        cache.insert((1, 100), make_block(), 100);
+
+        cache.erase_table(1);
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.usage(), 100);
+        assert!(cache.get(&(0, 0)).is_some());
+    }
+
+    #[test]
+    fn test_disabled_cache() {
+        let cache = BlockCache::new(0);
+
+        cache.insert((0, 0), make_block(), 100);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::core::{
+    disk_table::local::file_handle::ReadSeek,
+    marshal::{read_u32, write_u32},
+};
+use crate::errors::Result;
+
+pub const BLOOM_FILTER_SIZE: usize = size_of::<u32>();
+pub const BLOOM_FILTER_HASHES_SIZE: usize = size_of::<u32>();
+
+const MIN_FILTER_BITS: usize = 64;
+const MAX_HASHES: u32 = 30;
+
+// murmur-like hash of key from leveldb with the final mix of murmur3,
+// keys which differ only in the high bytes of a word get different bits
+pub fn bloom_hash(data: &[u8]) -> u32 {
+    const SEED: u32 = 0xbc9f1d34;
+    const M: u32 = 0xc6a4a793;
+    const R: u32 = 24;
+
+    let mut h = SEED ^ (data.len() as u32).wrapping_mul(M);
+
+    let mut chunks = data.chunks_exact(size_of::<u32>());
+    for chunk in &mut chunks {
+        let w = u32::from_le_bytes(chunk.try_into().unwrap());
+        h = h.wrapping_add(w).wrapping_mul(M);
+        h ^= h >> 16;
+    }
+
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (index, byte) in rest.iter().enumerate() {
+            h = h.wrapping_add((*byte as u32) << (8 * index));
+        }
+        h = h.wrapping_mul(M);
+        h ^= h >> R;
+    }
+
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85ebca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2ae35);
+    h ^= h >> 16;
+
+    h
+}
+
+// Filter section of index table:
+// [ size_bits count_hashes bits ]
+//
+// size_bits is 0 if the table was built without filter.
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    count_hashes: u32,
+}
+
+impl BloomFilter {
+    pub fn new(key_hashes: &[u32], bits_per_key: usize) -> Self {
+        // ln(2) * bits_per_key is the best number of hashes
+        let count_hashes = ((bits_per_key * 69 / 100) as u32).clamp(1, MAX_HASHES);
+
+        let count_bits = (key_hashes.len() * bits_per_key).max(MIN_FILTER_BITS);
+        let count_bytes = count_bits.div_ceil(8);
+        let count_bits = count_bytes * 8;
+
+        let mut bits = vec![0u8; count_bytes];
+
+        for hash in key_hashes {
+            // double hashing
+            let mut h = *hash;
+            let delta = h.rotate_right(17);
+            for _ in 0..count_hashes {
+                let bit = h as usize % count_bits;
+                bits[bit / 8] |= 1 << (bit % 8);
+                h = h.wrapping_add(delta);
+            }
+        }
+
+        Self { bits, count_hashes }
+    }
+
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        let count_bits = self.bits.len() * 8;
+
+        let mut h = bloom_hash(key);
+        let delta = h.rotate_right(17);
+        for _ in 0..self.count_hashes {
+            let bit = h as usize % count_bits;
+            if self.bits[bit / 8] & (1 << (bit % 8)) == 0 {
+                return false;
+            }
+            h = h.wrapping_add(delta);
+        }
+
+        true
+    }
+
+    pub fn size(&self) -> usize {
+        BLOOM_FILTER_SIZE + BLOOM_FILTER_HASHES_SIZE + self.bits.len()
+    }
+
+    pub fn serialize(filter: Option<&BloomFilter>) -> Result<Vec<u8>> {
+        let Some(filter) = filter else {
+            return Ok(vec![0u8; BLOOM_FILTER_SIZE]);
+        };
+
+        let mut buffer = vec![0u8; filter.size()];
+
+        let mut offset = write_u32(&mut buffer, filter.bits.len() as u32)?;
+        offset += write_u32(&mut buffer[offset..], filter.count_hashes)?;
+        buffer[offset..].copy_from_slice(&filter.bits);
+
+        Ok(buffer)
+    }
+
+    // reads the filter section from the current position of `fd`
+    pub fn from(fd: &mut Box<dyn ReadSeek>) -> Result<Option<Self>> {
+        let mut buffer = [0u8; BLOOM_FILTER_SIZE];
+        fd.read_exact(&mut buffer)?;
+
+        let count_bytes = read_u32(&buffer)? as usize;
+        if count_bytes == 0 {
+            return Ok(None);
+        }
+
+        let mut buffer = [0u8; BLOOM_FILTER_HASHES_SIZE];
+        fd.read_exact(&mut buffer)?;
+        let count_hashes = read_u32(&buffer)?;
+
+        let mut bits = vec![0u8; count_bytes];
+        fd.read_exact(&mut bits)?;
+
+        Ok(Some(Self { bits, count_hashes }))
+    }
+}
+
+// Counters of filter checks before reading disk tables.
+#[derive(Default)]
+pub struct BloomFilterStats {
+    // the filter allowed reading the table
+    hits: AtomicU64,
+    // the table was skipped
+    misses: AtomicU64,
+}
+
+impl BloomFilterStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, may_contain: bool) {
+        if may_contain {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_key(index: u32) -> Vec<u8> {
+        index.to_be_bytes().to_vec()
+    }
+
+    #[test]
+    fn test_no_false_negatives() {
+        let hashes = (0..1000)
+            .map(|i| bloom_hash(&make_key(i)))
+            .collect::<Vec<_>>();
+        let filter = BloomFilter::new(&hashes, 10);
+
+        for i in 0..1000 {
+            assert!(filter.may_contain(&make_key(i)));
+        }
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let hashes = (0..1000)
+            .map(|i| bloom_hash(&make_key(i)))
+            .collect::<Vec<_>>();
+        let filter = BloomFilter::new(&hashes, 10);
+
+        let false_positives = (1000..11000)
+            .filter(|i| filter.may_contain(&make_key(*i)))
+            .count();
+
+        // about 1% for 10 bits per key
+        assert!(false_positives < 300, "false_positives={}", false_positives);
+    }
+
+    #[test]
+    fn test_keys_with_one_byte_difference() {
+        let hashes = (0..128)
+            .map(|i| bloom_hash(&make_key(i)))
+            .collect::<Vec<_>>();
+        let filter = BloomFilter::new(&hashes, 10);
+
+        let false_positives = (128..256)
+            .filter(|i| filter.may_contain(&make_key(*i)))
+            .count();
+        assert!(false_positives < 16, "false_positives={}", false_positives);
+    }
+
+    #[test]
+    fn test_empty_filter() {
+        let filter = BloomFilter::new(&[], 10);
+        assert!(!filter.may_contain(&make_key(1)));
+    }
+}
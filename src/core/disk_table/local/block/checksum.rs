@@ -0,0 +1,6 @@
+pub const CHECKSUM_SIZE: usize = size_of::<u32>();
+
+// CRC32C of data block or section of index table
+pub fn checksum(data: &[u8]) -> u32 {
+    crc32c::crc32c(data)
+}
@@ -0,0 +1,69 @@
+use crate::errdata;
+use crate::errors::Result;
+
+// Codec of data block on disk, it's recorded in the trailer of every block,
+// so blocks with different codecs can be in one table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    Lz4 = 1,
+}
+
+impl Compression {
+    pub fn from(codec: u8) -> Result<Self> {
+        match codec {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Lz4),
+            _ => errdata!("unknown compression codec {}", codec),
+        }
+    }
+
+    // compressed data or None if it isn't smaller than raw one
+    pub fn compress(&self, raw: &[u8]) -> Option<Vec<u8>> {
+        let compressed = match self {
+            Compression::None => return None,
+            Compression::Lz4 => lz4_flex::block::compress(raw),
+        };
+
+        (compressed.len() < raw.len()).then_some(compressed)
+    }
+
+    pub fn decompress(&self, data: &[u8], raw_size: usize) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Lz4 => match lz4_flex::block::decompress(data, raw_size) {
+                Ok(raw) if raw.len() == raw_size => Ok(raw),
+                Ok(raw) => errdata!("decompressed {} bytes instead of {}", raw.len(), raw_size),
+                Err(er) => errdata!("failed decompress block: {}", er),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_and_decompress() {
+        let raw = b"{\"tenant\":1,\"entity\":\"user\"}".repeat(64);
+
+        let compressed = Compression::Lz4.compress(&raw).unwrap();
+        assert!(compressed.len() < raw.len());
+        assert_eq!(
+            Compression::Lz4.decompress(&compressed, raw.len()).unwrap(),
+            raw
+        );
+
+        assert!(Compression::None.compress(&raw).is_none());
+    }
+
+    #[test]
+    fn test_incompressible_data() {
+        let raw = (0..256u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect::<Vec<_>>();
+
+        assert!(Compression::Lz4.compress(&raw).is_none());
+    }
+}
@@ -1,96 +1,469 @@
 use crate::{
     common::memory::alloc_aligned,
     core::{
-        disk_table::local::file_handle::ReadSeek,
+        disk_table::local::{
+            block::{
+                checksum::{checksum, CHECKSUM_SIZE},
+                compression::Compression,
+                data_block_buffer::{BLOCK_ENTRY_METADATA_SIZE, BLOCK_TRAILER_SIZE},
+            },
+            file_handle::ReadSeek,
+        },
         entry::user_entry,
         field::Field,
-        marshal::read_u32,
-        storage::config::{DEFAULT_DATA_BLOCK_ALIGN, DEFAULT_DATA_BLOCK_SIZE},
+        marshal::{read_u32, read_u64},
+        storage::config::DEFAULT_DATA_BLOCK_ALIGN,
     },
+    errors::{Error, Result},
 };
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
 
+// Data block read from disk, entries are decoded on demand:
+// the buffer is kept as it's read, so uncompressed blocks aren't copied,
+// a key is found by binary search of restart offsets at the tail of block
+// and scan of entries after the restart point.
 pub struct DataBlock<K, V> {
-    _index_entries: Vec<u32>,
-    data: Vec<user_entry::UserEntry<K, V>>,
+    // aligned pages with the block, or the decompressed block
+    data: Vec<u8>,
+    // decoded block in data
+    block: Range<usize>,
+    // offset of restart offsets in block
+    restarts_offset: usize,
+    count_restarts: usize,
+    restart_interval: u32,
+    count_entries: u32,
+    _marker: PhantomData<(K, V)>,
 }
 
-impl<'a, K, V> DataBlock<K, V>
+// Entry which borrows key and value from block,
+// the key is borrowed from iterator because keys share prefixes
+#[derive(Debug, PartialEq)]
+pub struct EntryRef<'a> {
+    pub key: &'a [u8],
+    pub value: &'a [u8],
+    pub kind: user_entry::EntryKind,
+    pub sequence: user_entry::SequenceNumber,
+}
+
+impl EntryRef<'_> {
+    pub fn is_tombstone(&self) -> bool {
+        self.kind == user_entry::EntryKind::Tombstone
+    }
+
+    pub fn to_entry<K, V>(&self) -> user_entry::UserEntry<K, V>
+    where
+        K: Field + Ord + PartialEq + Eq + PartialOrd + Clone,
+        V: Field + Clone,
+    {
+        user_entry::UserEntry::from_parts(
+            K::new(self.key),
+            V::new(self.value),
+            self.kind,
+            self.sequence,
+        )
+    }
+}
+
+// entry as it's encoded in block
+struct RawEntry<'a> {
+    shared: usize,
+    unshared_key: &'a [u8],
+    value: &'a [u8],
+    kind: user_entry::EntryKind,
+    sequence: user_entry::SequenceNumber,
+    size: usize,
+}
+
+// position of the next entry and the key of the previous one
+struct EntryCursor {
+    offset: usize,
+    index: u32,
+    key: Vec<u8>,
+}
+
+impl EntryCursor {
+    fn new() -> Self {
+        Self {
+            offset: 0,
+            index: 0,
+            key: Vec::new(),
+        }
+    }
+
+    // decodes the entry at cursor and moves cursor to the next one,
+    // the key of entry is in cursor
+    fn advance<'a, K, V>(&mut self, block: &'a DataBlock<K, V>) -> Option<RawEntry<'a>> {
+        if self.index >= block.count_entries {
+            return None;
+        }
+
+        let entry = block.decode_at(self.offset);
+        self.key.truncate(entry.shared);
+        self.key.extend_from_slice(entry.unshared_key);
+
+        self.offset += entry.size;
+        self.index += 1;
+
+        Some(entry)
+    }
+
+    fn entry<'a>(&'a self, entry: RawEntry<'a>) -> EntryRef<'a> {
+        EntryRef {
+            key: &self.key,
+            value: entry.value,
+            kind: entry.kind,
+            sequence: entry.sequence,
+        }
+    }
+}
+
+impl<K, V> DataBlock<K, V> {
+    fn block(&self) -> &[u8] {
+        &self.data[self.block.clone()]
+    }
+
+    // restart offsets are checked by `from_data`
+    fn restart(&self, index: usize) -> usize {
+        let offset = self.restarts_offset + index * size_of::<u32>();
+        match read_u32(&self.block()[offset..]) {
+            Ok(restart) => restart as usize,
+            Err(er) => unreachable!("Restart point {} isn't checked: {}", index, er),
+        }
+    }
+
+    fn restart_cursor(&self, restart: usize) -> EntryCursor {
+        EntryCursor {
+            offset: self.restart(restart),
+            index: restart as u32 * self.restart_interval,
+            key: Vec::new(),
+        }
+    }
+
+    // entries are checked by `from_data`
+    fn decode_at(&self, offset: usize) -> RawEntry<'_> {
+        match self.try_decode_at(offset) {
+            Some(entry) => entry,
+            None => unreachable!("Entry at {} isn't checked", offset),
+        }
+    }
+
+    // None if the entry at offset is out of block or has an unknown kind
+    fn try_decode_at(&self, offset: usize) -> Option<RawEntry<'_>> {
+        let data = self.block().get(offset..self.restarts_offset)?;
+        if data.len() < BLOCK_ENTRY_METADATA_SIZE {
+            return None;
+        }
+
+        let read = |offset: usize| read_u32(&data[offset..]).ok().map(|value| value as usize);
+        let shared = read(0)?;
+        let unshared = read(size_of::<u32>())?;
+        let value_size = read(2 * size_of::<u32>())?;
+        let sequence = read_u64(&data[3 * size_of::<u32>()..]).ok()?;
+        let kind =
+            user_entry::EntryKind::from_u8(data[BLOCK_ENTRY_METADATA_SIZE - size_of::<u8>()])?;
+
+        let key_offset = BLOCK_ENTRY_METADATA_SIZE;
+        let value_offset = key_offset.checked_add(unshared)?;
+        let size = value_offset.checked_add(value_size)?;
+        if size > data.len() {
+            return None;
+        }
+
+        Some(RawEntry {
+            shared,
+            unshared_key: &data[key_offset..value_offset],
+            value: &data[value_offset..size],
+            kind,
+            sequence,
+            size,
+        })
+    }
+
+    // every entry is in block, restart offsets point to entries with full keys,
+    // and shared prefixes aren't longer than previous keys
+    fn check_entries(&self) -> bool {
+        let mut offset = 0;
+        let mut key_size = 0;
+        for index in 0..self.count_entries {
+            let is_restart = index % self.restart_interval == 0;
+            if is_restart && self.restart((index / self.restart_interval) as usize) != offset {
+                return false;
+            }
+
+            let Some(entry) = self.try_decode_at(offset) else {
+                return false;
+            };
+            if entry.shared > key_size || (is_restart && entry.shared != 0) {
+                return false;
+            }
+
+            key_size = entry.shared + entry.unshared_key.len();
+            offset += entry.size;
+        }
+
+        true
+    }
+}
+
+impl<K, V> DataBlock<K, V>
 where
     K: Field + Ord + PartialEq + Eq + PartialOrd + Clone,
     V: Field + Clone,
 {
-    pub fn new(fd: &mut Box<dyn ReadSeek>, block_offset: u32, block_size: u32) -> Self {
-        let _base = fd.seek(SeekFrom::Start(block_offset as u64));
+    // `block_size` is the size on disk with trailer,
+    // `path` of the data file is reported if the block is corrupted
+    pub fn new(
+        fd: &mut Box<dyn ReadSeek>,
+        path: &Path,
+        block_offset: u64,
+        block_size: u64,
+    ) -> Result<Self> {
+        let corruption = || Error::Corruption {
+            path: path.to_path_buf(),
+            offset: block_offset,
+        };
 
-        let mut buffer = alloc_aligned(DEFAULT_DATA_BLOCK_SIZE, DEFAULT_DATA_BLOCK_ALIGN);
+        if (block_size as usize) < BLOCK_TRAILER_SIZE {
+            return Err(corruption());
+        }
 
-        let Ok(bytes) = fd.read(&mut buffer) else {
-            panic!("Failed read from disk")
-        };
+        // blocks are packed one by one, the data file is read by aligned pages
+        let start = block_offset as usize / DEFAULT_DATA_BLOCK_ALIGN * DEFAULT_DATA_BLOCK_ALIGN;
+        let end = (block_offset as usize + block_size as usize).div_ceil(DEFAULT_DATA_BLOCK_ALIGN)
+            * DEFAULT_DATA_BLOCK_ALIGN;
 
-        assert_eq!(bytes, block_size as usize);
+        let mut pages = alloc_aligned(end - start, DEFAULT_DATA_BLOCK_ALIGN);
 
-        let buffer_count_entries = &buffer[(block_size - size_of::<u32>() as u32) as usize..];
-        let Ok(count_entries) = read_u32(&buffer_count_entries) else {
-            panic!("Failed read count entires from block")
-        };
+        // an index entry of a truncated file points past its end
+        fd.seek(SeekFrom::Start(start as u64))?;
+        fd.read_exact(&mut pages).map_err(|er| match er.kind() {
+            ErrorKind::UnexpectedEof => corruption(),
+            _ => Error::from(er),
+        })?;
 
-        let mut index_entries = Vec::with_capacity(count_entries as usize);
-        let mut metadata_offset = (block_size
-            - size_of::<u32>() as u32
-            - (count_entries * size_of::<u32>() as u32)) as usize;
-        for _ in 0..count_entries {
-            let Ok(index) = read_u32(&buffer[metadata_offset..]) else {
-                panic!(
-                    "Failed read index entires from block by metadata_offset {}",
-                    metadata_offset
-                )
-            };
-            metadata_offset += size_of::<u32>() as usize;
-            index_entries.push(index);
+        let block_start = block_offset as usize - start;
+        let block = &pages[block_start..block_start + block_size as usize];
+
+        let checksum_offset = block.len() - CHECKSUM_SIZE;
+        if checksum(&block[..checksum_offset]) != read_u32(&block[checksum_offset..])? {
+            return Err(corruption());
         }
 
-        let mut data = Vec::<user_entry::UserEntry<K, V>>::with_capacity(count_entries as usize);
+        let codec_offset = checksum_offset - size_of::<u8>();
+        let raw_size_offset = codec_offset - size_of::<u32>();
+        let raw_size = read_u32(&block[raw_size_offset..])? as usize;
 
-        for offset in &index_entries {
-            let b = buffer.as_slice();
-            data.push(user_entry::UserEntry::from(&b[*offset as usize..]));
+        // uncompressed block stays in pages
+        let (data, range) = match Compression::from(block[codec_offset])? {
+            Compression::None if raw_size == raw_size_offset => {
+                (pages, block_start..block_start + raw_size)
+            }
+            Compression::None => return Err(corruption()),
+            codec => {
+                let buffer = codec.decompress(&block[..raw_size_offset], raw_size)?;
+                if buffer.len() != raw_size {
+                    return Err(corruption());
+                }
+                (buffer, 0..raw_size)
+            }
+        };
+
+        Self::from_data(data, range).ok_or_else(corruption)
+    }
+
+    // checks restart offsets at the tail of block and entries,
+    // so they are decoded without checks later
+    fn from_data(data: Vec<u8>, block: Range<usize>) -> Option<Self> {
+        let tail = &data[block.clone()];
+
+        let meta_offset = tail.len().checked_sub(2 * size_of::<u32>())?;
+        let restart_interval = read_u32(&tail[meta_offset..]).ok()?;
+        let count_entries = read_u32(&tail[meta_offset + size_of::<u32>()..]).ok()?;
+        if restart_interval == 0 {
+            return None;
         }
 
-        Self {
+        let count_restarts = count_entries.div_ceil(restart_interval) as usize;
+        let restarts_offset = meta_offset.checked_sub(count_restarts * size_of::<u32>())?;
+        for index in 0..count_restarts {
+            let restart = read_u32(&tail[restarts_offset + index * size_of::<u32>()..]).ok()?;
+            if restart as usize >= restarts_offset {
+                return None;
+            }
+        }
+
+        let data_block = Self {
             data,
-            _index_entries: index_entries,
+            block,
+            restarts_offset,
+            count_restarts,
+            restart_interval,
+            count_entries,
+            _marker: PhantomData,
+        };
+        data_block.check_entries().then_some(data_block)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_entries(entries: Vec<user_entry::UserEntry<K, V>>) -> Self {
+        use super::data_block_buffer::encode_entry;
+        use crate::core::marshal::write_u32;
+
+        let mut data = Vec::new();
+        let mut restarts = Vec::new();
+
+        for entry in &entries {
+            restarts.push(data.len() as u32);
+
+            let mut buffer = vec![0u8; BLOCK_ENTRY_METADATA_SIZE + entry.size()];
+            encode_entry(&mut buffer, entry, 0).unwrap();
+            data.extend(buffer);
+        }
+
+        // every entry is a restart point
+        restarts.extend([1, entries.len() as u32]);
+        for value in restarts {
+            let mut buffer = [0u8; size_of::<u32>()];
+            write_u32(&mut buffer, value).unwrap();
+            data.extend(buffer);
         }
+
+        let size = data.len();
+        Self::from_data(data, 0..size).unwrap()
     }
 
-    pub fn get_by_key(&self, key: &K) -> Option<V> {
-        let idx = self
-            .data
-            .binary_search_by(|entry| entry.get_key().cmp(key))
-            .ok()?;
+    // size of memory which is held by block
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.count_entries as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count_entries == 0
+    }
+
+    // entries of block in order, keys and values are borrowed
+    pub fn iter(&self) -> BlockIter<'_, K, V> {
+        BlockIter {
+            block: self,
+            cursor: EntryCursor::new(),
+        }
+    }
 
-        Some(self.data[idx].get_value().clone())
+    // the newest version of key in block
+    pub fn get_entry_by_key(&self, key: &K) -> Option<user_entry::UserEntry<K, V>> {
+        self.get_entry_by_key_at(key, user_entry::SequenceNumber::MAX)
     }
 
-    pub fn get_by_index(&self, index: usize) -> &user_entry::UserEntry<K, V> {
-        &self.data[index]
+    // the newest version of key with sequence number <= `sequence`,
+    // only the found entry is copied
+    pub fn get_entry_by_key_at(
+        &self,
+        key: &K,
+        sequence: user_entry::SequenceNumber,
+    ) -> Option<user_entry::UserEntry<K, V>> {
+        let key = key.data();
+        let mut iter = self.iter();
+        let entry = iter.seek(key, sequence)?;
+
+        (entry.key == key).then(|| entry.to_entry())
     }
 
-    pub fn into_iter(self) -> impl Iterator<Item = user_entry::UserEntry<K, V>> {
+    pub fn get_by_key(&self, key: &K) -> Option<V> {
+        self.get_entry_by_key(key)
+            .filter(|entry| !entry.is_tombstone())
+            .map(|entry| entry.get_value().clone())
+    }
+
+    pub fn get_by_index(&self, index: usize) -> user_entry::UserEntry<K, V> {
+        assert!(index < self.count_entries as usize);
+
+        let mut cursor = self.restart_cursor(index / self.restart_interval as usize);
+        loop {
+            let entry = cursor.advance(self).unwrap();
+            if cursor.index as usize > index {
+                return cursor.entry(entry).to_entry();
+            }
+        }
+    }
+
+    // block can be shared with the block cache, so entries are decoded by iterator
+    pub fn into_iter(self: Arc<Self>) -> impl Iterator<Item = user_entry::UserEntry<K, V>> {
         DataBlockIterator {
             block: self,
-            pos: 0,
+            cursor: EntryCursor::new(),
         }
     }
 }
 
+// Iterator over entries of block without copies.
+// Keys are kept by iterator, so an entry lives until the next call.
+pub struct BlockIter<'a, K, V> {
+    block: &'a DataBlock<K, V>,
+    cursor: EntryCursor,
+}
+
+impl<K, V> BlockIter<'_, K, V>
+where
+    K: Field + Ord + PartialEq + Eq + PartialOrd + Clone,
+    V: Field + Clone,
+{
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<EntryRef<'_>> {
+        let entry = self.cursor.advance(self.block)?;
+        Some(self.cursor.entry(entry))
+    }
+
+    // moves to the first entry which isn't before key with sequence number, keys are compared
+    // by bytes, newer versions of key are before older ones
+    pub fn seek(
+        &mut self,
+        key: &[u8],
+        sequence: user_entry::SequenceNumber,
+    ) -> Option<EntryRef<'_>> {
+        let block = self.block;
+        let is_before = |entry_key: &[u8], entry_sequence| {
+            entry_key < key || (entry_key == key && entry_sequence > sequence)
+        };
+
+        // binary search of the last restart point before the entry,
+        // keys of restart points are whole in block
+        let (mut left, mut right) = (0, block.count_restarts);
+        while left < right {
+            let middle = (left + right) / 2;
+
+            let entry = block.decode_at(block.restart(middle));
+            if is_before(entry.unshared_key, entry.sequence) {
+                left = middle + 1;
+            } else {
+                right = middle;
+            }
+        }
+
+        self.cursor = block.restart_cursor(left.saturating_sub(1));
+        while let Some(entry) = self.cursor.advance(block) {
+            if !is_before(&self.cursor.key, entry.sequence) {
+                return Some(self.cursor.entry(entry));
+            }
+        }
+
+        None
+    }
+}
+
 pub struct DataBlockIterator<K, V> {
-    block: DataBlock<K, V>,
-    pos: usize,
+    block: Arc<DataBlock<K, V>>,
+    cursor: EntryCursor,
 }
 
-impl<'a, K, V> Iterator for DataBlockIterator<K, V>
+impl<K, V> Iterator for DataBlockIterator<K, V>
 where
     K: Field + Ord + PartialEq + Eq + PartialOrd + Clone,
     V: Field + Clone,
@@ -98,13 +471,75 @@ where
     type Item = user_entry::UserEntry<K, V>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.block._index_entries.len() {
-            return None;
-        }
+        let entry = self.cursor.advance(&self.block)?;
+
+        Some(self.cursor.entry(entry).to_entry())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{field::FlexibleField, marshal::write_u32};
+
+    type Block = DataBlock<FlexibleField, FlexibleField>;
+
+    // encoded block of two entries where every entry is a restart point
+    fn make_data() -> Vec<u8> {
+        let entries = ["a", "b"]
+            .into_iter()
+            .map(|key| user_entry::UserEntry::new(FlexibleField::new(key), FlexibleField::new("x")))
+            .collect();
+        Block::from_entries(entries).block().to_vec()
+    }
+
+    fn restarts_offset(data: &[u8]) -> usize {
+        data.len() - 4 * size_of::<u32>()
+    }
+
+    fn is_corrupted(data: Vec<u8>) -> bool {
+        let size = data.len();
+        Block::from_data(data, 0..size).is_none()
+    }
+
+    #[test]
+    fn test_valid_block() {
+        assert!(!is_corrupted(make_data()));
+    }
+
+    #[test]
+    fn test_restart_inside_entry() {
+        let mut data = make_data();
+        let offset = restarts_offset(&data) + size_of::<u32>();
+        write_u32(&mut data[offset..], 1).unwrap();
+        assert!(is_corrupted(data));
+    }
+
+    #[test]
+    fn test_unknown_entry_kind() {
+        let mut data = make_data();
+        data[BLOCK_ENTRY_METADATA_SIZE - size_of::<u8>()] = 0;
+        assert!(is_corrupted(data));
+    }
+
+    #[test]
+    fn test_entry_out_of_block() {
+        let mut data = make_data();
+        write_u32(&mut data[size_of::<u32>()..], 1000).unwrap();
+        assert!(is_corrupted(data));
+    }
 
-        let r = self.block.get_by_index(self.pos);
-        self.pos += 1;
+    #[test]
+    fn test_shared_longer_than_previous_key() {
+        // the second entry isn't a restart point with interval 2
+        let mut data = make_data();
+        let offset = restarts_offset(&data);
+        let second = read_u32(&data[offset + size_of::<u32>()..]).unwrap() as usize;
+        data.drain(offset + size_of::<u32>()..offset + 2 * size_of::<u32>());
+        write_u32(&mut data[offset + size_of::<u32>()..], 2).unwrap();
+        assert!(!is_corrupted(data.clone()));
 
-        Some(r.clone())
+        write_u32(&mut data[second..], 2).unwrap();
+        assert!(is_corrupted(data));
     }
 }
@@ -1,57 +1,122 @@
 use std::cell::RefCell;
 
 use super::block;
+use super::checksum::{checksum, CHECKSUM_SIZE};
+use super::compression::Compression;
 use crate::common::memory::alloc_aligned;
 use crate::core::{
-    entry::flexible_user_entry::FlexibleUserEntry,
-    marshal::write_u32,
-    storage::config::{DEFAULT_DATA_BLOCK_ALIGN, DEFAULT_DATA_BLOCK_SIZE},
+    entry::{flexible_user_entry::FlexibleUserEntry, user_entry::UserEntry},
+    field::Field,
+    marshal::{write_u32, write_u64},
+    storage::config::DEFAULT_DATA_BLOCK_ALIGN,
 };
 use crate::errors::Result;
 
-pub const ENTRY_METADATA_SIZE: u32 = 2 * size_of::<u32>() as u32;
+pub const ENTRY_METADATA_SIZE: u32 =
+    (2 * size_of::<u32>() + size_of::<u64>() + size_of::<u8>()) as u32;
+// shared_key_size, unshared_key_size, value_size, sequence, kind
+pub const BLOCK_ENTRY_METADATA_SIZE: usize =
+    3 * size_of::<u32>() + size_of::<u64>() + size_of::<u8>();
+// decoded size, codec and checksum after every block on disk
+pub const BLOCK_TRAILER_SIZE: usize = size_of::<u32>() + size_of::<u8>() + CHECKSUM_SIZE;
+// every 16th entry keeps the full key
+pub const BLOCK_RESTART_INTERVAL: u32 = 16;
+
+fn shared_prefix_size(lhs: &[u8], rhs: &[u8]) -> usize {
+    lhs.iter().zip(rhs).take_while(|(l, r)| l == r).count()
+}
+
+fn block_entry_size<K: Field + Eq + PartialOrd, V: Field>(
+    entry: &UserEntry<K, V>,
+    shared: usize,
+) -> usize {
+    BLOCK_ENTRY_METADATA_SIZE + entry.size() - shared
+}
+
+// Entry in data block:
+// [ shared_key_size unshared_key_size value_size sequence kind unshared_key value ]
+//
+// the key is the first shared_key_size bytes of the previous key and unshared_key.
+pub(crate) fn encode_entry<K: Field + Eq + PartialOrd, V: Field>(
+    dst: &mut [u8],
+    entry: &UserEntry<K, V>,
+    shared: usize,
+) -> Result<usize> {
+    let key = &entry.get_key().data()[shared..];
+    let value = entry.get_value().data();
+
+    let mut offset = write_u32(dst, shared as u32)?;
+    offset += write_u32(&mut dst[offset..], key.len() as u32)?;
+    offset += write_u32(&mut dst[offset..], value.len() as u32)?;
+    offset += write_u64(&mut dst[offset..], entry.get_sequence())?;
+    dst[offset] = entry.get_kind() as u8;
+    offset += size_of::<u8>();
+
+    dst[offset..offset + key.len()].copy_from_slice(key);
+    offset += key.len();
+    dst[offset..offset + value.len()].copy_from_slice(value);
+    offset += value.len();
+
+    Ok(offset)
+}
+
+// size of block for one entry which doesn't fit into usual block
+pub fn single_entry_block_size(entry: &FlexibleUserEntry) -> usize {
+    let mut meta = Metadata::new(BLOCK_RESTART_INTERVAL);
+    meta.append(0);
 
-fn block_entry_size(entry: &FlexibleUserEntry) -> usize {
-    ENTRY_METADATA_SIZE as usize + entry.size()
+    // data block keeps a gap after entries
+    (block_entry_size(entry, 0) + meta.size() + 1).next_multiple_of(DEFAULT_DATA_BLOCK_ALIGN)
 }
 
 struct Metadata {
-    index_entries: Vec<u32>,
+    restarts: Vec<u32>,
+    restart_interval: u32,
     count_entries: u32,
 }
 
 impl Metadata {
-    fn new() -> Self {
+    fn new(restart_interval: u32) -> Self {
         Self {
-            index_entries: Vec::new(),
+            restarts: Vec::new(),
+            restart_interval,
             count_entries: 0,
         }
     }
 
     fn size(&self) -> usize {
-        size_of::<u32>() + size_of::<u32>() * self.index_entries.len()
+        size_of::<u32>() * self.restarts.len() + 2 * size_of::<u32>()
+    }
+
+    fn is_next_restart(&self) -> bool {
+        self.count_entries.is_multiple_of(self.restart_interval)
     }
 
     fn size_with_entry(&self) -> usize {
-        self.size() + size_of::<u32>()
+        match self.is_next_restart() {
+            true => self.size() + size_of::<u32>(),
+            false => self.size(),
+        }
     }
 
     fn append(&mut self, offset: u32) {
+        if self.is_next_restart() {
+            self.restarts.push(offset);
+        }
         self.count_entries += 1;
-        self.index_entries.push(offset);
     }
 
     fn reset(&mut self) {
         self.count_entries = 0;
-        self.index_entries.clear();
+        self.restarts.clear();
     }
 
     fn serialize_to(&self, buffer: &mut [u8]) -> Result<()> {
         let mut offset = 0;
-        for index in &self.index_entries {
-            write_u32(&mut buffer[offset..], *index)?;
-            offset += size_of::<u32>();
+        for restart in &self.restarts {
+            offset += write_u32(&mut buffer[offset..], *restart)?;
         }
+        offset += write_u32(&mut buffer[offset..], self.restart_interval)?;
         write_u32(&mut buffer[offset..], self.count_entries)?;
         Ok(())
     }
@@ -61,20 +126,25 @@ pub struct DataBlockBuffer {
     block_data: RefCell<Vec<u8>>,
     max_size: usize,
     current_pos: usize,
+    compression: Compression,
+    last_key: Vec<u8>,
 
     meta: Metadata,
 }
 
 impl DataBlockBuffer {
-    pub fn new() -> Self {
-        let buffer = alloc_aligned(DEFAULT_DATA_BLOCK_SIZE, DEFAULT_DATA_BLOCK_ALIGN);
+    pub fn new(block_size: usize, compression: Compression) -> Self {
+        assert_eq!(block_size % DEFAULT_DATA_BLOCK_ALIGN, 0);
+        let buffer = alloc_aligned(block_size, DEFAULT_DATA_BLOCK_ALIGN);
 
         Self {
             // @todo change
             block_data: RefCell::new(buffer),
-            max_size: DEFAULT_DATA_BLOCK_SIZE,
+            max_size: block_size,
             current_pos: 0,
-            meta: Metadata::new(),
+            compression,
+            last_key: Vec::new(),
+            meta: Metadata::new(BLOCK_RESTART_INTERVAL),
         }
     }
 
@@ -90,22 +160,31 @@ impl DataBlockBuffer {
         self.max_size - self.current_pos
     }
 
-    fn possible_append(&self, entry: &FlexibleUserEntry) -> bool {
-        self.current_pos + block_entry_size(entry) < self.max_size - self.meta.size_with_entry()
+    // restart points keep the full key
+    fn shared_key_size(&self, entry: &FlexibleUserEntry) -> usize {
+        match self.meta.is_next_restart() {
+            true => 0,
+            false => shared_prefix_size(&self.last_key, entry.get_key().data()),
+        }
     }
 
     pub fn append(&mut self, entry: &FlexibleUserEntry) -> crate::errors::Result<usize> {
-        if !self.possible_append(entry) {
+        let shared = self.shared_key_size(entry);
+        if self.current_pos + block_entry_size(entry, shared)
+            >= self.max_size - self.meta.size_with_entry()
+        {
             return Ok(0);
         }
 
         let mut dst = self.block_data.borrow_mut();
-        let entry_bytes = entry.serialize_to(&mut dst[self.current_pos..])? as usize;
+        let entry_bytes = encode_entry(&mut dst[self.current_pos..], entry, shared)?;
 
-        assert_eq!(block_entry_size(entry), entry_bytes);
+        assert_eq!(block_entry_size(entry, shared), entry_bytes);
 
         self.meta.append(self.current_pos as u32);
         self.current_pos += entry_bytes;
+        self.last_key.clear();
+        self.last_key.extend_from_slice(entry.get_key().data());
 
         Ok(entry_bytes)
     }
@@ -115,18 +194,41 @@ impl DataBlockBuffer {
 
         data.fill(0);
         self.current_pos = 0;
+        self.last_key.clear();
         self.meta.reset();
     }
 }
 
+// Data block:
+// [ entry1 ... entryN padding restart1 ... restartM restart_interval count_entries ]
+//
+// restarts are offsets of every restart_interval-th entry, their keys aren't shared.
+//
+// Block on disk:
+// [ block or compressed block, block_size, codec, checksum ]
+//
+// block_size is the decoded size, so tables with different sizes of blocks
+// can be read. checksum is CRC32C of all previous bytes.
 impl block::WriteToTable for DataBlockBuffer {
-    fn write_to(&self, ptr: &mut Box<dyn std::io::Write>) -> Result<()> {
+    fn write_to(&self, ptr: &mut Box<dyn std::io::Write>) -> Result<usize> {
         let offset = self.max_size - self.meta.size();
 
         let mut dst = self.block_data.borrow_mut();
         self.meta.serialize_to(&mut dst[offset..])?;
-        ptr.write(dst.as_slice())?;
 
-        Ok(())
+        let (codec, compressed) = match self.compression.compress(dst.as_slice()) {
+            Some(compressed) => (self.compression, compressed),
+            None => (Compression::None, dst.to_vec()),
+        };
+
+        let mut block = compressed;
+        block.extend_from_slice(&(self.max_size as u32).to_le_bytes());
+        block.push(codec as u8);
+        let block_checksum = checksum(&block);
+        block.extend_from_slice(&block_checksum.to_le_bytes());
+
+        ptr.write_all(&block)?;
+
+        Ok(block.len())
     }
 }
@@ -0,0 +1,302 @@
+use std::ops::Range;
+use std::path::Path;
+
+use crate::core::marshal::{read_u32, read_u64, write_u32, write_u64};
+use crate::errdata;
+use crate::errors::{Error, Result};
+
+use super::checksum::CHECKSUM_SIZE;
+
+// "kvs_data" and "kvs_indx" in little endian
+pub const DATA_TABLE_MAGIC: u64 = 0x6174_6164_5f73_766b;
+pub const INDEX_TABLE_MAGIC: u64 = 0x7864_6e69_5f73_766b;
+
+// version of tables written by builder, readers accept all versions up to it:
+// 1 - 32-bit offsets and sizes of blocks in index table,
+// 2 - 64-bit offsets and sizes of blocks,
+// 3 - ordinal of the first entry in index blocks instead of index of every entry,
+// 4 - properties section in index table
+pub const FORMAT_VERSION: u32 = 4;
+
+const VERSION_SIZE: usize = size_of::<u32>();
+const MAGIC_SIZE: usize = size_of::<u64>();
+const SECTION_SIZE: usize = 2 * size_of::<u64>();
+
+// [ data_size version magic ]
+pub const DATA_FOOTER_SIZE: usize = size_of::<u64>() + VERSION_SIZE + MAGIC_SIZE;
+
+// filter, index blocks, index entries and properties since the fourth version
+pub const fn count_sections(version: u32) -> usize {
+    match version {
+        1..=3 => 3,
+        _ => 4,
+    }
+}
+
+// [ filter index_blocks index_entries properties data_size
+//   checksum_filter checksum_index_blocks checksum_index_entries checksum_properties
+//   version magic ]
+pub const fn index_footer_size(version: u32) -> usize {
+    count_sections(version) * (SECTION_SIZE + CHECKSUM_SIZE)
+        + size_of::<u64>()
+        + VERSION_SIZE
+        + MAGIC_SIZE
+}
+
+pub const INDEX_FOOTER_SIZE: usize = index_footer_size(FORMAT_VERSION);
+
+// version and magic at the end of file, they are checked before anything else
+fn read_version(path: &Path, data: &[u8], magic: u64) -> Result<u32> {
+    let Some(magic_offset) = data.len().checked_sub(MAGIC_SIZE) else {
+        return errdata!("{} is truncated: {} bytes", path.display(), data.len());
+    };
+    if read_u64(&data[magic_offset..])? != magic {
+        return errdata!("{} isn't a disk table: wrong magic", path.display());
+    }
+
+    let version = read_u32(&data[magic_offset - VERSION_SIZE..])?;
+    if version == 0 || version > FORMAT_VERSION {
+        return errdata!(
+            "{} has unsupported format version {}",
+            path.display(),
+            version
+        );
+    }
+
+    Ok(version)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Section {
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl Section {
+    pub fn range(&self) -> Range<usize> {
+        self.offset as usize..(self.offset + self.size) as usize
+    }
+}
+
+// Tail of the data file, the file is padded before it up to the alignment.
+#[derive(Debug, PartialEq)]
+pub struct DataFooter {
+    pub data_size: u64,
+    pub version: u32,
+}
+
+impl DataFooter {
+    pub fn new(data_size: u64) -> Self {
+        Self {
+            data_size,
+            version: FORMAT_VERSION,
+        }
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; DATA_FOOTER_SIZE];
+
+        let mut offset = write_u64(&mut buffer, self.data_size)?;
+        offset += write_u32(&mut buffer[offset..], self.version)?;
+        write_u64(&mut buffer[offset..], DATA_TABLE_MAGIC)?;
+
+        Ok(buffer)
+    }
+
+    // `data` is the tail of the data file
+    pub fn from(path: &Path, data: &[u8]) -> Result<Self> {
+        let version = read_version(path, data, DATA_TABLE_MAGIC)?;
+        if data.len() < DATA_FOOTER_SIZE {
+            return errdata!("{} is truncated: {} bytes", path.display(), data.len());
+        }
+
+        let data_size = read_u64(&data[data.len() - DATA_FOOTER_SIZE..])?;
+
+        Ok(Self { data_size, version })
+    }
+}
+
+// Tail of the index table with offsets and checksums of its sections.
+#[derive(Debug, Default, PartialEq)]
+pub struct IndexFooter {
+    pub filter: Section,
+    pub index_blocks: Section,
+    pub index_entries: Section,
+    // empty before the fourth version
+    pub properties: Section,
+    // size of data blocks in the data file
+    pub data_size: u64,
+    pub checksums: [u32; 4],
+    pub version: u32,
+}
+
+impl IndexFooter {
+    // sections of the footer version with their checksums
+    pub fn sections(&self) -> Vec<(Section, u32)> {
+        [
+            self.filter,
+            self.index_blocks,
+            self.index_entries,
+            self.properties,
+        ]
+        .into_iter()
+        .zip(self.checksums)
+        .take(count_sections(self.version))
+        .collect()
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; index_footer_size(self.version)];
+        let sections = self.sections();
+
+        let mut offset = 0;
+        for (section, _checksum) in &sections {
+            offset += write_u64(&mut buffer[offset..], section.offset)?;
+            offset += write_u64(&mut buffer[offset..], section.size)?;
+        }
+        offset += write_u64(&mut buffer[offset..], self.data_size)?;
+        for (_section, checksum) in &sections {
+            offset += write_u32(&mut buffer[offset..], *checksum)?;
+        }
+        offset += write_u32(&mut buffer[offset..], self.version)?;
+        write_u64(&mut buffer[offset..], INDEX_TABLE_MAGIC)?;
+
+        Ok(buffer)
+    }
+
+    // `data` is the whole index table, sections must be before the footer
+    pub fn from(path: &Path, data: &[u8]) -> Result<Self> {
+        let version = read_version(path, data, INDEX_TABLE_MAGIC)?;
+        let Some(footer_offset) = data.len().checked_sub(index_footer_size(version)) else {
+            return errdata!("{} is truncated: {} bytes", path.display(), data.len());
+        };
+
+        let footer = &data[footer_offset..];
+        let mut offset = 0;
+        let mut sections = [Section::default(); 4];
+        for section in &mut sections[..count_sections(version)] {
+            section.offset = read_u64(&footer[offset..])?;
+            section.size = read_u64(&footer[offset + size_of::<u64>()..])?;
+            offset += SECTION_SIZE;
+
+            let end = section.offset.checked_add(section.size);
+            if end.is_none_or(|end| end > footer_offset as u64) {
+                return Err(Error::Corruption {
+                    path: path.to_path_buf(),
+                    offset: footer_offset as u64,
+                });
+            }
+        }
+
+        let data_size = read_u64(&footer[offset..])?;
+        offset += size_of::<u64>();
+
+        let mut checksums = [0u32; 4];
+        for checksum in &mut checksums[..count_sections(version)] {
+            *checksum = read_u32(&footer[offset..])?;
+            offset += CHECKSUM_SIZE;
+        }
+
+        let [filter, index_blocks, index_entries, properties] = sections;
+        Ok(Self {
+            filter,
+            index_blocks,
+            index_entries,
+            properties,
+            data_size,
+            checksums,
+            version,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_footer() {
+        let path = Path::new("segment_1_1.idx");
+        let footer = IndexFooter {
+            filter: Section {
+                offset: 0,
+                size: 16,
+            },
+            index_blocks: Section {
+                offset: 16,
+                size: 32,
+            },
+            index_entries: Section {
+                offset: 48,
+                size: 12,
+            },
+            properties: Section {
+                offset: 60,
+                size: 4,
+            },
+            data_size: 8192,
+            checksums: [1, 2, 3, 4],
+            version: FORMAT_VERSION,
+        };
+
+        let mut data = vec![0u8; 64];
+        data.extend(footer.serialize().unwrap());
+        assert_eq!(IndexFooter::from(path, &data).unwrap(), footer);
+
+        // the section is out of file
+        assert!(matches!(
+            IndexFooter::from(path, &data[1..]),
+            Err(Error::Corruption { .. })
+        ));
+
+        // the data footer isn't an index footer
+        let data = DataFooter::new(8192).serialize().unwrap();
+        assert!(matches!(
+            IndexFooter::from(path, &data),
+            Err(Error::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn test_footer_without_properties() {
+        let path = Path::new("segment_1_1.idx");
+        let footer = IndexFooter {
+            index_blocks: Section {
+                offset: 0,
+                size: 32,
+            },
+            index_entries: Section {
+                offset: 32,
+                size: 4,
+            },
+            data_size: 4096,
+            checksums: [1, 2, 3, 0],
+            version: 3,
+            ..Default::default()
+        };
+
+        let mut data = vec![0u8; 36];
+        data.extend(footer.serialize().unwrap());
+        assert_eq!(data.len(), 36 + index_footer_size(3));
+        assert_eq!(IndexFooter::from(path, &data).unwrap(), footer);
+        assert_eq!(footer.sections().len(), 3);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let path = Path::new("segment_1_1.bin");
+
+        let mut data = DataFooter::new(4096).serialize().unwrap();
+        assert_eq!(
+            DataFooter::from(path, &data).unwrap(),
+            DataFooter::new(4096)
+        );
+
+        let version_offset = DATA_FOOTER_SIZE - MAGIC_SIZE - VERSION_SIZE;
+        write_u32(&mut data[version_offset..], FORMAT_VERSION + 1).unwrap();
+        assert!(matches!(
+            DataFooter::from(path, &data),
+            Err(Error::InvalidData(_))
+        ));
+    }
+}
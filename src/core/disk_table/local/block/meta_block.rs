@@ -1,32 +1,79 @@
 use crate::core::{
     disk_table::local::file_handle::ReadSeek,
     field::{Field, FlexibleField},
-    marshal::{read_u32, write_u32},
-    storage::config,
+    marshal::{read_u32, read_u64, write_u32, write_u64},
 };
 
+use crate::errdata;
 use crate::errors::Result;
 
 use super::block;
+use super::footer::FORMAT_VERSION;
 
-pub type Offsets = Vec<Offset>;
-
-pub const INDEX_BLOCK_OFFSET: usize = size_of::<u32>();
-pub const INDEX_BLOCK_SIZE: usize = size_of::<u32>();
 pub const INDEX_BLOCK_KEY_SIZE: usize = size_of::<u32>();
-pub const INDEX_BLOCKS_OFFSET_SIZE: usize =
-    INDEX_BLOCK_OFFSET + INDEX_BLOCK_SIZE + INDEX_BLOCK_KEY_SIZE;
+pub const INDEX_BLOCK_FIRST_ENTRY_SIZE: usize = size_of::<u32>();
 pub const INDEX_BLOCKS_COUNT_SIZE: usize = size_of::<u32>();
 pub const INDEX_BLOCKS_BASE: usize = size_of::<u32>();
 
-pub const INDEX_ENTRIES_OFFSET_SIZE: usize = size_of::<u32>();
-pub const INDEX_ENTRIES_LEN_SIZE: usize = size_of::<u32>();
-pub const INDEX_ENTRIES_SIZE: usize = INDEX_ENTRIES_OFFSET_SIZE + INDEX_ENTRIES_LEN_SIZE;
+pub const INDEX_ENTRIES_INDEX_SIZE: usize = size_of::<u32>();
 pub const INDEX_ENTRIES_COUNT_SIZE: usize = size_of::<u32>();
 
+// offsets and sizes of blocks are 32-bit in the first format version, 64-bit since the second
+pub fn offset_size(version: u32) -> usize {
+    match version {
+        1 => size_of::<u32>(),
+        _ => size_of::<u64>(),
+    }
+}
+
+// offset of block, size of block, first entry since the third version and size of key
+pub fn index_block_header_size(version: u32) -> usize {
+    match version {
+        1 | 2 => 2 * offset_size(version) + INDEX_BLOCK_KEY_SIZE,
+        _ => 2 * offset_size(version) + INDEX_BLOCK_FIRST_ENTRY_SIZE + INDEX_BLOCK_KEY_SIZE,
+    }
+}
+
+pub fn index_entry_size(version: u32) -> usize {
+    offset_size(version) + INDEX_ENTRIES_INDEX_SIZE
+}
+
+fn read_offset(src: &[u8], version: u32) -> Result<u64> {
+    match version {
+        1 => Ok(read_u32(src)? as u64),
+        _ => read_u64(src),
+    }
+}
+
+// fields which stay 32-bit on disk, a table which needs more can't be written
+pub fn check_u32(value: usize, field: &str) -> Result<u32> {
+    match u32::try_from(value) {
+        Ok(value) => Ok(value),
+        Err(_) => errdata!(
+            "disk table exceeds limits: {} is {}, max {}",
+            field,
+            value,
+            u32::MAX
+        ),
+    }
+}
+
+// entry is addressed by its block, because blocks can be compressed,
+// tables before the third format version have it for every entry
 pub struct Offset {
-    pub pos: u32,
-    pub size: u32,
+    // offset of block in data file
+    pub block_offset: u64,
+    // position of entry in block
+    pub index: u32,
+}
+
+impl Offset {
+    pub fn from(src: &[u8], version: u32) -> Result<Self> {
+        Ok(Offset {
+            block_offset: read_offset(src, version)?,
+            index: read_u32(&src[offset_size(version)..])?,
+        })
+    }
 }
 
 // todo result
@@ -82,12 +129,8 @@ impl IndexBlocks {
         }
     }
 
-    pub fn size(&self) -> u32 {
-        let mut result = 0;
-        for v in &self.data {
-            result += v.size();
-        }
-        result
+    pub fn size(&self) -> usize {
+        self.data.iter().map(|index_block| index_block.size()).sum()
     }
 
     pub fn len(&self) -> usize {
@@ -102,42 +145,88 @@ impl IndexBlocks {
     pub fn get_by_index(&self, index: usize) -> &IndexBlock {
         &self.data[index]
     }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut IndexBlock> {
+        self.data.iter_mut()
+    }
+
+    // block of the entry with ordinal `index` in table and position of entry in block
+    pub fn find_by_entry(&self, index: u32) -> Option<(&IndexBlock, u32)> {
+        let block_index = self
+            .data
+            .partition_point(|block| block.first_entry <= index)
+            .checked_sub(1)?;
+        let block = &self.data[block_index];
+
+        Some((block, index - block.first_entry))
+    }
+
+    // index of the first block which may contain keys >= key
+    pub fn seek(&self, key: &FlexibleField) -> usize {
+        self.data
+            .partition_point(|block| block.first_key < *key)
+            .saturating_sub(1)
+    }
 }
 
-impl block::WriteToTable for IndexBlocks {
-    fn write_to(&self, ptr: &mut Box<dyn std::io::Write>) -> Result<()> {
+impl IndexBlocks {
+    // [ index_block1 ... index_blockN size_blocks count_blocks ]
+    pub fn serialize(&self) -> Result<Vec<u8>> {
         assert_ne!(0, self.data.len());
 
+        let size_blocks = check_u32(self.size(), "size of index blocks")?;
+        let count_blocks = check_u32(self.data.len(), "count of blocks")?;
+
+        let mut buffer = vec![0u8; self.size() + INDEX_BLOCKS_BASE + INDEX_BLOCKS_COUNT_SIZE];
+
+        let mut offset = 0;
         for index_block in &self.data {
-            index_block.write_to(ptr)?;
+            offset += index_block.serialize_to(&mut buffer[offset..])?;
         }
 
-        ptr.write(&(self.size()).to_le_bytes())?;
-        ptr.write(&(self.data.len() as u32).to_le_bytes())?;
+        offset += write_u32(&mut buffer[offset..], size_blocks)?;
+        write_u32(&mut buffer[offset..], count_blocks)?;
+
+        Ok(buffer)
+    }
+}
+
+impl block::WriteToTable for IndexBlocks {
+    fn write_to(&self, ptr: &mut Box<dyn std::io::Write>) -> Result<usize> {
+        let buffer = self.serialize()?;
+        ptr.write_all(&buffer)?;
 
-        Ok(())
+        Ok(buffer.len())
     }
 }
 
 pub struct IndexBlock {
-    pub block_offset: u32,
-    pub block_size: u32,
+    pub block_offset: u64,
+    // size of block on disk with trailer
+    pub block_size: u64,
+    // ordinal of the first entry of block in table
+    pub first_entry: u32,
     pub key_size: u32,
     pub first_key: FlexibleField,
 }
 
 impl IndexBlock {
     // @todo depends on seek position
-    pub fn from(fd: &mut Box<dyn ReadSeek>) -> Result<Self> {
-        let mut buffer = [0u8; INDEX_BLOCKS_OFFSET_SIZE];
-
-        let bytes = fd.read(&mut buffer)?;
-
-        assert_eq!(bytes, INDEX_BLOCKS_OFFSET_SIZE);
-
-        let block_offset = read_u32(&buffer[..INDEX_BLOCK_OFFSET])?;
-        let block_size = read_u32(&buffer[INDEX_BLOCK_OFFSET..])?;
-        let key_size = read_u32(&buffer[INDEX_BLOCK_OFFSET + INDEX_BLOCK_SIZE..])?;
+    // first_entry of older versions is 0, it's restored from index entries
+    pub fn from(fd: &mut Box<dyn ReadSeek>, version: u32) -> Result<Self> {
+        let mut buffer = vec![0u8; index_block_header_size(version)];
+        fd.read_exact(&mut buffer)?;
+
+        let offset_size = offset_size(version);
+        let block_offset = read_offset(&buffer, version)?;
+        let block_size = read_offset(&buffer[offset_size..], version)?;
+        let (first_entry, key_size) = match version {
+            1 | 2 => (0, read_u32(&buffer[2 * offset_size..])?),
+            _ => (
+                read_u32(&buffer[2 * offset_size..])?,
+                read_u32(&buffer[2 * offset_size + INDEX_BLOCK_FIRST_ENTRY_SIZE..])?,
+            ),
+        };
 
         let mut buffer = vec![0u8; key_size as usize];
 
@@ -146,37 +235,136 @@ impl IndexBlock {
 
         let first_key = FlexibleField::new(buffer);
 
-        assert_eq!(block_size, config::DEFAULT_DATA_BLOCK_SIZE as u32);
-
         Ok(IndexBlock {
             block_offset,
             block_size,
+            first_entry,
             key_size,
             first_key,
         })
     }
 
-    pub fn size(&self) -> u32 {
-        (3 * size_of::<u32>()) as u32 + self.key_size
+    // size in index table of the current format version
+    pub fn size(&self) -> usize {
+        index_block_header_size(FORMAT_VERSION) + self.key_size as usize
+    }
+}
+
+impl IndexBlock {
+    pub fn serialize_to(&self, dst: &mut [u8]) -> Result<usize> {
+        let mut offset = write_u64(dst, self.block_offset)?;
+        offset += write_u64(&mut dst[offset..], self.block_size)?;
+        offset += write_u32(&mut dst[offset..], self.first_entry)?;
+        offset += write_u32(&mut dst[offset..], self.key_size)?;
+
+        let key = self.first_key.data();
+        dst[offset..offset + key.len()].copy_from_slice(key);
+
+        Ok(offset + key.len())
     }
 }
 
 impl block::WriteToTable for IndexBlock {
-    fn write_to(&self, ptr: &mut Box<dyn std::io::Write>) -> Result<()> {
-        let mut tmp = [0; INDEX_BLOCKS_OFFSET_SIZE];
+    fn write_to(&self, ptr: &mut Box<dyn std::io::Write>) -> Result<usize> {
+        let mut buffer = vec![0u8; self.size()];
+        self.serialize_to(&mut buffer)?;
+        ptr.write_all(&buffer)?;
 
-        write_u32(&mut tmp[0..INDEX_BLOCK_OFFSET], self.block_offset)?;
-        write_u32(&mut tmp[INDEX_BLOCK_OFFSET..], self.block_size)?;
-        write_u32(
-            &mut tmp[INDEX_BLOCK_OFFSET + INDEX_BLOCK_SIZE..],
-            self.key_size,
-        )?;
-        ptr.write(&tmp)?;
+        Ok(buffer.len())
+    }
+}
 
-        assert_eq!(self.block_size, config::DEFAULT_DATA_BLOCK_SIZE as u32);
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::errors::Error;
+
+    #[test]
+    fn test_offsets_over_4gib() {
+        let index_block = IndexBlock {
+            block_offset: 5 << 30,
+            block_size: 4101,
+            first_entry: 1 << 20,
+            key_size: 3,
+            first_key: FlexibleField::new(b"key".to_vec()),
+        };
+
+        let mut buffer = vec![0u8; index_block.size()];
+        index_block.serialize_to(&mut buffer).unwrap();
+
+        let mut fd: Box<dyn ReadSeek> = Box::new(Cursor::new(buffer));
+        let read = IndexBlock::from(&mut fd, FORMAT_VERSION).unwrap();
+        assert_eq!(read.block_offset, 5 << 30);
+        assert_eq!(read.block_size, 4101);
+        assert_eq!(read.first_entry, 1 << 20);
+        assert_eq!(read.first_key, index_block.first_key);
+
+        // [ block_offset index ] of the second format version
+        let buffer = [(6u64 << 30).to_le_bytes().as_slice(), &7u32.to_le_bytes()].concat();
+        assert_eq!(buffer.len(), index_entry_size(2));
+
+        let read = Offset::from(&buffer, 2).unwrap();
+        assert_eq!((read.block_offset, read.index), (6 << 30, 7));
+    }
+
+    #[test]
+    fn test_find_by_entry() {
+        let mut index_blocks = IndexBlocks::new();
+        for (block_offset, first_entry) in [(0, 0), (4096, 10), (8192, 11)] {
+            index_blocks.append(IndexBlock {
+                block_offset,
+                block_size: 4096,
+                first_entry,
+                key_size: 1,
+                first_key: FlexibleField::new(vec![first_entry as u8]),
+            });
+        }
 
-        ptr.write(self.first_key.data())?;
+        let find = |index| {
+            index_blocks
+                .find_by_entry(index)
+                .map(|(block, index)| (block.block_offset, index))
+        };
+        assert_eq!(find(0), Some((0, 0)));
+        assert_eq!(find(9), Some((0, 9)));
+        assert_eq!(find(10), Some((4096, 0)));
+        assert_eq!(find(11), Some((8192, 0)));
+        assert_eq!(find(20), Some((8192, 9)));
+    }
+
+    #[test]
+    fn test_first_format_version() {
+        // [ block_offset block_size key_size key ] with 32-bit offset and size
+        let mut buffer = [
+            8192u32.to_le_bytes(),
+            4101u32.to_le_bytes(),
+            3u32.to_le_bytes(),
+        ]
+        .concat();
+        buffer.extend_from_slice(b"key");
+
+        let mut fd: Box<dyn ReadSeek> = Box::new(Cursor::new(buffer));
+        let read = IndexBlock::from(&mut fd, 1).unwrap();
+        assert_eq!((read.block_offset, read.block_size), (8192, 4101));
+        assert_eq!(read.first_entry, 0);
+        assert_eq!(read.first_key, FlexibleField::new(b"key".to_vec()));
+
+        let buffer = [8192u32.to_le_bytes(), 7u32.to_le_bytes()].concat();
+        let read = Offset::from(&buffer, 1).unwrap();
+        assert_eq!((read.block_offset, read.index), (8192, 7));
+    }
 
-        Ok(())
+    #[test]
+    fn test_limits() {
+        assert_eq!(
+            check_u32(u32::MAX as usize, "count of entries"),
+            Ok(u32::MAX)
+        );
+        assert!(matches!(
+            check_u32(u32::MAX as usize + 1, "count of entries"),
+            Err(Error::InvalidData(_))
+        ));
     }
 }
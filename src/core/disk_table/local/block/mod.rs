@@ -1,4 +1,10 @@
 pub mod block;
+pub mod block_cache;
+pub mod bloom_filter;
+pub mod checksum;
+pub mod compression;
 pub mod data_block;
 pub mod data_block_buffer;
+pub mod footer;
 pub mod meta_block;
+pub mod properties;
@@ -0,0 +1,272 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::core::disk_table::disk_tables_shard::Levels;
+use crate::core::entry::flexible_user_entry::FlexibleUserEntry;
+use crate::core::field::{Field, FlexibleField};
+use crate::core::marshal::{read_u32, read_u64, write_u32, write_u64};
+use crate::errdata;
+use crate::errors::Result;
+
+// Properties section of index table:
+// [ count_entries count_tombstones raw_key_size raw_value_size creation_time level
+//   smallest_key_size smallest_key largest_key_size largest_key
+//   count_sources source_size1 source1 ... source_sizeN sourceN ]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableProperties {
+    pub smallest_key: FlexibleField,
+    pub largest_key: FlexibleField,
+    pub count_entries: u64,
+    pub count_tombstones: u64,
+    // sizes of keys and values before compression
+    pub raw_key_size: u64,
+    pub raw_value_size: u64,
+    // seconds since the unix epoch
+    pub creation_time: u64,
+    pub level: Levels,
+    // names of merged tables, a flushed memory table has none
+    pub source_tables: Vec<String>,
+}
+
+impl TableProperties {
+    // the key can be in the table
+    pub fn contains_key(&self, key: &FlexibleField) -> bool {
+        self.smallest_key <= *key && *key <= self.largest_key
+    }
+
+    // the table can have keys in [start, end), None is an open bound
+    pub fn overlaps(&self, start: Option<&FlexibleField>, end: Option<&FlexibleField>) -> bool {
+        start.is_none_or(|start| *start <= self.largest_key)
+            && end.is_none_or(|end| self.smallest_key < *end)
+    }
+
+    fn size(&self) -> usize {
+        5 * size_of::<u64>()
+            + size_of::<Levels>()
+            + 2 * size_of::<u32>()
+            + self.smallest_key.size()
+            + self.largest_key.size()
+            + size_of::<u32>()
+            + self
+                .source_tables
+                .iter()
+                .map(|name| size_of::<u32>() + name.len())
+                .sum::<usize>()
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; self.size()];
+
+        let mut offset = 0;
+        for value in [
+            self.count_entries,
+            self.count_tombstones,
+            self.raw_key_size,
+            self.raw_value_size,
+            self.creation_time,
+        ] {
+            offset += write_u64(&mut buffer[offset..], value)?;
+        }
+        buffer[offset] = self.level;
+        offset += size_of::<Levels>();
+
+        offset += TableProperties::write_bytes(&mut buffer[offset..], self.smallest_key.data())?;
+        offset += TableProperties::write_bytes(&mut buffer[offset..], self.largest_key.data())?;
+
+        offset += write_u32(&mut buffer[offset..], self.source_tables.len() as u32)?;
+        for name in &self.source_tables {
+            offset += TableProperties::write_bytes(&mut buffer[offset..], name.as_bytes())?;
+        }
+
+        assert_eq!(offset, buffer.len());
+
+        Ok(buffer)
+    }
+
+    pub fn from(src: &[u8]) -> Result<Self> {
+        let mut reader = SectionReader { src, offset: 0 };
+
+        let count_entries = reader.read_u64()?;
+        let count_tombstones = reader.read_u64()?;
+        let raw_key_size = reader.read_u64()?;
+        let raw_value_size = reader.read_u64()?;
+        let creation_time = reader.read_u64()?;
+        let level = reader.read_bytes(size_of::<Levels>())?[0];
+
+        let smallest_key = FlexibleField::new(reader.read_sized()?);
+        let largest_key = FlexibleField::new(reader.read_sized()?);
+
+        let count_sources = reader.read_u32()?;
+        let mut source_tables = Vec::new();
+        for _ in 0..count_sources {
+            let Ok(name) = String::from_utf8(reader.read_sized()?.to_vec()) else {
+                return errdata!("invalid source table name in properties");
+            };
+            source_tables.push(name);
+        }
+
+        Ok(Self {
+            smallest_key,
+            largest_key,
+            count_entries,
+            count_tombstones,
+            raw_key_size,
+            raw_value_size,
+            creation_time,
+            level,
+            source_tables,
+        })
+    }
+
+    fn write_bytes(dst: &mut [u8], bytes: &[u8]) -> Result<usize> {
+        let offset = write_u32(dst, bytes.len() as u32)?;
+        dst[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Ok(offset + bytes.len())
+    }
+}
+
+// reads fields of the section one by one, a truncated section is invalid data
+struct SectionReader<'a> {
+    src: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> SectionReader<'a> {
+    fn read_bytes(&mut self, size: usize) -> Result<&'a [u8]> {
+        let Some(bytes) = self.src.get(self.offset..self.offset + size) else {
+            return errdata!("properties are truncated: {} bytes", self.src.len());
+        };
+        self.offset += size;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        read_u32(self.read_bytes(size_of::<u32>())?)
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        read_u64(self.read_bytes(size_of::<u64>())?)
+    }
+
+    fn read_sized(&mut self) -> Result<&'a [u8]> {
+        let size = self.read_u32()? as usize;
+        self.read_bytes(size)
+    }
+}
+
+// Properties of entries appended to the building table, entries come sorted by key.
+#[derive(Default)]
+pub struct PropertiesCollector {
+    smallest_key: Option<FlexibleField>,
+    largest_key: Option<FlexibleField>,
+    count_entries: u64,
+    count_tombstones: u64,
+    raw_key_size: u64,
+    raw_value_size: u64,
+}
+
+impl PropertiesCollector {
+    pub fn add(&mut self, entry: &FlexibleUserEntry) {
+        if self.smallest_key.is_none() {
+            self.smallest_key = Some(entry.get_key().clone());
+        }
+        if self.largest_key.as_ref() != Some(entry.get_key()) {
+            self.largest_key = Some(entry.get_key().clone());
+        }
+
+        self.count_entries += 1;
+        if entry.is_tombstone() {
+            self.count_tombstones += 1;
+        }
+        self.raw_key_size += entry.get_key().size() as u64;
+        self.raw_value_size += entry.get_value().size() as u64;
+    }
+
+    // None if no entry was added
+    pub fn finish(&self, level: Levels, source_tables: &[String]) -> Option<TableProperties> {
+        let creation_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        Some(TableProperties {
+            smallest_key: self.smallest_key.clone()?,
+            largest_key: self.largest_key.clone()?,
+            count_entries: self.count_entries,
+            count_tombstones: self.count_tombstones,
+            raw_key_size: self.raw_key_size,
+            raw_value_size: self.raw_value_size,
+            creation_time,
+            level,
+            source_tables: source_tables.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::Error;
+
+    fn make_key(index: u8) -> FlexibleField {
+        FlexibleField::new(vec![index, 1])
+    }
+
+    #[test]
+    fn test_collect_and_serialize() {
+        let mut collector = PropertiesCollector::default();
+        assert!(collector.finish(1, &[]).is_none());
+
+        collector.add(&FlexibleUserEntry::new(
+            make_key(1),
+            FlexibleField::new(vec![1; 10]),
+        ));
+        collector.add(&FlexibleUserEntry::new_tombstone(make_key(2)));
+        collector.add(&FlexibleUserEntry::new(
+            make_key(5),
+            FlexibleField::new(vec![5; 6]),
+        ));
+
+        let sources = vec!["segment_1_1.bin".to_string(), "segment_2_1.bin".to_string()];
+        let properties = collector.finish(2, &sources).unwrap();
+        assert_eq!(properties.smallest_key, make_key(1));
+        assert_eq!(properties.largest_key, make_key(5));
+        assert_eq!(
+            (properties.count_entries, properties.count_tombstones),
+            (3, 1)
+        );
+        assert_eq!(
+            (properties.raw_key_size, properties.raw_value_size),
+            (6, 16)
+        );
+
+        let data = properties.serialize().unwrap();
+        assert_eq!(TableProperties::from(&data).unwrap(), properties);
+        assert!(matches!(
+            TableProperties::from(&data[..data.len() - 1]),
+            Err(Error::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn test_key_range() {
+        let mut collector = PropertiesCollector::default();
+        for index in [2, 4, 6] {
+            collector.add(&FlexibleUserEntry::new(
+                make_key(index),
+                FlexibleField::new(vec![index]),
+            ));
+        }
+        let properties = collector.finish(1, &[]).unwrap();
+
+        assert!(!properties.contains_key(&make_key(1)));
+        assert!(properties.contains_key(&make_key(2)));
+        assert!(properties.contains_key(&make_key(3)));
+        assert!(properties.contains_key(&make_key(6)));
+        assert!(!properties.contains_key(&make_key(7)));
+
+        assert!(properties.overlaps(None, None));
+        assert!(properties.overlaps(Some(&make_key(6)), None));
+        assert!(!properties.overlaps(Some(&make_key(7)), None));
+        assert!(!properties.overlaps(None, Some(&make_key(2))));
+        assert!(properties.overlaps(Some(&make_key(0)), Some(&make_key(3))));
+    }
+}
@@ -1,18 +1,31 @@
+use std::fs;
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use super::file_handle::FileHandle;
+use super::file_handle::{self, FileHandle};
 use super::reader_local_disk_table::{ReaderDiskTablePtr, ReaderFlexibleDiskTable};
+use crate::core::disk_table::disk_tables_shard::{Levels, SEGMENTS_MIN_LEVEL};
 use crate::core::disk_table::local::block::{
     block::WriteToTable,
-    data_block_buffer,
-    data_block_buffer::DataBlockBuffer,
+    block_cache::BlockCache,
+    bloom_filter::{bloom_hash, BloomFilter},
+    checksum::checksum,
+    compression::Compression,
+    data_block_buffer::{self, DataBlockBuffer},
+    footer::{DataFooter, IndexFooter, Section, DATA_FOOTER_SIZE, FORMAT_VERSION},
     meta_block,
-    meta_block::{IndexBlock, IndexBlocks, Offset},
+    meta_block::{IndexBlock, IndexBlocks},
+    properties::PropertiesCollector,
 };
 use crate::core::entry::flexible_user_entry::FlexibleUserEntry;
-use crate::core::field::Field;
+use crate::core::field::{Field, FlexibleField};
 use crate::core::marshal::write_u32;
-use crate::errors::Result;
+use crate::core::storage::config::{
+    DEFAULT_BLOOM_BITS_PER_KEY, DEFAULT_COMPRESSION, DEFAULT_DATA_BLOCK_ALIGN,
+    DEFAULT_DATA_BLOCK_SIZE,
+};
+use crate::errors::{Error, Result};
 
 pub struct DiskTableBuilder {
     disk_table_path: PathBuf,
@@ -21,9 +34,27 @@ pub struct DiskTableBuilder {
     building_index_table: Option<Box<dyn std::io::Write>>,
 
     data_block: Option<DataBlockBuffer>,
-    index_entries: Vec<Offset>,
+    count_entries: u32,
     index_blocks: IndexBlocks,
-    offset: u32,
+    // offset of the building block in data file
+    offset: u64,
+    // first key and count of entries of the building block
+    block_first_key: Option<FlexibleField>,
+    block_entries: u32,
+    data_block_size: usize,
+    compression: Compression,
+
+    bloom_bits_per_key: usize,
+    key_hashes: Vec<u32>,
+
+    properties: PropertiesCollector,
+    level: Levels,
+    source_tables: Vec<String>,
+
+    block_cache: Option<Arc<BlockCache>>,
+
+    // the first exceeded limit of format, it's returned by build
+    limit_error: Option<Error>,
 }
 
 impl DiskTableBuilder {
@@ -43,10 +74,24 @@ impl DiskTableBuilder {
             index_table_path: index_table_path.as_ref().to_path_buf(),
             building_disk_table: Some(data_handle),
             building_index_table: Some(index_handle),
-            index_entries: Vec::<Offset>::new(),
+            count_entries: 0,
             index_blocks: IndexBlocks::new(),
             offset: 0,
-            data_block: Some(DataBlockBuffer::new()),
+            block_first_key: None,
+            block_entries: 0,
+            data_block_size: DEFAULT_DATA_BLOCK_SIZE,
+            compression: DEFAULT_COMPRESSION,
+            data_block: Some(DataBlockBuffer::new(
+                DEFAULT_DATA_BLOCK_SIZE,
+                DEFAULT_COMPRESSION,
+            )),
+            bloom_bits_per_key: DEFAULT_BLOOM_BITS_PER_KEY,
+            key_hashes: Vec::new(),
+            properties: PropertiesCollector::default(),
+            level: SEGMENTS_MIN_LEVEL,
+            source_tables: Vec::new(),
+            block_cache: None,
+            limit_error: None,
         }
     }
 
@@ -56,59 +101,163 @@ impl DiskTableBuilder {
             index_table_path: index_table_path.as_ref().to_path_buf(),
             building_disk_table: None,
             building_index_table: None,
-            index_entries: Vec::<Offset>::new(),
+            count_entries: 0,
             index_blocks: IndexBlocks::new(),
             offset: 0,
+            block_first_key: None,
+            block_entries: 0,
+            data_block_size: DEFAULT_DATA_BLOCK_SIZE,
+            compression: DEFAULT_COMPRESSION,
             data_block: None,
+            bloom_bits_per_key: DEFAULT_BLOOM_BITS_PER_KEY,
+            key_hashes: Vec::new(),
+            properties: PropertiesCollector::default(),
+            level: SEGMENTS_MIN_LEVEL,
+            source_tables: Vec::new(),
+            block_cache: None,
+            limit_error: None,
         }
     }
 
-    pub fn append_entry(&mut self, entry: &FlexibleUserEntry) -> &mut Self {
-        let esstimate_entry_size = data_block_buffer::ENTRY_METADATA_SIZE as usize + entry.size();
+    // level of the table in properties
+    pub fn set_level(&mut self, level: Levels) -> &mut Self {
+        self.level = level;
+        self
+    }
+
+    // names of tables which are merged into the table
+    pub fn set_source_tables(&mut self, source_tables: Vec<String>) -> &mut Self {
+        self.source_tables = source_tables;
+        self
+    }
+
+    // 0 builds the table without bloom filter
+    pub fn set_bloom_bits_per_key(&mut self, bloom_bits_per_key: usize) -> &mut Self {
+        self.bloom_bits_per_key = bloom_bits_per_key;
+        self
+    }
+
+    // the built table reads data blocks through the shared cache
+    pub fn set_block_cache(&mut self, block_cache: Arc<BlockCache>) -> &mut Self {
+        self.block_cache = Some(block_cache);
+        self
+    }
+
+    // codec of data blocks, a block which isn't compressed well is stored as is
+    pub fn set_compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = compression;
+        self.reset_data_block_buffer();
+        self
+    }
 
-        let Some(data_block) = &mut self.data_block else {
-            panic!("Logic error")
+    // decoded size of data blocks, a multiple of the alignment
+    pub fn set_data_block_size(&mut self, data_block_size: usize) -> &mut Self {
+        assert_eq!(data_block_size % DEFAULT_DATA_BLOCK_ALIGN, 0);
+        assert_ne!(data_block_size, 0);
+
+        self.data_block_size = data_block_size;
+        self.reset_data_block_buffer();
+        self
+    }
+
+    fn reset_data_block_buffer(&mut self) {
+        if let Some(data_block) = &mut self.data_block {
+            assert!(data_block.empty());
+            *data_block = DataBlockBuffer::new(self.data_block_size, self.compression);
+        }
+    }
+
+    // writes the building block and indexes it by its offset and size on disk
+    fn flush_data_block(&mut self) {
+        let (Some(data_block), Some(writer)) =
+            (&mut self.data_block, &mut self.building_disk_table)
+        else {
+            panic!("Failed write data block to None")
+        };
+
+        let Some(first_key) = self.block_first_key.take() else {
+            return;
         };
 
-        for i in 0..3 {
-            if i == 2 {
+        let block_size = match data_block.write_to(writer) {
+            Ok(bytes) => bytes as u64,
+            Err(er) => panic!("Failed write data block in builder: {}", er),
+        };
+
+        self.index_blocks.append(IndexBlock {
+            block_offset: self.offset,
+            block_size,
+            first_entry: self.count_entries - self.block_entries,
+            key_size: first_key.size() as u32,
+            first_key,
+        });
+
+        self.offset += block_size;
+        self.block_entries = 0;
+        data_block.reset();
+    }
+
+    // an entry which can't be written to any disk table, writes of such entries fail
+    pub fn check_entry(entry: &FlexibleUserEntry) -> Result<()> {
+        meta_block::check_u32(
+            data_block_buffer::single_entry_block_size(entry),
+            "size of block with entry",
+        )
+        .map(|_| ())
+    }
+
+    // an entry which exceeds limits of format isn't appended, build returns the error
+    pub fn append_entry(&mut self, entry: &FlexibleUserEntry) -> &mut Self {
+        if self.limit_error.is_some() {
+            return self;
+        }
+        if let Err(er) = Self::check_entry(entry).and_then(|_| {
+            meta_block::check_u32(self.count_entries as usize + 1, "count of entries").map(|_| ())
+        }) {
+            self.limit_error = Some(er);
+            return self;
+        }
+
+        for i in 0..4 {
+            if i == 3 {
                 panic!("Logic error")
             }
 
+            let Some(data_block) = &mut self.data_block else {
+                panic!("Logic error")
+            };
+
             let is_block_empty = data_block.empty();
             match data_block.append(entry) {
                 Ok(0) => {
                     if is_block_empty {
-                        panic!("problem with size entry and data block size")
-                    }
-                    let remaining_bytes = data_block.remaining_size();
-                    self.offset += remaining_bytes as u32;
-
-                    match &mut self.building_disk_table {
-                        Some(writer) => {
-                            if let Err(er) = data_block.write_to(writer) {
-                                panic!("Failed write data block in builder: {}", er)
-                            }
-                        }
-                        None => panic!("Failed write entry to None"),
+                        // entry which doesn't fit into block gets its own larger block
+                        let block_size = data_block_buffer::single_entry_block_size(entry);
+                        assert!(block_size > self.data_block_size);
+                        *data_block = DataBlockBuffer::new(block_size, self.compression);
+                        continue;
                     }
 
-                    data_block.reset();
+                    self.flush_data_block();
                 }
-                Ok(bytes) => {
+                Ok(_bytes) => {
                     if is_block_empty {
-                        self.index_blocks.append(IndexBlock {
-                            block_offset: self.offset,
-                            block_size: data_block.max_size() as u32,
-                            key_size: entry.get_key().size() as u32,
-                            first_key: entry.get_key().clone(),
-                        });
+                        self.block_first_key = Some(entry.get_key().clone());
+                    }
+                    self.count_entries += 1;
+                    self.block_entries += 1;
+                    self.properties.add(entry);
+
+                    // versions of key go one by one
+                    let key_hash = bloom_hash(entry.get_key().data());
+                    if self.key_hashes.last() != Some(&key_hash) {
+                        self.key_hashes.push(key_hash);
+                    }
+
+                    if data_block.max_size() != self.data_block_size {
+                        self.flush_data_block();
+                        self.reset_data_block_buffer();
                     }
-                    self.index_entries.push(Offset {
-                        pos: self.offset,
-                        size: esstimate_entry_size as u32,
-                    });
-                    self.offset += bytes as u32;
                     break;
                 }
                 Err(er) => panic!(
@@ -121,34 +270,110 @@ impl DiskTableBuilder {
         self
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.count_entries == 0
+    }
+
+    // merges start the next table, half of the limit leaves room for versions of the last key
+    pub fn is_full(&self) -> bool {
+        self.count_entries >= u32::MAX / 2
+    }
+
+    // size of written data blocks, the building block isn't counted
+    pub fn data_size(&self) -> u64 {
+        self.offset
+    }
+
+    // removes files of the table which won't be built or which failed to build,
+    // a failed build may have already closed writers or not created the index file
+    pub fn discard(&mut self) -> Result<()> {
+        self.building_disk_table.take();
+        self.building_index_table.take();
+        self.data_block.take();
+
+        for path in [&self.disk_table_path, &self.index_table_path] {
+            match fs::remove_file(path) {
+                Err(er) if er.kind() != ErrorKind::NotFound => return Err(er.into()),
+                _ => {}
+            }
+        }
+
+        file_handle::sync_dir(self.disk_table_path.as_path())?;
+
+        Ok(())
+    }
+
+    // the footer ends the last aligned page of data file
+    fn write_data_footer(&mut self) -> Result<()> {
+        let Some(writer) = &mut self.building_disk_table else {
+            panic!("Failed write data footer to None")
+        };
+
+        let data_end = self.offset as usize + DATA_FOOTER_SIZE;
+        let padding = data_end.next_multiple_of(DEFAULT_DATA_BLOCK_ALIGN) - data_end;
+
+        writer.write_all(&vec![0u8; padding])?;
+        writer.write_all(&DataFooter::new(self.offset).serialize()?)?;
+
+        Ok(())
+    }
+
     fn write_index_table(&mut self) -> Result<()> {
         let Some(index_table) = &mut self.building_index_table else {
             return Ok(());
         };
 
-        assert_ne!(self.index_entries.len(), 0);
+        assert_ne!(self.count_entries, 0);
         assert_ne!(self.index_blocks.len(), 0);
         assert_ne!(self.index_blocks.size(), 0);
 
-        self.index_blocks.write_to(index_table)?;
+        // sections are found by offsets in the footer
+        let filter = (self.bloom_bits_per_key != 0)
+            .then(|| BloomFilter::new(&self.key_hashes, self.bloom_bits_per_key));
+        let filter_section = BloomFilter::serialize(filter.as_ref())?;
 
-        // write index_entries
-        for offset in &self.index_entries {
-            let mut tmp = [0; meta_block::INDEX_ENTRIES_SIZE];
-            write_u32(
-                &mut tmp[0..meta_block::INDEX_ENTRIES_OFFSET_SIZE],
-                offset.pos,
-            )?;
-            write_u32(
-                &mut tmp[meta_block::INDEX_ENTRIES_OFFSET_SIZE..],
-                offset.size,
-            )?;
+        let index_blocks_section = self.index_blocks.serialize()?;
 
-            index_table.write(&tmp)?;
+        let Some(properties) = self.properties.finish(self.level, &self.source_tables) else {
+            panic!("Failed build properties of empty disk table")
+        };
+        let properties_section = properties.serialize()?;
+
+        // entries are found by first entries of index blocks, only their count is left
+        let mut index_entries_section = vec![0u8; meta_block::INDEX_ENTRIES_COUNT_SIZE];
+        write_u32(&mut index_entries_section, self.count_entries)?;
+
+        // the count of entries is the last section before the footer
+        let sections = [
+            filter_section,
+            index_blocks_section,
+            properties_section,
+            index_entries_section,
+        ];
+        let mut bounds = [Section::default(); 4];
+        let mut checksums = [0u32; 4];
+        let mut offset = 0;
+        for (index, section) in sections.iter().enumerate() {
+            index_table.write_all(section)?;
+
+            bounds[index] = Section {
+                offset,
+                size: section.len() as u64,
+            };
+            checksums[index] = checksum(section);
+            offset += section.len() as u64;
         }
 
-        // write index_entries size
-        index_table.write(&(self.index_entries.len() as u32).to_le_bytes())?;
+        let footer = IndexFooter {
+            filter: bounds[0],
+            index_blocks: bounds[1],
+            index_entries: bounds[3],
+            properties: bounds[2],
+            data_size: self.offset,
+            checksums: [checksums[0], checksums[1], checksums[3], checksums[2]],
+            version: FORMAT_VERSION,
+        };
+        index_table.write_all(&footer.serialize()?)?;
 
         {
             let Some(mut writer) = self.building_index_table.take() else {
@@ -173,20 +398,17 @@ impl DiskTableBuilder {
             let reader = ReaderFlexibleDiskTable::new(
                 self.disk_table_path.as_path(),
                 self.index_table_path.as_path(),
+                self.block_cache.clone(),
             )?;
             return Ok(reader);
         };
 
-        let Some(disk_table) = &mut self.building_disk_table else {
-            panic!("We are building disk table")
-        };
+        if let Some(er) = self.limit_error.take() {
+            return Err(er);
+        }
 
-        if let Some(data_block) = &mut self.data_block {
-            if let Err(er) = data_block.write_to(disk_table) {
-                panic!("Failed write last data block in builder: {}", er)
-            }
-            data_block.reset();
-        };
+        self.flush_data_block();
+        self.write_data_footer()?;
 
         // @todo close?
         {
@@ -204,6 +426,7 @@ impl DiskTableBuilder {
         let reader = ReaderFlexibleDiskTable::new(
             self.disk_table_path.as_path(),
             self.index_table_path.as_path(),
+            self.block_cache.clone(),
         )?;
         Ok(reader)
     }
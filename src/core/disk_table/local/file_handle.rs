@@ -7,6 +7,8 @@ use nix::fcntl;
 use nix::fcntl::OFlag;
 use nix::unistd::Whence;
 
+use crate::common::memory::alloc_aligned;
+use crate::core::storage::config::DEFAULT_DATA_BLOCK_ALIGN;
 use crate::errors::Result;
 use crate::logicerr;
 
@@ -15,6 +17,55 @@ pub(super) struct FileHandle {
     table_path: PathBuf,
 }
 
+// Packs blocks of any size into the data file opened with O_DIRECT:
+// bytes are written by aligned pages, the tail is padded with zeros on flush,
+// so flush must be called once after the last block.
+struct AlignedWriter {
+    file: FileHandle,
+    page: Vec<u8>,
+    len: usize,
+}
+
+impl AlignedWriter {
+    fn new(file: FileHandle) -> Self {
+        Self {
+            file,
+            page: alloc_aligned(DEFAULT_DATA_BLOCK_ALIGN, DEFAULT_DATA_BLOCK_ALIGN),
+            len: 0,
+        }
+    }
+}
+
+impl std::io::Write for AlignedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+
+        while written < buf.len() {
+            let bytes = (self.page.len() - self.len).min(buf.len() - written);
+            self.page[self.len..self.len + bytes].copy_from_slice(&buf[written..written + bytes]);
+            self.len += bytes;
+            written += bytes;
+
+            if self.len == self.page.len() {
+                self.file.write_all(&self.page)?;
+                self.len = 0;
+            }
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.len != 0 {
+            self.page[self.len..].fill(0);
+            self.file.write_all(&self.page)?;
+            self.len = 0;
+        }
+
+        self.file.flush()
+    }
+}
+
 pub trait ReadSeek: std::io::Read + std::io::Seek + Send + Sync {}
 
 pub fn sync_dir<P: AsRef<Path>>(path: P) -> Result<()> {
@@ -39,10 +90,10 @@ impl FileHandle {
         )?;
 
         let disk_table_path = disk_table_path.as_ref().to_path_buf();
-        Ok(Box::new(Self {
+        Ok(Box::new(AlignedWriter::new(Self {
             fd,
             table_path: disk_table_path,
-        }))
+        })))
     }
     pub fn new_index_writer<P: AsRef<Path>>(
         index_table_path: P,
@@ -163,3 +214,5 @@ impl Drop for FileHandle {
         }
     }
 }
+
+impl ReadSeek for std::io::Cursor<Vec<u8>> {}
@@ -1,18 +1,25 @@
 use std::cell::RefCell;
 use std::fs;
-use std::io::SeekFrom;
+use std::io::{Cursor, Read, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use crate::common::memory::alloc_aligned;
-use crate::core::disk_table::local::block::{data_block, data_block_buffer, meta_block};
-use crate::core::field::Field;
-use crate::core::marshal::read_u32;
+use crate::core::disk_table::local::block::{
+    block_cache::{BlockCache, CachedBlock},
+    bloom_filter::BloomFilter,
+    checksum::checksum,
+    data_block, footer, meta_block,
+    properties::TableProperties,
+};
 use crate::core::storage::config::DEFAULT_DATA_BLOCK_ALIGN;
 use crate::core::{
-    disk_table::disk_table, entry::flexible_user_entry::FlexibleUserEntry, field::FlexibleField,
+    disk_table::disk_table,
+    entry::{flexible_user_entry::FlexibleUserEntry, user_entry::SequenceNumber},
+    field::{Field, FlexibleField},
 };
-use crate::errors::Result;
+use crate::errdata;
+use crate::errors::{Error, Result};
 
 use super::file_handle::{self, FileHandle, ReadSeek};
 
@@ -23,8 +30,15 @@ pub struct ReaderFlexibleDiskTable {
     index_table_path: PathBuf,
     fd: Mutex<RefCell<Box<dyn ReadSeek>>>,
     count_entries: u32,
-    entries_offsets: meta_block::Offsets,
+    // size of data blocks in the data file
+    data_size: u64,
     index_blocks: meta_block::IndexBlocks,
+    filter: Option<BloomFilter>,
+    // tables before the fourth format version have no properties
+    properties: Option<TableProperties>,
+    // decoded blocks are shared by tables of storage
+    block_cache: Option<Arc<BlockCache>>,
+    table_id: u64,
 }
 
 // @todo drop
@@ -32,98 +46,213 @@ impl ReaderFlexibleDiskTable {
     pub(super) fn new<P: AsRef<Path>>(
         disk_table_path: P,
         index_table_path: P,
+        block_cache: Option<Arc<BlockCache>>,
     ) -> Result<ReaderDiskTablePtr> {
-        let mut index_fd: Box<dyn ReadSeek> =
-            FileHandle::new_index_reader(index_table_path.as_ref())?;
-        index_fd
-            .seek(std::io::SeekFrom::End(
-                -(meta_block::INDEX_ENTRIES_COUNT_SIZE as i64),
-            ))
-            .unwrap();
+        let (footer, index_table) =
+            ReaderFlexibleDiskTable::read_index_table(index_table_path.as_ref())?;
+        let section = |section: footer::Section| -> Box<dyn ReadSeek> {
+            Box::new(Cursor::new(index_table[section.range()].to_vec()))
+        };
 
-        let mut buffer = [0u8; meta_block::INDEX_ENTRIES_COUNT_SIZE];
+        let mut index_fd = section(footer.index_entries);
+        index_fd.seek(std::io::SeekFrom::End(
+            -(meta_block::INDEX_ENTRIES_COUNT_SIZE as i64),
+        ))?;
 
-        match index_fd.read(&mut buffer) {
-            Ok(bytes) => assert_eq!(bytes, meta_block::INDEX_ENTRIES_COUNT_SIZE),
-            Err(er) => return Err(er.into()),
-        }
+        let mut buffer = [0u8; meta_block::INDEX_ENTRIES_COUNT_SIZE];
+        index_fd.read_exact(&mut buffer)?;
 
         let count_entries = u32::from_le_bytes(buffer);
-        let entries_offsets =
-            ReaderFlexibleDiskTable::read_index_entries(&mut index_fd, count_entries)?;
-
-        assert_ne!(entries_offsets.len(), 0);
-
-        let base = (meta_block::INDEX_ENTRIES_COUNT_SIZE
-            + count_entries as usize * meta_block::INDEX_ENTRIES_SIZE) as i64;
+        assert_ne!(count_entries, 0);
 
+        let mut index_blocks_fd = section(footer.index_blocks);
         let (offset_index_blocks, count_blocks) =
-            meta_block::metadata_index_blocks(base, &mut index_fd);
+            meta_block::metadata_index_blocks(0, &mut index_blocks_fd);
 
-        let index_blocks = ReaderFlexibleDiskTable::read_index_blocks(
-            &mut index_fd,
+        let mut index_blocks = ReaderFlexibleDiskTable::read_index_blocks(
+            &mut index_blocks_fd,
             offset_index_blocks,
             count_blocks,
+            footer.version,
         )?;
 
         assert_ne!(index_blocks.len(), 0);
         assert_ne!(index_blocks.size(), 0);
 
-        let data_fd: Box<dyn ReadSeek> = FileHandle::new_data_reader(disk_table_path.as_ref())?;
+        if footer.version < 3 {
+            ReaderFlexibleDiskTable::read_first_entries(
+                &mut index_fd,
+                &mut index_blocks,
+                count_entries,
+                footer.version,
+            )
+            .map_err(|_| Error::Corruption {
+                path: index_table_path.as_ref().to_path_buf(),
+                offset: footer.index_entries.offset,
+            })?;
+        }
+
+        let filter = BloomFilter::from(&mut section(footer.filter))?;
+
+        let properties = match footer.version {
+            1..=3 => None,
+            _ => Some(TableProperties::from(
+                &index_table[footer.properties.range()],
+            )?),
+        };
+
+        let mut data_fd: Box<dyn ReadSeek> = FileHandle::new_data_reader(disk_table_path.as_ref())?;
+        ReaderFlexibleDiskTable::check_data_table(disk_table_path.as_ref(), &mut data_fd, &footer)?;
+
+        let table_id = block_cache
+            .as_ref()
+            .map_or(0, |block_cache| block_cache.new_table_id());
 
         Ok(Arc::new(Self {
             disk_table_path: disk_table_path.as_ref().to_path_buf(),
             index_table_path: index_table_path.as_ref().to_path_buf(),
             fd: Mutex::new(RefCell::new(data_fd)),
             count_entries,
-            entries_offsets,
+            data_size: footer.data_size,
             index_blocks,
+            filter,
+            properties,
+            block_cache,
+            table_id,
         }))
     }
 
-    #[deprecated]
-    fn read_index_entries(
+    fn load_block(&self, index_block: &meta_block::IndexBlock) -> Result<CachedBlock> {
+        let key = (self.table_id, index_block.block_offset);
+
+        if let Some(block) = self
+            .block_cache
+            .as_ref()
+            .and_then(|block_cache| block_cache.get(&key))
+        {
+            return Ok(block);
+        }
+
+        let block = Arc::new(data_block::DataBlock::new(
+            &mut self.fd.lock().unwrap().borrow_mut(),
+            self.disk_table_path.as_path(),
+            index_block.block_offset,
+            index_block.block_size,
+        )?);
+
+        if let Some(block_cache) = &self.block_cache {
+            // the cache is charged by decoded size
+            block_cache.insert(key, block.clone(), block.size());
+        }
+
+        Ok(block)
+    }
+
+    // Index table:
+    // [ filter index_blocks index_entries footer ]
+    //
+    // The footer is checked and sections are verified by their checksums.
+    fn read_index_table(index_table_path: &Path) -> Result<(footer::IndexFooter, Vec<u8>)> {
+        let mut data = Vec::new();
+        FileHandle::new_index_reader(index_table_path)?.read_to_end(&mut data)?;
+
+        let footer = footer::IndexFooter::from(index_table_path, &data)?;
+
+        for (section, expected) in footer.sections() {
+            if checksum(&data[section.range()]) != expected {
+                return Err(Error::Corruption {
+                    path: index_table_path.to_path_buf(),
+                    offset: section.offset,
+                });
+            }
+        }
+
+        Ok((footer, data))
+    }
+
+    // Data table:
+    // [ data_block1 ... data_blockN padding footer ]
+    //
+    // The footer ends the last aligned page, its data size must match the index table.
+    fn check_data_table(
+        disk_table_path: &Path,
+        fd: &mut Box<dyn ReadSeek>,
+        index_footer: &footer::IndexFooter,
+    ) -> Result<()> {
+        let size = fd.seek(SeekFrom::End(0))? as usize;
+        if size < DEFAULT_DATA_BLOCK_ALIGN || !size.is_multiple_of(DEFAULT_DATA_BLOCK_ALIGN) {
+            return errdata!("{} is truncated: {} bytes", disk_table_path.display(), size);
+        }
+
+        let mut page = alloc_aligned(DEFAULT_DATA_BLOCK_ALIGN, DEFAULT_DATA_BLOCK_ALIGN);
+        fd.seek(SeekFrom::Start((size - DEFAULT_DATA_BLOCK_ALIGN) as u64))?;
+        fd.read_exact(&mut page)?;
+
+        let footer = footer::DataFooter::from(disk_table_path, &page)?;
+        if footer.data_size != index_footer.data_size
+            || footer.data_size as usize + footer::DATA_FOOTER_SIZE > size
+        {
+            return errdata!(
+                "{} doesn't match its index table: data size {} instead of {}",
+                disk_table_path.display(),
+                footer.data_size,
+                index_footer.data_size
+            );
+        }
+
+        Ok(())
+    }
+
+    // Tables before the third format version index every entry, they are scanned once
+    // for first entries of blocks and aren't kept in memory.
+    fn read_first_entries(
         fd: &mut Box<dyn ReadSeek>,
+        index_blocks: &mut meta_block::IndexBlocks,
         count_entries: u32,
-    ) -> Result<meta_block::Offsets> {
+        version: u32,
+    ) -> Result<()> {
+        let entry_size = meta_block::index_entry_size(version);
         fd.seek(std::io::SeekFrom::End(
             -(meta_block::INDEX_ENTRIES_COUNT_SIZE as i64
-                + count_entries as i64 * meta_block::INDEX_ENTRIES_SIZE as i64),
+                + count_entries as i64 * entry_size as i64),
         ))?;
 
-        let mut index_entries = meta_block::Offsets::new();
-        index_entries.reserve(count_entries as usize);
-
-        for _entry_offset in 0..count_entries {
-            let mut buffer = [0u8; meta_block::INDEX_ENTRIES_SIZE];
-
-            let bytes = fd.read(&mut buffer)?;
-            assert_eq!(bytes, meta_block::INDEX_ENTRIES_SIZE);
-            let entry_offset = read_u32(&buffer[..meta_block::INDEX_ENTRIES_OFFSET_SIZE])?;
-            let entry_size = read_u32(&buffer[meta_block::INDEX_ENTRIES_OFFSET_SIZE..])?;
+        let mut blocks = index_blocks.iter_mut();
+        let mut buffer = vec![0u8; entry_size];
+        for entry in 0..count_entries {
+            fd.read_exact(&mut buffer)?;
+            let offset = meta_block::Offset::from(&buffer, version)?;
+            if offset.index != 0 {
+                continue;
+            }
 
-            assert_ne!(entry_size, 0);
+            match blocks.next() {
+                Some(block) if block.block_offset == offset.block_offset => {
+                    block.first_entry = entry
+                }
+                _ => return errdata!("index entries don't match index blocks"),
+            }
+        }
 
-            index_entries.push(meta_block::Offset {
-                pos: entry_offset,
-                size: entry_size,
-            });
+        if blocks.next().is_some() {
+            return errdata!("index entries don't match index blocks");
         }
 
-        Ok(index_entries)
+        Ok(())
     }
 
     fn read_index_blocks(
         fd: &mut Box<dyn ReadSeek>,
         start_offset: i64,
         count_blocks: u32,
+        version: u32,
     ) -> Result<meta_block::IndexBlocks> {
         fd.seek(std::io::SeekFrom::End(-(start_offset)))?;
 
         let mut index_blocks = meta_block::IndexBlocks::with_capacity(count_blocks as usize);
 
         for _ in 0..count_blocks {
-            index_blocks.append(meta_block::IndexBlock::from(fd)?);
+            index_blocks.append(meta_block::IndexBlock::from(fd, version)?);
         }
 
         Ok(index_blocks)
@@ -146,6 +275,10 @@ impl disk_table::DiskTable<FlexibleField, FlexibleField> for ReaderFlexibleDiskT
     }
 
     fn remove(&self) -> Result<()> {
+        if let Some(block_cache) = &self.block_cache {
+            block_cache.erase_table(self.table_id);
+        }
+
         // @todo unlink through the file handle
         fs::remove_file(self.disk_table_path.as_path())?;
         fs::remove_file(self.index_table_path.as_path())?;
@@ -163,113 +296,112 @@ impl disk_table::DiskTable<FlexibleField, FlexibleField> for ReaderFlexibleDiskT
 
 impl disk_table::Reader<FlexibleField, FlexibleField> for ReaderFlexibleDiskTable {
     fn read(&self, key: &FlexibleField) -> Result<Option<FlexibleField>> {
-        let mut left = 0;
-        let mut right = self.index_blocks.len();
+        let entry = self.read_entry(key)?;
 
-        let result = loop {
-            if !(left < right) {
-                break None;
-            }
-            let mid = (left + right) / 2;
-            let index = self.index_blocks.get_by_index(mid);
-
-            match index.first_key.cmp(key) {
-                std::cmp::Ordering::Less => {
-                    if left + 1 == right {
-                        break Some(mid);
-                    }
-                    left = mid;
-                }
-                std::cmp::Ordering::Equal => {
-                    break Some(mid);
-                }
-                std::cmp::Ordering::Greater => {
-                    if left + 1 == right {
-                        break None;
-                    }
-                    right = mid;
-                }
-            };
-        };
-
-        match result {
-            Some(index) => {
-                let index_block = self.index_blocks.get_by_index(index);
+        Ok(entry
+            .filter(|entry| !entry.is_tombstone())
+            .map(|entry| entry.get_value().clone()))
+    }
 
-                let block = data_block::DataBlock::new(
-                    &mut self.fd.lock().unwrap().borrow_mut(),
-                    index_block.block_offset,
-                    index_block.block_size,
-                );
-                return Ok(block.get_by_key(key));
-            }
-            None => Ok(None),
-        }
+    fn read_entry(&self, key: &FlexibleField) -> Result<Option<FlexibleUserEntry>> {
+        self.read_entry_at(key, SequenceNumber::MAX)
     }
 
-    fn read_entry_by_index(&self, index: u32) -> Result<Option<FlexibleUserEntry>> {
-        let Some(offset) = self.entries_offsets.get(index as usize) else {
-            panic!("Something wrong: index {} must's be here", index)
-        };
+    fn read_entry_at(
+        &self,
+        key: &FlexibleField,
+        sequence: SequenceNumber,
+    ) -> Result<Option<FlexibleUserEntry>> {
+        if !self.may_contain(key) {
+            return Ok(None);
+        }
 
-        let buffer = {
-            let lock = self.fd.lock().unwrap();
+        // the key is out of the key range of table
+        if self
+            .properties
+            .as_ref()
+            .is_some_and(|properties| !properties.contains_key(key))
+        {
+            return Ok(None);
+        }
 
-            lock.borrow_mut().seek(SeekFrom::Start(offset.pos as u64))?;
+        // versions of key can continue in the next blocks
+        let mut index = self.index_blocks.seek(key);
 
-            assert_ne!(offset.size, 0);
+        while index < self.index_blocks.len() {
+            let index_block = self.index_blocks.get_by_index(index);
+            if index_block.first_key > *key {
+                break;
+            }
 
-            // read row with entry
-            let mut buffer = alloc_aligned(offset.size as usize, DEFAULT_DATA_BLOCK_ALIGN);
+            let block = self.load_block(index_block)?;
+            if let Some(entry) = block.get_entry_by_key_at(key, sequence) {
+                return Ok(Some(entry));
+            }
 
-            let bytes = lock.borrow_mut().read(&mut buffer)?;
-            assert_eq!(bytes, offset.size as usize);
+            index += 1;
+        }
 
-            buffer
-        };
+        Ok(None)
+    }
 
-        // parse metadata
-        let key_len = read_u32(&buffer[0..])? as usize;
-        assert_ne!(key_len, 0);
+    fn may_contain(&self, key: &FlexibleField) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.may_contain(key.data()))
+    }
 
-        let value_len = read_u32(&buffer[size_of::<u32>() as usize..])?;
-        assert_ne!(value_len, 0);
+    fn read_entry_by_index(&self, index: u32) -> Result<Option<FlexibleUserEntry>> {
+        if index >= self.count_entries {
+            return Ok(None);
+        }
 
-        let (buffer_with_key, buffer_value) =
-            buffer.split_at(data_block_buffer::ENTRY_METADATA_SIZE as usize + key_len);
+        let Some((index_block, index)) = self.index_blocks.find_by_entry(index) else {
+            return Err(Error::Corruption {
+                path: self.index_table_path.clone(),
+                offset: 0,
+            });
+        };
 
-        let key_buffer = &buffer_with_key[data_block_buffer::ENTRY_METADATA_SIZE as usize
-            ..data_block_buffer::ENTRY_METADATA_SIZE as usize + key_len];
-        let value_buffer = &buffer_value[..];
+        let block = self.load_block(index_block)?;
+        if index as usize >= block.len() {
+            return Err(Error::Corruption {
+                path: self.disk_table_path.clone(),
+                offset: index_block.block_offset,
+            });
+        }
 
-        return Ok(Some(FlexibleUserEntry::new(
-            FlexibleField::new(key_buffer.to_vec()),
-            FlexibleField::new(value_buffer.to_vec()),
-        )));
+        Ok(Some(block.get_by_index(index as usize)))
     }
 
     fn read_block(
         &self,
         index: usize,
-    ) -> Option<data_block::DataBlock<FlexibleField, FlexibleField>> {
+    ) -> Result<Option<Arc<data_block::DataBlock<FlexibleField, FlexibleField>>>> {
         assert_ne!(self.index_blocks.len(), 0);
 
         if index >= self.index_blocks.len() {
-            return None;
+            return Ok(None);
         }
 
         let index_block = self.index_blocks.get_by_index(index);
 
-        let block = data_block::DataBlock::new(
-            &mut self.fd.lock().unwrap().borrow_mut(),
-            index_block.block_offset,
-            index_block.block_size,
-        );
+        self.load_block(index_block).map(Some)
+    }
 
-        Some(block)
+    fn seek_block(&self, key: &FlexibleField) -> usize {
+        self.index_blocks.seek(key)
     }
 
     fn count_entries(&self) -> u32 {
         self.count_entries
     }
+
+    fn data_size(&self) -> u64 {
+        self.data_size
+    }
+
+    fn properties(&self) -> Option<&TableProperties> {
+        self.properties.as_ref()
+    }
 }
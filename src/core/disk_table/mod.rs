@@ -1,3 +1,4 @@
+pub mod compaction;
 pub mod disk_table;
 pub mod disk_tables_shard;
 pub mod id;
@@ -1,19 +1,39 @@
-use std::sync::{Arc, RwLock};
+use std::cmp::Reverse;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
 
 use crate::errors::Result;
 
-use crate::core::disk_table::local::reader_local_disk_table::ReaderDiskTablePtr;
+use crate::core::{
+    disk_table::{
+        disk_table::get_disk_table_id, local::reader_local_disk_table::ReaderDiskTablePtr,
+    },
+    field::FlexibleField,
+};
 
 pub type ReaderDiskTables = Vec<ReaderDiskTablePtr>;
 
+// Tables of one level.
+//
+// Tables of the first level overlap and are read from the newest one.
+// Tables of the next levels have disjoint key ranges and are sorted by them,
+// so a key is in one table at most. Storages before leveled compaction can have
+// overlapping tables there, such a level is read as the first one until it's compacted.
 pub(super) struct ShardLevel {
     pub disk_tables: Arc<RwLock<ReaderDiskTables>>,
+    sorted: bool,
+    // tables are sorted by key ranges, it's changed under the write lock of tables
+    disjoint: AtomicBool,
 }
 
 impl<'a> ShardLevel {
-    pub fn new() -> Self {
+    pub fn new(sorted: bool) -> Self {
         Self {
             disk_tables: Arc::new(RwLock::new(Vec::new())),
+            sorted,
+            disjoint: AtomicBool::new(sorted),
         }
     }
 
@@ -21,8 +41,36 @@ impl<'a> ShardLevel {
         let mut lock = self.disk_tables.write().unwrap();
         lock.push(reader);
 
+        self.sort(&mut lock);
+    }
+
+    // tables without properties have unknown key ranges
+    fn sort(&self, disk_tables: &mut ReaderDiskTables) {
+        let disjoint = self.sorted
+            && disk_tables
+                .iter()
+                .all(|disk_table| disk_table.properties().is_some())
+            && {
+                disk_tables.sort_by(|l, r| {
+                    let (l, r) = (l.properties().unwrap(), r.properties().unwrap());
+                    l.smallest_key.cmp(&r.smallest_key)
+                });
+                disk_tables.windows(2).all(|pair| {
+                    pair[0].properties().unwrap().largest_key
+                        < pair[1].properties().unwrap().smallest_key
+                })
+            };
+
         // @todo sort at once
-        lock.sort_by(|l, r| r.get_name().cmp(l.get_name()));
+        if !disjoint {
+            disk_tables.sort_by_key(|disk_table| {
+                Reverse((
+                    get_disk_table_id(disk_table.get_name()),
+                    disk_table.get_name().to_string(),
+                ))
+            });
+        }
+        self.disjoint.store(disjoint, Ordering::SeqCst);
     }
 
     pub fn get(&self, index: usize) -> ReaderDiskTablePtr {
@@ -36,9 +84,27 @@ impl<'a> ShardLevel {
         for r in &mut lock.iter() {
             // @todo
             // assert_eq!(Arc::strong_count(r), 1);
+            // also evicts blocks of table from block cache
             r.remove()?;
         }
         lock.clear();
+        self.disjoint.store(self.sorted, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    // removes files of tables with the names
+    pub fn remove(&self, names: &[String]) -> Result<()> {
+        let mut lock = self.disk_tables.write().unwrap();
+
+        for disk_table in lock.iter() {
+            if names.iter().any(|name| name == disk_table.get_name()) {
+                disk_table.remove()?;
+            }
+        }
+        lock.retain(|disk_table| !names.iter().any(|name| name == disk_table.get_name()));
+
+        self.sort(&mut lock);
 
         Ok(())
     }
@@ -48,6 +114,27 @@ impl<'a> ShardLevel {
         lock.len()
     }
 
+    pub fn is_disjoint(&self) -> bool {
+        self.disjoint.load(Ordering::SeqCst)
+    }
+
+    // tables which can contain key in order of reads, the only one of disjoint level.
+    // `disjoint` is changed under the write lock, so it's checked under the same read lock
+    pub fn find(&self, key: &FlexibleField) -> ReaderDiskTables {
+        let lock = self.disk_tables.read().unwrap();
+        if !self.is_disjoint() {
+            return lock.clone();
+        }
+
+        let index =
+            lock.partition_point(|disk_table| disk_table.properties().unwrap().largest_key < *key);
+        lock.get(index)
+            .filter(|disk_table| disk_table.properties().unwrap().contains_key(key))
+            .cloned()
+            .into_iter()
+            .collect()
+    }
+
     pub fn iter(&'a self) -> impl Iterator<Item = ReaderDiskTablePtr> + 'a {
         ShardLevelIterator {
             shard: self,
@@ -73,3 +160,53 @@ impl<'a> Iterator for ShardLevelIterator<'a> {
         Some(self.shard.get(index))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        disk_table::local::disk_table_builder::DiskTableBuilder,
+        entry::flexible_user_entry::FlexibleUserEntry, field::Field,
+        storage::config::DEFAULT_TEST_TABLES_PATH,
+    };
+    use tempfile::Builder;
+
+    fn make_key(index: u32) -> FlexibleField {
+        FlexibleField::new(index.to_be_bytes())
+    }
+
+    fn build_table(path: &std::path::Path, id: u32, keys: &[u32]) -> ReaderDiskTablePtr {
+        let mut builder = DiskTableBuilder::new(
+            path.join(format!("segment_{id}_2.bin")),
+            path.join(format!("segment_{id}_2.idx")),
+        );
+        for index in keys {
+            builder.append_entry(&FlexibleUserEntry::new(make_key(*index), make_key(id)));
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_find_in_overlapping_sorted_level() {
+        let tmp_dir = Builder::new()
+            .prefix(DEFAULT_TEST_TABLES_PATH)
+            .tempdir()
+            .unwrap();
+        let level = ShardLevel::new(true);
+
+        level.push(build_table(tmp_dir.path(), 1, &[1, 2]));
+        level.push(build_table(tmp_dir.path(), 2, &[3, 4]));
+        assert_eq!(level.find(&make_key(3)).len(), 1);
+        assert!(level.find(&make_key(5)).is_empty());
+
+        // an overlapping table makes the level to be read as the first one, the newest table first
+        level.push(build_table(tmp_dir.path(), 3, &[2, 3]));
+        let names = level
+            .find(&make_key(3))
+            .iter()
+            .map(|disk_table| disk_table.get_name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), 3);
+        assert!(names[0].starts_with("segment_3_"));
+    }
+}
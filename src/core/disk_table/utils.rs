@@ -1,11 +1,16 @@
 use std::fs;
 use std::path::Path;
 
+use log::info;
+
 use crate::errors::Result;
 
 use super::disk_table::get_disk_table_path;
-use super::disk_tables_shard::DiskTablesShards;
+use super::disk_tables_shard::{DiskTablesShards, Levels};
 use super::local::disk_table_builder::DiskTableBuilder;
+use crate::core::storage::config::StorageConfig;
+use crate::core::storage::manifest::Version;
+use crate::core::storage::value_log::ValueLog;
 
 fn extract_level(disk_table: &str) -> Option<u8> {
     // segment_123_4.bin
@@ -18,59 +23,86 @@ fn extract_level(disk_table: &str) -> Option<u8> {
     level.parse::<u8>().ok()
 }
 
-pub fn get_disk_tables(storage_path: &Path) -> Result<DiskTablesShards> {
-    let segment_dir = format!("{}/segment", storage_path.to_str().unwrap());
-
-    let shards = fs::read_dir(segment_dir)?
-        .filter_map(|entry| {
-            let result = match entry {
-                Ok(entry) => {
-                    let pb = entry.path();
-                    let ext = pb.extension().unwrap().to_str().unwrap();
-                    if ext == "idx" {
-                        return None;
-                    }
-                    let disk_table_name = pb.file_name().unwrap().to_str().unwrap();
-
-                    let result = match extract_level(disk_table_name) {
-                        Some(level) => {
-                            let idx_file_path = pb.with_extension("idx");
-                            assert!(idx_file_path.exists());
-
-                            let index_table_name =
-                                idx_file_path.file_name().unwrap().to_str().unwrap();
-
-                            let (disk_table_path, index_table_path) = get_disk_table_path(
-                                storage_path,
-                                &disk_table_name,
-                                &index_table_name,
-                            );
-
-                            // @todo
-                            let reader_disk_table =
-                                DiskTableBuilder::from(disk_table_path, index_table_path)
-                                    .build()
-                                    .unwrap();
-                            Some((level, reader_disk_table))
-                        }
-                        None => panic!("failed parse disk table name ={}.", disk_table_name),
-                    };
-                    Some(result)
-                }
-                Err(er) => {
-                    panic!("get_disk_tables failed with error={}", er);
-                }
-            };
-            result
-        })
-        // Sorting in shard level for every push isn't good idea. However, it is very fast implemention.
-        // Assume here we could accumalate all disk tables for sorting.
-        .fold(DiskTablesShards::new(), |table, res| {
-            if let Some((level, reader_disk_table)) = res {
-                table.put_disk_table_by_level(level, reader_disk_table);
+fn segment_dir(storage_path: &Path) -> String {
+    format!("{}/segment", storage_path.to_str().unwrap())
+}
+
+// disk tables with levels from their file names,
+// storages which were created before manifest have only them
+pub fn list_disk_tables(storage_path: &Path) -> Result<Vec<(Levels, String)>> {
+    let mut disk_tables = Vec::new();
+
+    for entry in fs::read_dir(segment_dir(storage_path))? {
+        let pb = entry?.path();
+        let ext = pb.extension().unwrap().to_str().unwrap();
+        if ext == "idx" {
+            continue;
+        }
+        let disk_table_name = pb.file_name().unwrap().to_str().unwrap();
+
+        match extract_level(disk_table_name) {
+            Some(level) => {
+                assert!(pb.with_extension("idx").exists());
+                disk_tables.push((level, disk_table_name.to_string()));
             }
-            table
-        });
+            None => panic!("failed parse disk table name ={}.", disk_table_name),
+        }
+    }
+
+    Ok(disk_tables)
+}
+
+// opens disk tables of version
+pub fn get_disk_tables(
+    storage_path: &Path,
+    config: &StorageConfig,
+    version: &Version,
+) -> Result<DiskTablesShards> {
+    let mut shards = DiskTablesShards::from_config(config.clone());
+    shards.set_value_log(ValueLog::open(storage_path, config.value_log_file_size)?);
+
+    // Sorting in shard level for every push isn't good idea. However, it is very fast implemention.
+    // Assume here we could accumalate all disk tables for sorting.
+    for (disk_table_name, level) in version.tables() {
+        let index_table_name = Path::new(disk_table_name).with_extension("idx");
+
+        let (disk_table_path, index_table_path) = get_disk_table_path(
+            storage_path,
+            disk_table_name,
+            index_table_name.to_str().unwrap(),
+        );
+
+        let reader_disk_table = DiskTableBuilder::from(disk_table_path, index_table_path)
+            .set_block_cache(shards.block_cache().clone())
+            .build()?;
+        shards.put_disk_table_by_level(level, reader_disk_table);
+    }
 
     Ok(shards)
 }
+
+// removes files of disk tables which aren't in version:
+// outputs of interrupted flushes and inputs of interrupted merges
+pub fn remove_unreferenced_disk_tables(storage_path: &Path, version: &Version) -> Result<usize> {
+    let mut removed = 0;
+
+    for entry in fs::read_dir(segment_dir(storage_path))? {
+        let pb = entry?.path();
+        let disk_table_name = pb.with_extension("bin");
+        let disk_table_name = disk_table_name.file_name().unwrap().to_str().unwrap();
+
+        if version.contains(disk_table_name) {
+            continue;
+        }
+
+        info!("remove unreferenced file {}", pb.display());
+        fs::remove_file(pb.as_path())?;
+        removed += 1;
+    }
+
+    if removed != 0 {
+        fs::File::open(segment_dir(storage_path))?.sync_all()?;
+    }
+
+    Ok(removed)
+}
@@ -1,10 +1,44 @@
 use crate::core::field::Field;
-use crate::core::marshal::{read_u32, write_data, write_u32};
+use std::cmp::Ordering;
+
+use crate::core::marshal::{read_u32, read_u64, write_data, write_u32, write_u64};
 use crate::errors::Result;
 use crate::logicerr;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
-pub struct UserEntry<K, V>(K, V);
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum EntryKind {
+    Value = 1,
+    // hides all older versions of the key, the value is empty
+    Tombstone = 2,
+    // the value is a pointer into the value log, only disk tables have such entries
+    ValuePointer = 3,
+}
+
+impl EntryKind {
+    pub fn from(kind: u8) -> Self {
+        match Self::from_u8(kind) {
+            Some(kind) => kind,
+            None => panic!("unknown entry kind {}", kind),
+        }
+    }
+
+    // None for an unknown kind of broken data
+    pub fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            1 => Some(EntryKind::Value),
+            2 => Some(EntryKind::Tombstone),
+            3 => Some(EntryKind::ValuePointer),
+            _ => None,
+        }
+    }
+}
+
+// Every write gets the next sequence number of storage, the greater number wins.
+pub type SequenceNumber = u64;
+
+// Entries are ordered by key, and the newest version of key goes first.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct UserEntry<K, V>(K, V, EntryKind, SequenceNumber);
 
 impl<K, V> UserEntry<K, V>
 where
@@ -12,7 +46,15 @@ where
     V: Field,
 {
     pub fn new(key: K, value: V) -> Self {
-        UserEntry { 0: key, 1: value }
+        UserEntry(key, value, EntryKind::Value, 0)
+    }
+
+    pub fn new_tombstone(key: K) -> Self {
+        UserEntry(key, V::new(Vec::new()), EntryKind::Tombstone, 0)
+    }
+
+    pub fn from_parts(key: K, value: V, kind: EntryKind, sequence: SequenceNumber) -> Self {
+        UserEntry(key, value, kind, sequence)
     }
 
     pub fn from(buffer: &[u8]) -> Self {
@@ -22,9 +64,18 @@ where
         offset += size_of::<u32>() as usize;
 
         let value_len = read_u32(&buffer[offset..]).unwrap() as usize;
-        assert_ne!(value_len, 0);
         offset += size_of::<u32>() as usize;
 
+        let sequence = read_u64(&buffer[offset..]).unwrap();
+        offset += size_of::<u64>();
+
+        let kind = EntryKind::from(buffer[offset]);
+        offset += size_of::<u8>();
+
+        if kind == EntryKind::Value {
+            assert_ne!(value_len, 0);
+        }
+
         let mut k: Vec<u8> = vec![0u8; key_len];
         write_data(&mut k, &buffer[offset..], key_len).unwrap();
         offset += key_len;
@@ -33,10 +84,7 @@ where
         write_data(&mut v, &buffer[offset..], value_len).unwrap();
         // offset += value_len;
 
-        UserEntry {
-            0: K::new(k),
-            1: V::new(v),
-        }
+        UserEntry(K::new(k), V::new(v), kind, sequence)
     }
 
     pub fn get_key(&self) -> &K {
@@ -47,6 +95,22 @@ where
         &self.1
     }
 
+    pub fn get_kind(&self) -> EntryKind {
+        self.2
+    }
+
+    pub fn get_sequence(&self) -> SequenceNumber {
+        self.3
+    }
+
+    pub fn set_sequence(&mut self, sequence: SequenceNumber) {
+        self.3 = sequence;
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        self.2 == EntryKind::Tombstone
+    }
+
     pub fn size(&self) -> usize {
         self.0.size() + self.1.size()
     }
@@ -56,7 +120,9 @@ where
         let v_bytes = self.get_value().size() as u32;
 
         assert_ne!(k_bytes, 0);
-        assert_ne!(v_bytes, 0);
+        if !self.is_tombstone() {
+            assert_ne!(v_bytes, 0);
+        }
 
         let mut offset = 0usize;
 
@@ -66,6 +132,16 @@ where
         // write size of value
         offset += write_u32(&mut buffer[offset..offset + size_of::<u32>()], v_bytes)?;
 
+        // write sequence number
+        offset += write_u64(
+            &mut buffer[offset..offset + size_of::<u64>()],
+            self.get_sequence(),
+        )?;
+
+        // write kind
+        buffer[offset] = self.get_kind() as u8;
+        offset += size_of::<u8>();
+
         // write key
         offset += write_data(
             &mut buffer[offset..offset + k_bytes as usize],
@@ -87,3 +163,15 @@ where
         Ok(offset as u64)
     }
 }
+
+impl<K: Ord + Eq, V: Eq> Ord for UserEntry<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0).then_with(|| other.3.cmp(&self.3))
+    }
+}
+
+impl<K: Ord + Eq, V: Eq> PartialOrd for UserEntry<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
@@ -29,3 +29,23 @@ pub fn write_data(dst: &mut [u8], src: &[u8], bytes: usize) -> Result<usize> {
 
     Ok(bytes)
 }
+
+pub fn write_u64(dst: &mut [u8], src: u64) -> Result<usize> {
+    let src = src.to_le_bytes();
+
+    unsafe {
+        ptr::copy_nonoverlapping(src.as_ptr(), dst.as_mut_ptr(), size_of::<u64>());
+    }
+
+    Ok(size_of::<u64>() as usize)
+}
+
+pub fn read_u64(src: &[u8]) -> Result<u64> {
+    let mut dst = [0u8; 8];
+
+    unsafe {
+        ptr::copy_nonoverlapping(src.as_ptr(), dst.as_mut_ptr(), size_of::<u64>());
+    }
+
+    Ok(u64::from_le_bytes(dst))
+}
@@ -1,13 +1,14 @@
-use std::{collections::BTreeSet, iter::IntoIterator};
+use std::{collections::BTreeSet, iter::IntoIterator, ops::Bound};
 
-use crate::core::entry::flexible_user_entry::FlexibleUserEntry;
+use crate::core::entry::{flexible_user_entry::FlexibleUserEntry, user_entry::SequenceNumber};
 
-use super::field::FlexibleField;
+use super::field::{Field, FlexibleField};
 
 pub struct MemoryTable {
     entries: BTreeSet<FlexibleUserEntry>,
     current_size: usize,
     max_table_size: usize,
+    last_sequence: SequenceNumber,
 }
 
 impl MemoryTable {
@@ -18,6 +19,7 @@ impl MemoryTable {
             entries,
             current_size: 0,
             max_table_size,
+            last_sequence: 0,
         }
     }
 
@@ -33,15 +35,39 @@ impl MemoryTable {
         self.current_size() >= self.max_table_size()
     }
 
+    // the greatest sequence number among entries
+    pub fn last_sequence(&self) -> SequenceNumber {
+        self.last_sequence
+    }
+
+    // older versions of key are kept, an entry with the same sequence number is replaced
     pub fn append(&mut self, entry: &FlexibleUserEntry) {
-        self.entries.insert(entry.clone());
-        self.current_size += 1;
+        if self.entries.replace(entry.clone()).is_none() {
+            self.current_size += 1;
+        }
+        self.last_sequence = self.last_sequence.max(entry.get_sequence());
     }
 
-    pub fn get_value(&self, key: &FlexibleField) -> Option<FlexibleField> {
+    // the newest version of key, returns tombstones as well
+    pub fn get_entry(&self, key: &FlexibleField) -> Option<&FlexibleUserEntry> {
+        self.get_entry_at(key, SequenceNumber::MAX)
+    }
+
+    // the newest version of key with sequence number <= `sequence`
+    pub fn get_entry_at(
+        &self,
+        key: &FlexibleField,
+        sequence: SequenceNumber,
+    ) -> Option<&FlexibleUserEntry> {
         self.entries
-            .iter()
-            .find(|entry| entry.get_key() == key)
+            .range(MemoryTable::lower_bound(key, sequence)..)
+            .next()
+            .filter(|entry| entry.get_key() == key)
+    }
+
+    pub fn get_value(&self, key: &FlexibleField) -> Option<FlexibleField> {
+        self.get_entry(key)
+            .filter(|entry| !entry.is_tombstone())
             .map(|entry| entry.get_value().clone())
     }
 
@@ -49,9 +75,33 @@ impl MemoryTable {
         self.entries.iter()
     }
 
+    // entries with keys in [start, end), unbounded if None
+    pub fn range<'a>(
+        &'a self,
+        start: Option<&FlexibleField>,
+        end: Option<&'a FlexibleField>,
+    ) -> impl Iterator<Item = &'a FlexibleUserEntry> + 'a {
+        let lower_bound = match start {
+            Some(key) => Bound::Included(MemoryTable::lower_bound(key, SequenceNumber::MAX)),
+            None => Bound::Unbounded,
+        };
+
+        self.entries
+            .range((lower_bound, Bound::Unbounded))
+            .take_while(move |entry| end.is_none_or(|end| entry.get_key() < end))
+    }
+
     pub fn clear(&mut self) {
         self.entries.clear();
         self.current_size = 0;
+        self.last_sequence = 0;
+    }
+
+    // the smallest possible entry with key and sequence number <= `sequence`
+    fn lower_bound(key: &FlexibleField, sequence: SequenceNumber) -> FlexibleUserEntry {
+        let mut entry = FlexibleUserEntry::new(key.clone(), FlexibleField::new(Vec::new()));
+        entry.set_sequence(sequence);
+        entry
     }
 }
 
@@ -132,4 +182,90 @@ mod tests {
             assert_eq!(actual, expected);
         }
     }
+
+    #[test]
+    fn check_range() {
+        let mut mem_table = mem_table::MemoryTable::new(8);
+
+        for index in 0..8u8 {
+            mem_table.append(&FlexibleUserEntry::new(
+                FlexibleField::new(vec![index]),
+                FlexibleField::new(vec![index, index]),
+            ));
+        }
+
+        let start = FlexibleField::new(vec![2]);
+        let end = FlexibleField::new(vec![5]);
+
+        let keys = mem_table
+            .range(Some(&start), Some(&end))
+            .map(|entry| entry.get_key().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                FlexibleField::new(vec![2]),
+                FlexibleField::new(vec![3]),
+                FlexibleField::new(vec![4])
+            ]
+        );
+
+        assert_eq!(mem_table.range(None, Some(&start)).count(), 2);
+        assert_eq!(mem_table.range(Some(&end), None).count(), 3);
+        assert_eq!(mem_table.range(None, None).count(), 8);
+    }
+
+    #[test]
+    fn check_tombstone() {
+        let mut mem_table = mem_table::MemoryTable::new(3);
+
+        let key = FlexibleField::new(vec![1, 2, 3]);
+
+        let mut entry = FlexibleUserEntry::new(key.clone(), FlexibleField::new(vec![10, 20, 30]));
+        entry.set_sequence(1);
+        mem_table.append(&entry);
+
+        let mut entry = FlexibleUserEntry::new_tombstone(key.clone());
+        entry.set_sequence(2);
+        mem_table.append(&entry);
+
+        assert_eq!(mem_table.get_value(&key), None);
+        assert!(mem_table.get_entry(&key).unwrap().is_tombstone());
+        assert_eq!(mem_table.current_size(), 2);
+
+        let mut entry = FlexibleUserEntry::new(key.clone(), FlexibleField::new(vec![40]));
+        entry.set_sequence(3);
+        mem_table.append(&entry);
+
+        assert_eq!(
+            mem_table.get_value(&key),
+            Some(FlexibleField::new(vec![40]))
+        );
+        assert_eq!(mem_table.current_size(), 3);
+        assert_eq!(mem_table.last_sequence(), 3);
+    }
+
+    #[test]
+    fn check_last_write_wins() {
+        let mut mem_table = mem_table::MemoryTable::new(8);
+
+        let key = FlexibleField::new(vec![1]);
+
+        // appended out of order
+        for sequence in [2u64, 5, 1, 4, 3] {
+            let mut entry =
+                FlexibleUserEntry::new(key.clone(), FlexibleField::new(vec![sequence as u8]));
+            entry.set_sequence(sequence);
+            mem_table.append(&entry);
+        }
+
+        assert_eq!(mem_table.get_value(&key), Some(FlexibleField::new(vec![5])));
+        assert_eq!(mem_table.current_size(), 5);
+
+        let sequences = mem_table
+            .iter()
+            .map(|entry| entry.get_sequence())
+            .collect::<Vec<_>>();
+        assert_eq!(sequences, vec![5, 4, 3, 2, 1]);
+    }
 }
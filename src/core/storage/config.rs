@@ -1,16 +1,54 @@
+use crate::core::disk_table::{
+    compaction::CompactionStyle, local::block::compression::Compression,
+};
+
 pub const DEFAULT_TABLES_PATH: &'static str = "/tmp/kvs/tables/";
 pub const DEFAULT_TEST_TABLES_PATH: &'static str = "/tmp/";
 pub const DETAULT_MEM_TABLE_SIZE: usize = 4;
 pub const DEFAULT_DISK_TABLES_LIMIT_BY_LEVEL: usize = 4;
 
+// decoded size of data blocks, every table keeps the size of its blocks
 pub const DEFAULT_DATA_BLOCK_SIZE: usize = 4 * (1 << 10);
+// alignment of O_DIRECT reads and writes
 pub const DEFAULT_DATA_BLOCK_ALIGN: usize = 4 * (1 << 10);
+// 0 disables bloom filters
+pub const DEFAULT_BLOOM_BITS_PER_KEY: usize = 10;
+// bytes of data blocks in block cache, 0 disables the cache
+pub const DEFAULT_BLOCK_CACHE_SIZE: usize = 8 * (1 << 20);
+// codec of new data blocks, tables can have blocks of any codec
+pub const DEFAULT_COMPRESSION: Compression = Compression::Lz4;
+// values of this size and larger are kept in the value log, 0 keeps all values in disk tables
+pub const DEFAULT_VALUE_THRESHOLD: usize = 0;
+// a value log file is sealed after this size, only sealed files are garbage collected
+pub const DEFAULT_VALUE_LOG_FILE_SIZE: usize = 64 * (1 << 20);
+// merged tables are split when their data blocks reach this size
+pub const DEFAULT_TARGET_TABLE_SIZE: usize = 2 * (1 << 20);
+// bytes of data blocks in the second level, the first one is limited by count of tables
+pub const DEFAULT_LEVEL_BASE_SIZE: usize = 10 * (1 << 20);
+// every next level holds this times more bytes, the last level is unbounded
+pub const DEFAULT_LEVEL_SIZE_MULTIPLIER: usize = 10;
+// policy of merges of disk tables
+pub const DEFAULT_COMPACTION_STYLE: CompactionStyle = CompactionStyle::Leveled;
+// FIFO compaction deletes the oldest tables when all tables are larger
+pub const DEFAULT_FIFO_MAX_SIZE: usize = 1 << 30;
+// threads which run non-conflicting compactions in parallel, flushes have their own thread
+pub const DEFAULT_COMPACTION_THREADS: usize = 2;
 
 #[derive(Clone)]
 pub struct StorageConfig {
     pub mem_table_size: usize,
     pub disk_tables_limit_by_level: usize,
     pub data_block_size: usize,
+    pub bloom_bits_per_key: usize,
+    pub block_cache_size: usize,
+    pub compression: Compression,
+    pub value_threshold: usize,
+    pub value_log_file_size: usize,
+    pub target_table_size: usize,
+    pub level_base_size: usize,
+    pub compaction_style: CompactionStyle,
+    pub fifo_max_size: usize,
+    pub compaction_threads: usize,
 }
 
 impl StorageConfig {
@@ -23,14 +61,41 @@ impl StorageConfig {
             mem_table_size,
             disk_tables_limit_by_level,
             data_block_size,
+            bloom_bits_per_key: DEFAULT_BLOOM_BITS_PER_KEY,
+            block_cache_size: DEFAULT_BLOCK_CACHE_SIZE,
+            compression: DEFAULT_COMPRESSION,
+            value_threshold: DEFAULT_VALUE_THRESHOLD,
+            value_log_file_size: DEFAULT_VALUE_LOG_FILE_SIZE,
+            target_table_size: DEFAULT_TARGET_TABLE_SIZE,
+            level_base_size: DEFAULT_LEVEL_BASE_SIZE,
+            compaction_style: DEFAULT_COMPACTION_STYLE,
+            fifo_max_size: DEFAULT_FIFO_MAX_SIZE,
+            compaction_threads: DEFAULT_COMPACTION_THREADS,
         }
     }
 
+    // data_block_size rounded up to a multiple of the alignment
+    pub fn aligned_data_block_size(&self) -> usize {
+        self.data_block_size
+            .max(1)
+            .next_multiple_of(DEFAULT_DATA_BLOCK_ALIGN)
+    }
+
     pub fn default_config() -> Self {
         StorageConfig {
             mem_table_size: DETAULT_MEM_TABLE_SIZE,
             disk_tables_limit_by_level: DEFAULT_DISK_TABLES_LIMIT_BY_LEVEL,
             data_block_size: DEFAULT_DATA_BLOCK_SIZE,
+            bloom_bits_per_key: DEFAULT_BLOOM_BITS_PER_KEY,
+            block_cache_size: DEFAULT_BLOCK_CACHE_SIZE,
+            compression: DEFAULT_COMPRESSION,
+            value_threshold: DEFAULT_VALUE_THRESHOLD,
+            value_log_file_size: DEFAULT_VALUE_LOG_FILE_SIZE,
+            target_table_size: DEFAULT_TARGET_TABLE_SIZE,
+            level_base_size: DEFAULT_LEVEL_BASE_SIZE,
+            compaction_style: DEFAULT_COMPACTION_STYLE,
+            fifo_max_size: DEFAULT_FIFO_MAX_SIZE,
+            compaction_threads: DEFAULT_COMPACTION_THREADS,
         }
     }
 }
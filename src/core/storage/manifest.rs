@@ -0,0 +1,458 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::core::disk_table::disk_tables_shard::Levels;
+use crate::core::disk_table::local::block::checksum::{checksum, CHECKSUM_SIZE};
+use crate::core::entry::user_entry::SequenceNumber;
+use crate::core::marshal::{read_u32, read_u64, write_u32, write_u64};
+use crate::errdata;
+use crate::errors::{Error, Result};
+
+const MANIFEST_NAME: &str = "MANIFEST";
+const MANIFEST_TMP_NAME: &str = "MANIFEST.tmp";
+const RECORD_HEADER_SIZE: usize = size_of::<u32>();
+
+// Changes of the set of disk tables made by one flush or merge.
+// An edit is logged as one record, so it is applied on replay fully or not at all.
+//
+// Record:
+// [ next_table_id last_sequence count_added added ... count_removed removed ... checksum ]
+//
+// Added table:
+// [ level name_size name ]
+//
+// Removed table:
+// [ name_size name ]
+#[derive(Debug, PartialEq)]
+pub struct VersionEdit {
+    next_table_id: u64,
+    last_sequence: SequenceNumber,
+    added: Vec<(Levels, String)>,
+    removed: Vec<String>,
+}
+
+impl VersionEdit {
+    pub fn new(next_table_id: u64, last_sequence: SequenceNumber) -> Self {
+        Self {
+            next_table_id,
+            last_sequence,
+            added: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+
+    pub fn add_table(&mut self, level: Levels, disk_table_name: &str) -> &mut Self {
+        self.added.push((level, disk_table_name.to_string()));
+        self
+    }
+
+    pub fn remove_table(&mut self, disk_table_name: &str) -> &mut Self {
+        self.removed.push(disk_table_name.to_string());
+        self
+    }
+
+    fn size(&self) -> usize {
+        2 * size_of::<u64>()
+            + size_of::<u32>()
+            + self
+                .added
+                .iter()
+                .map(|(_level, name)| size_of::<Levels>() + size_of::<u32>() + name.len())
+                .sum::<usize>()
+            + size_of::<u32>()
+            + self
+                .removed
+                .iter()
+                .map(|name| size_of::<u32>() + name.len())
+                .sum::<usize>()
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        let record_size = self.size() + CHECKSUM_SIZE;
+        let mut buffer = vec![0u8; RECORD_HEADER_SIZE + record_size];
+
+        let mut offset = write_u32(&mut buffer, record_size as u32)?;
+        offset += write_u64(&mut buffer[offset..], self.next_table_id)?;
+        offset += write_u64(&mut buffer[offset..], self.last_sequence)?;
+
+        offset += write_u32(&mut buffer[offset..], self.added.len() as u32)?;
+        for (level, name) in &self.added {
+            buffer[offset] = *level;
+            offset += size_of::<Levels>();
+            offset += VersionEdit::write_name(&mut buffer[offset..], name)?;
+        }
+
+        offset += write_u32(&mut buffer[offset..], self.removed.len() as u32)?;
+        for name in &self.removed {
+            offset += VersionEdit::write_name(&mut buffer[offset..], name)?;
+        }
+
+        let crc = checksum(&buffer[..offset]);
+        offset += write_u32(&mut buffer[offset..], crc)?;
+
+        assert_eq!(offset, buffer.len());
+
+        Ok(buffer)
+    }
+
+    // record without checksum, sizes and counts are checked against its size
+    fn from(record: &[u8]) -> Result<Self> {
+        let mut reader = RecordReader { record, offset: 0 };
+
+        let next_table_id = read_u64(reader.take(size_of::<u64>())?)?;
+        let last_sequence = read_u64(reader.take(size_of::<u64>())?)?;
+
+        let mut edit = VersionEdit::new(next_table_id, last_sequence);
+
+        let count_added = read_u32(reader.take(size_of::<u32>())?)?;
+        for _ in 0..count_added {
+            let level = reader.take(size_of::<Levels>())?[0];
+            let name = reader.read_name()?;
+            edit.add_table(level, &name);
+        }
+
+        let count_removed = read_u32(reader.take(size_of::<u32>())?)?;
+        for _ in 0..count_removed {
+            let name = reader.read_name()?;
+            edit.remove_table(&name);
+        }
+
+        if reader.offset != record.len() {
+            return errdata!("unexpected bytes after edit in manifest");
+        }
+
+        Ok(edit)
+    }
+
+    fn write_name(dst: &mut [u8], name: &str) -> Result<usize> {
+        let offset = write_u32(dst, name.len() as u32)?;
+        dst[offset..offset + name.len()].copy_from_slice(name.as_bytes());
+        Ok(offset + name.len())
+    }
+}
+
+// Reads fields of a record one by one, a field can't pass its end.
+struct RecordReader<'a> {
+    record: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> RecordReader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8]> {
+        let Some(field) = self
+            .offset
+            .checked_add(size)
+            .and_then(|end| self.record.get(self.offset..end))
+        else {
+            return errdata!("edit is out of record in manifest");
+        };
+        self.offset += size;
+        Ok(field)
+    }
+
+    fn read_name(&mut self) -> Result<String> {
+        let name_size = read_u32(self.take(size_of::<u32>())?)? as usize;
+        let Ok(name) = String::from_utf8(self.take(name_size)?.to_vec()) else {
+            return errdata!("invalid disk table name in manifest");
+        };
+        Ok(name)
+    }
+}
+
+// Disk tables of storage after all logged edits.
+#[derive(Debug, Default, PartialEq)]
+pub struct Version {
+    // disk table name -> level
+    tables: BTreeMap<String, Levels>,
+    next_table_id: u64,
+    last_sequence: SequenceNumber,
+}
+
+impl Version {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&mut self, edit: &VersionEdit) {
+        for name in &edit.removed {
+            self.tables.remove(name);
+        }
+        for (level, name) in &edit.added {
+            self.tables.insert(name.clone(), *level);
+        }
+        self.next_table_id = self.next_table_id.max(edit.next_table_id);
+        self.last_sequence = self.last_sequence.max(edit.last_sequence);
+    }
+
+    // disk table names with their levels
+    pub fn tables(&self) -> impl Iterator<Item = (&str, Levels)> {
+        self.tables
+            .iter()
+            .map(|(name, level)| (name.as_str(), *level))
+    }
+
+    pub fn contains(&self, disk_table_name: &str) -> bool {
+        self.tables.contains_key(disk_table_name)
+    }
+
+    pub fn next_table_id(&self) -> u64 {
+        self.next_table_id
+    }
+
+    pub fn last_sequence(&self) -> SequenceNumber {
+        self.last_sequence
+    }
+
+    // the edit which creates this version from empty one
+    fn snapshot_edit(&self) -> VersionEdit {
+        let mut edit = VersionEdit::new(self.next_table_id, self.last_sequence);
+        for (name, level) in self.tables() {
+            edit.add_table(level, name);
+        }
+        edit
+    }
+}
+
+// Manifest file:
+// [ record_size record ... record_size record ]
+//
+// Checksum of record is CRC32C of all previous bytes with record_size.
+// Replay stops at a truncated or broken record, it's the tail of an interrupted append.
+// Edits are appended and synced before the disk tables become visible or removed,
+// so the manifest always describes a consistent set of tables. Files which aren't
+// in the manifest are leftovers of interrupted flushes and merges.
+pub struct Manifest {
+    log: File,
+}
+
+impl Manifest {
+    pub fn make_path<P: AsRef<Path>>(storage_path: P) -> PathBuf {
+        storage_path.as_ref().join(MANIFEST_NAME)
+    }
+
+    pub fn exists<P: AsRef<Path>>(storage_path: P) -> bool {
+        Manifest::make_path(storage_path).exists()
+    }
+
+    /// Applies all edits of the manifest to an empty version.
+    /// Edits from a truncated or broken record are skipped,
+    /// an edit of a whole record which can't be decoded is corruption.
+    pub fn replay<P: AsRef<Path>>(storage_path: P) -> Result<Version> {
+        let path = Manifest::make_path(storage_path);
+
+        let mut data = Vec::new();
+        File::open(path.as_path())?.read_to_end(&mut data)?;
+
+        let mut version = Version::new();
+        let mut offset = 0;
+
+        while offset < data.len() {
+            if offset + RECORD_HEADER_SIZE > data.len() {
+                warn!("truncated record header in {}", path.display());
+                break;
+            }
+
+            let record_size = read_u32(&data[offset..])? as usize;
+            let record_start = offset;
+            offset += RECORD_HEADER_SIZE;
+
+            if offset + record_size > data.len() {
+                warn!("truncated record in {}", path.display());
+                break;
+            }
+
+            // a zero-filled or garbage tail isn't a record
+            let checksum_offset = offset + record_size;
+            if record_size < CHECKSUM_SIZE
+                || read_u32(&data[checksum_offset - CHECKSUM_SIZE..])?
+                    != checksum(&data[record_start..checksum_offset - CHECKSUM_SIZE])
+            {
+                warn!("broken record in {}", path.display());
+                break;
+            }
+
+            let record = &data[offset..checksum_offset - CHECKSUM_SIZE];
+            let Ok(edit) = VersionEdit::from(record) else {
+                return Err(Error::Corruption {
+                    path,
+                    offset: record_start as u64,
+                });
+            };
+            version.apply(&edit);
+            offset += record_size;
+        }
+
+        Ok(version)
+    }
+
+    /// Starts a new manifest with the single edit of `version`,
+    /// the old one is replaced atomically.
+    pub fn create<P: AsRef<Path>>(storage_path: P, version: &Version) -> Result<Self> {
+        let tmp_path = storage_path.as_ref().join(MANIFEST_TMP_NAME);
+        let path = Manifest::make_path(storage_path.as_ref());
+
+        {
+            let mut tmp = File::create(tmp_path.as_path())?;
+            tmp.write_all(&version.snapshot_edit().serialize()?)?;
+            tmp.sync_all()?;
+        }
+
+        fs::rename(tmp_path.as_path(), path.as_path())?;
+        File::open(storage_path.as_ref())?.sync_all()?;
+
+        let log = OpenOptions::new().append(true).open(path.as_path())?;
+
+        Ok(Self { log })
+    }
+
+    pub fn log_edit(&mut self, edit: &VersionEdit) -> Result<()> {
+        self.log.write_all(&edit.serialize()?)?;
+        self.log.sync_all()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::core::storage::config::DEFAULT_TEST_TABLES_PATH;
+
+    #[test]
+    fn test_replay_edits() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        {
+            let mut manifest = Manifest::create(tmp_dir.path(), &Version::new()).unwrap();
+
+            let mut edit = VersionEdit::new(3, 10);
+            edit.add_table(1, "segment_0000001_1.bin")
+                .add_table(1, "segment_0000002_1.bin");
+            manifest.log_edit(&edit).unwrap();
+
+            let mut edit = VersionEdit::new(4, 10);
+            edit.remove_table("segment_0000001_1.bin")
+                .remove_table("segment_0000002_1.bin")
+                .add_table(2, "segment_0000003_2.bin");
+            manifest.log_edit(&edit).unwrap();
+        }
+
+        let version = Manifest::replay(tmp_dir.path()).unwrap();
+
+        assert_eq!(
+            version.tables().collect::<Vec<_>>(),
+            vec![("segment_0000003_2.bin", 2)]
+        );
+        assert_eq!(version.next_table_id(), 4);
+        assert_eq!(version.last_sequence(), 10);
+
+        // the new manifest keeps the same version
+        let _manifest = Manifest::create(tmp_dir.path(), &version).unwrap();
+        assert_eq!(Manifest::replay(tmp_dir.path()).unwrap(), version);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_edit() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        {
+            let mut manifest = Manifest::create(tmp_dir.path(), &Version::new()).unwrap();
+
+            let mut edit = VersionEdit::new(2, 4);
+            edit.add_table(1, "segment_0000001_1.bin");
+            manifest.log_edit(&edit).unwrap();
+
+            let mut edit = VersionEdit::new(3, 4);
+            edit.remove_table("segment_0000001_1.bin")
+                .add_table(2, "segment_0000002_2.bin");
+            manifest.log_edit(&edit).unwrap();
+
+            // crash in the middle of the last edit
+            let log = OpenOptions::new()
+                .write(true)
+                .open(Manifest::make_path(tmp_dir.path()))?;
+            let size = log.metadata()?.len();
+            log.set_len(size - 4)?;
+        }
+
+        let version = Manifest::replay(tmp_dir.path()).unwrap();
+
+        assert_eq!(
+            version.tables().collect::<Vec<_>>(),
+            vec![("segment_0000001_1.bin", 1)]
+        );
+        assert_eq!(version.next_table_id(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zero_filled_tail() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        {
+            let mut manifest = Manifest::create(tmp_dir.path(), &Version::new()).unwrap();
+
+            let mut edit = VersionEdit::new(2, 4);
+            edit.add_table(1, "segment_0000001_1.bin");
+            manifest.log_edit(&edit).unwrap();
+
+            // a crash after the size of file is changed, but before data is written
+            let mut log = OpenOptions::new()
+                .append(true)
+                .open(Manifest::make_path(tmp_dir.path()))?;
+            log.write_all(&[0u8; 64])?;
+        }
+
+        let version = Manifest::replay(tmp_dir.path()).unwrap();
+
+        assert_eq!(
+            version.tables().collect::<Vec<_>>(),
+            vec![("segment_0000001_1.bin", 1)]
+        );
+        assert_eq!(version.next_table_id(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broken_edit() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        {
+            let _manifest = Manifest::create(tmp_dir.path(), &Version::new()).unwrap();
+
+            // the record is whole, but the name of the added table is out of it
+            let mut edit = vec![0u8; 2 * size_of::<u64>()];
+            edit.extend(1u32.to_le_bytes());
+            edit.push(1);
+            edit.extend(1000u32.to_le_bytes());
+
+            let mut record = ((edit.len() + CHECKSUM_SIZE) as u32).to_le_bytes().to_vec();
+            record.extend(edit);
+            record.extend(checksum(&record).to_le_bytes());
+
+            let mut log = OpenOptions::new()
+                .append(true)
+                .open(Manifest::make_path(tmp_dir.path()))?;
+            log.write_all(&record)?;
+        }
+
+        assert!(matches!(
+            Manifest::replay(tmp_dir.path()),
+            Err(Error::Corruption { .. })
+        ));
+
+        Ok(())
+    }
+}
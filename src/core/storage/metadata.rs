@@ -4,9 +4,16 @@ use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
 use crate::core::disk_table::id::DiskTableID;
+use crate::core::entry::user_entry::SequenceNumber;
 
+// Metadata file:
+// [ segment_id last_sequence ]
+//
+// Storages before sequence numbers have only segment_id, last_sequence is 0 for them.
 pub struct StorageMetadata {
     segment_id: DiskTableID,
+    // the greatest sequence number in disk tables
+    last_sequence: SequenceNumber,
     metadata_path: PathBuf,
 }
 
@@ -14,6 +21,7 @@ impl StorageMetadata {
     pub fn new(table_path: &Path) -> Self {
         StorageMetadata {
             segment_id: DiskTableID::new(),
+            last_sequence: 0,
             metadata_path: StorageMetadata::make_path(table_path),
         }
     }
@@ -21,6 +29,7 @@ impl StorageMetadata {
     pub fn from_file<P: AsRef<Path> + Copy>(metadata_path: P) -> Self {
         let mut metadata = StorageMetadata {
             segment_id: DiskTableID::new(),
+            last_sequence: 0,
             metadata_path: metadata_path.as_ref().to_path_buf(),
         };
 
@@ -31,9 +40,24 @@ impl StorageMetadata {
                         Ok(mut fd) => {
                             let mut data = String::new();
                             let _r = fd.read_to_string(&mut data);
-                            match data.parse::<u64>() {
-                                Ok(id) => metadata.segment_id = DiskTableID::from(id),
-                                Err(_) => {
+
+                            // metadata is written only after the first flush
+                            if data.is_empty() {
+                                return metadata;
+                            }
+
+                            let mut values = data.split_whitespace().map(|v| v.parse::<u64>());
+
+                            match (values.next(), values.next()) {
+                                (Some(Ok(id)), Some(Ok(last_sequence))) => {
+                                    metadata.segment_id = DiskTableID::from(id);
+                                    metadata.last_sequence = last_sequence;
+                                }
+                                // storages before sequence numbers keep only id
+                                (Some(Ok(id)), None) => {
+                                    metadata.segment_id = DiskTableID::from(id);
+                                }
+                                _ => {
                                     panic!(
                                         "broken metadata: {}, path={}",
                                         data,
@@ -82,17 +106,38 @@ impl StorageMetadata {
         self.segment_id.get_and_next()
     }
 
+    // id of the next disk table
+    pub fn next_disk_table_id(&self) -> u64 {
+        self.segment_id.get_id()
+    }
+
+    // manifest can be ahead of metadata after crash
+    pub fn recover(&mut self, next_disk_table_id: u64, last_sequence: SequenceNumber) {
+        if next_disk_table_id > self.segment_id.get_id() {
+            self.segment_id = DiskTableID::from(next_disk_table_id);
+        }
+        self.update_last_sequence(last_sequence);
+    }
+
+    pub fn last_sequence(&self) -> SequenceNumber {
+        self.last_sequence
+    }
+
+    pub fn update_last_sequence(&mut self, sequence: SequenceNumber) {
+        self.last_sequence = self.last_sequence.max(sequence);
+    }
+
     pub fn sync_disk(&self) {
         let mut options: OpenOptions = OpenOptions::new();
-        options.write(true).create(true);
+        options.write(true).create(true).truncate(true);
 
         match options.open(self.get_metadata_path()) {
             Ok(mut fd) => {
-                let segment_id = self.segment_id.get_id().to_string();
-                if let Err(er) = fd.write_all(segment_id.as_bytes()) {
+                let data = format!("{} {}", self.segment_id.get_id(), self.last_sequence);
+                if let Err(er) = fd.write_all(data.as_bytes()) {
                     panic!(
-                        "Failed to write segment_id to table metadata. segment_id={}, metadata_path={}, error= {}",
-                        segment_id,
+                        "Failed to write table metadata. data={}, metadata_path={}, error= {}",
+                        data,
                         self.get_metadata_path().display(),
                         er
                     );
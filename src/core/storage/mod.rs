@@ -1,4 +1,10 @@
 pub mod config;
+pub mod manifest;
 pub mod metadata;
 pub mod ordered_storage;
+pub mod snapshot;
 pub mod storage;
+pub mod storage_iterator;
+pub mod value_log;
+pub mod wal;
+pub mod write_batch;
@@ -1,8 +1,9 @@
 use std::{
     fs::create_dir_all,
+    ops::Range,
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc, Mutex, RwLock,
     },
     thread,
@@ -13,20 +14,31 @@ use log::{debug, error, info, trace};
 use crate::{
     core::{
         disk_table::{
+            compaction::{Compaction, CompactionSummary},
             disk_table::{get_disk_table_name, get_disk_table_name_by_level, get_disk_table_path},
             disk_tables_shard::{self, DiskTablesShards},
             local::{
-                disk_table_builder::DiskTableBuilder, reader_local_disk_table::ReaderDiskTablePtr,
+                block::bloom_filter::BloomFilterStats, disk_table_builder::DiskTableBuilder,
+                reader_local_disk_table::ReaderDiskTablePtr,
             },
             utils,
         },
-        entry::flexible_user_entry::FlexibleUserEntry,
-        field::FlexibleField,
+        entry::{
+            flexible_user_entry::FlexibleUserEntry,
+            user_entry::{EntryKind, SequenceNumber},
+        },
+        field::{Field, FlexibleField},
         mem_table::MemoryTable,
         storage::{
             config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+            manifest::{Manifest, Version, VersionEdit},
             metadata::StorageMetadata,
+            snapshot::{Snapshot, VersionFilter},
             storage::Storage,
+            storage_iterator::StorageIterator,
+            value_log::{ValueLogFiles, ValuePointer},
+            wal::WriteAheadLog,
+            write_batch::WriteBatch,
         },
     },
     errors::Error,
@@ -36,6 +48,7 @@ fn create_dirs(storage_path: &Path) -> Result<(), Error> {
     create_dir_all(Path::new(storage_path))?;
     let segment_dir = format!("{}/segment", storage_path.to_str().unwrap());
     create_dir_all(Path::new(&segment_dir))?;
+    create_dir_all(WriteAheadLog::make_path(storage_path))?;
 
     Ok(())
 }
@@ -44,11 +57,23 @@ pub struct OrderedStorage {
     // @todo
     storage_path: PathBuf,
     m_mem_table: Arc<RwLock<MemoryTable>>,
-    // i_mem_table: Option<Box<MemoryTable>>,
+    // the previous memory table while it is written to disk table
+    i_mem_table: Arc<RwLock<Option<Arc<MemoryTable>>>>,
+    wal: Arc<Mutex<WriteAheadLog>>,
+    // the last assigned sequence number, changed under the memory table lock
+    last_sequence: AtomicU64,
     need_flush: Arc<AtomicBool>,
+    // flushes of the worker and manual ones go one by one
+    flush_lock: Arc<Mutex<()>>,
     flush_worker: Option<thread::JoinHandle<()>>,
     shutdown: Arc<AtomicBool>,
+    // flushes request compactions, they run on own workers, so merges don't delay flushes
+    compaction_workers: Vec<thread::JoinHandle<()>>,
+    // compaction workers finish requested compactions and stop after the flush worker
+    stop_compaction: Arc<AtomicBool>,
     metadata: Arc<Mutex<StorageMetadata>>,
+    // the set of disk tables, it's changed by flushes and merges
+    manifest: Arc<Mutex<Manifest>>,
     shards: Arc<DiskTablesShards>,
     config: StorageConfig,
 }
@@ -63,31 +88,80 @@ impl OrderedStorage {
             )
         }
 
-        let Ok(shards) = utils::get_disk_tables(storage_path.as_ref()) else {
-            panic!("Faield read disk tables")
+        let mut metadata =
+            StorageMetadata::from_file(StorageMetadata::make_path(&storage_path).as_path());
+
+        let (manifest, shards) = match Self::open_disk_tables(storage_path.as_ref(), &config) {
+            Ok((manifest, version, shards)) => {
+                metadata.recover(version.next_table_id(), version.last_sequence());
+                (Arc::new(Mutex::new(manifest)), shards)
+            }
+            Err(er) => panic!(
+                "Faield read disk tables: table_path={}, error={}",
+                storage_path.as_ref().display(),
+                er
+            ),
+        };
+        let metadata = Arc::new(Mutex::new(metadata));
+
+        let mut mem_table = MemoryTable::new(config.mem_table_size);
+        let wal = match WriteAheadLog::open(storage_path.as_ref(), &mut mem_table) {
+            Ok(wal) => Arc::new(Mutex::new(wal)),
+            Err(er) => panic!(
+                "Failed replay write-ahead log: table_path={}, error={}",
+                storage_path.as_ref().display(),
+                er
+            ),
         };
 
-        let metadata = Arc::new(Mutex::new(StorageMetadata::from_file(
-            StorageMetadata::make_path(&storage_path).as_path(),
-        )));
+        // entries of the write-ahead log can be newer than disk tables
+        let last_sequence: SequenceNumber = metadata
+            .lock()
+            .unwrap()
+            .last_sequence()
+            .max(mem_table.last_sequence());
 
-        let m_mem_table = Arc::new(RwLock::new(MemoryTable::new(config.mem_table_size)));
-        let need_flush = Arc::new(AtomicBool::new(false));
+        let need_flush = Arc::new(AtomicBool::new(mem_table.need_flush()));
+        let m_mem_table = Arc::new(RwLock::new(mem_table));
+        let i_mem_table = Arc::new(RwLock::new(None));
         let shutdown = Arc::new(AtomicBool::new(false));
+        let flush_lock = Arc::new(Mutex::new(()));
 
         let shards = Arc::new(shards);
 
         let storage_path = storage_path.as_ref().to_path_buf();
 
+        // tables of the previous run can be out of shape
+        let need_compaction = Arc::new(AtomicBool::new(true));
+        let stop_compaction = Arc::new(AtomicBool::new(false));
+        let compaction_workers = (0..config.compaction_threads.max(1))
+            .map(|_| {
+                Self::spawn_compaction_worker(
+                    shards.clone(),
+                    metadata.clone(),
+                    manifest.clone(),
+                    storage_path.clone(),
+                    need_compaction.clone(),
+                    stop_compaction.clone(),
+                )
+            })
+            .collect();
+
         Self {
             m_mem_table: m_mem_table.clone(),
-            // i_mem_table: None,
+            i_mem_table: i_mem_table.clone(),
+            wal: wal.clone(),
+            last_sequence: AtomicU64::new(last_sequence),
             need_flush: need_flush.clone(),
+            flush_lock: flush_lock.clone(),
             shutdown: shutdown.clone(),
             storage_path: storage_path.clone(),
             metadata: metadata.clone(),
+            manifest: manifest.clone(),
             shards: shards.clone(),
             config,
+            compaction_workers,
+            stop_compaction,
 
             flush_worker: Some(thread::spawn(move || loop {
                 let mut tables = shards.clone();
@@ -96,32 +170,40 @@ impl OrderedStorage {
                     thread::sleep(std::time::Duration::from_millis(200));
                 }
 
+                let _flush_lock = flush_lock.lock().unwrap();
+
                 if shutdown.load(Ordering::SeqCst) && !need_flush.load(Ordering::SeqCst) {
                     info!("call last flush");
 
                     Self::save_mem_table(
                         m_mem_table.clone(),
+                        i_mem_table.clone(),
+                        wal.clone(),
                         metadata.clone(),
+                        manifest.clone(),
                         storage_path.clone(),
                         &mut tables,
                     );
 
-                    // Self::merge_disk_tables(shards.clone(), metadata.clone(), storage_path.clone());
                     return;
                 }
 
                 trace!("call flush");
 
+                // writes into the new memory table can request the next flush
+                need_flush.store(false, Ordering::SeqCst);
+
                 Self::save_mem_table(
                     m_mem_table.clone(),
+                    i_mem_table.clone(),
+                    wal.clone(),
                     metadata.clone(),
+                    manifest.clone(),
                     storage_path.clone(),
                     &mut tables,
                 );
 
-                Self::merge_disk_tables(shards.clone(), metadata.clone(), storage_path.clone());
-
-                need_flush.store(false, Ordering::SeqCst);
+                need_compaction.store(true, Ordering::SeqCst);
             })),
         }
     }
@@ -132,122 +214,553 @@ impl OrderedStorage {
         ))
     }
 
+    // disk tables from manifest, files which aren't in manifest are removed
+    fn open_disk_tables(
+        storage_path: &Path,
+        config: &StorageConfig,
+    ) -> Result<(Manifest, Version, DiskTablesShards), Error> {
+        let version = if Manifest::exists(storage_path) {
+            Manifest::replay(storage_path)?
+        } else {
+            // storage before manifest: all disk tables are live
+            let mut edit = VersionEdit::new(0, 0);
+            for (level, disk_table_name) in utils::list_disk_tables(storage_path)? {
+                edit.add_table(level, &disk_table_name);
+            }
+
+            let mut version = Version::new();
+            version.apply(&edit);
+            version
+        };
+
+        // the manifest is rewritten on every start, so it doesn't grow forever
+        let manifest = Manifest::create(storage_path, &version)?;
+        utils::remove_unreferenced_disk_tables(storage_path, &version)?;
+
+        let shards = utils::get_disk_tables(storage_path, config, &version)?;
+
+        Ok((manifest, version, shards))
+    }
+
     fn save_mem_table(
         mem_table: Arc<RwLock<MemoryTable>>,
+        i_mem_table: Arc<RwLock<Option<Arc<MemoryTable>>>>,
+        wal: Arc<Mutex<WriteAheadLog>>,
         metadata: Arc<Mutex<StorageMetadata>>,
+        manifest: Arc<Mutex<Manifest>>,
         storage_path: PathBuf,
         shards: &mut Arc<DiskTablesShards>,
     ) {
-        let mut lock = mem_table.write().unwrap();
+        // writers continue into a new memory table while the old one is written
+        let flushing = {
+            let mut lock = mem_table.write().unwrap();
 
-        trace!("call save_mem_table, size={}", lock.current_size());
+            trace!("call save_mem_table, size={}", lock.current_size());
 
-        if lock.current_size() == 0 {
-            return;
-        }
+            if lock.current_size() == 0 {
+                return;
+            }
+
+            let max_table_size = lock.max_table_size();
+            let flushing = Arc::new(std::mem::replace(
+                &mut *lock,
+                MemoryTable::new(max_table_size),
+            ));
+            *i_mem_table.write().unwrap() = Some(flushing.clone());
+
+            // new writes go to a new log, the sealed ones keep entries of the flushing table
+            if let Err(er) = wal.lock().unwrap().rotate() {
+                panic!("Failed rotate write-ahead log. {}", er)
+            }
+
+            flushing
+        };
 
         let disk_table_id = metadata.lock().unwrap().get_new_disk_table_id();
         let (disk_table_name, index_table_name) = get_disk_table_name(disk_table_id);
         let (disk_table_path, index_table_path) =
             get_disk_table_path(storage_path.as_path(), &disk_table_name, &index_table_name);
 
-        let disk_table_from_mem_table = lock
-            .into_iter()
-            .fold(
-                DiskTableBuilder::new(disk_table_path.as_path(), index_table_path.as_path()),
-                |mut builder, entry| {
-                    builder.append_entry(entry);
-                    builder
-                },
-            )
-            .build();
+        // older versions of key are flushed only for live snapshots
+        let mut filter = VersionFilter::new(shards.snapshots().sequences(), false);
+        let mut builder =
+            shards.new_disk_table_builder(disk_table_path.as_path(), index_table_path.as_path());
+        for entry in flushing.iter() {
+            if filter.keep(entry) {
+                match shards.separate_value(entry) {
+                    Ok(Some(pointer_entry)) => builder.append_entry(&pointer_entry),
+                    Ok(None) => builder.append_entry(entry),
+                    Err(er) => panic!("Failed write value to value log. {}", er),
+                };
+            }
+        }
+        let disk_table_from_mem_table = builder.build();
+
+        // values must be durable before the disk table which points to them
+        if let Some(value_log) = shards.value_log() {
+            if let Err(er) = value_log.sync() {
+                panic!("Failed sync value log. {}", er)
+            }
+        }
 
-        match disk_table_from_mem_table {
-            Ok(disk_table) => {
-                shards.put_disk_table_by_level(disk_tables_shard::SEGMENTS_MIN_LEVEL, disk_table);
+        // entries of the memory table stay in the write-ahead log
+        let disk_table = match disk_table_from_mem_table {
+            Ok(disk_table) => disk_table,
+            Err(er) => {
+                if let Err(discard_er) = builder.discard() {
+                    error!("Failed discard disk table of memory table: {}", discard_er);
+                }
+                panic!("Failed save_mem_table. {}", er)
             }
-            Err(er) => panic!("Failed save_mem_table. {}", er),
+        };
+
+        let edit = {
+            let mut metadata = metadata.lock().unwrap();
+            metadata.update_last_sequence(flushing.last_sequence());
+
+            let mut edit =
+                VersionEdit::new(metadata.next_disk_table_id(), metadata.last_sequence());
+            edit.add_table(disk_tables_shard::SEGMENTS_MIN_LEVEL, disk_table.get_name());
+            edit
+        };
+        if let Err(er) = manifest.lock().unwrap().log_edit(&edit) {
+            panic!("Failed log flush to manifest. {}", er)
         }
 
-        lock.clear();
+        // readers find entries in the disk table before the immutable table is gone
+        shards.put_disk_table_by_level(disk_tables_shard::SEGMENTS_MIN_LEVEL, disk_table);
+        *i_mem_table.write().unwrap() = None;
 
         metadata.lock().unwrap().sync_disk();
+
+        // entries of the flushed memory table are durable in the disk table now
+        if let Err(er) = wal.lock().unwrap().remove_sealed() {
+            panic!("Failed remove sealed write-ahead logs. {}", er)
+        }
     }
 
+    fn spawn_compaction_worker(
+        shards: Arc<DiskTablesShards>,
+        metadata: Arc<Mutex<StorageMetadata>>,
+        manifest: Arc<Mutex<Manifest>>,
+        storage_path: PathBuf,
+        need_compaction: Arc<AtomicBool>,
+        stop_compaction: Arc<AtomicBool>,
+    ) -> thread::JoinHandle<()> {
+        thread::spawn(move || loop {
+            while !need_compaction.load(Ordering::SeqCst) && !stop_compaction.load(Ordering::SeqCst)
+            {
+                thread::sleep(std::time::Duration::from_millis(200));
+            }
+
+            // another worker can take the request, this one waits for the next one then
+            if !need_compaction.swap(false, Ordering::SeqCst) {
+                if stop_compaction.load(Ordering::SeqCst) {
+                    return;
+                }
+                continue;
+            }
+
+            trace!("call compaction");
+
+            Self::merge_disk_tables(
+                shards.clone(),
+                metadata.clone(),
+                manifest.clone(),
+                storage_path.clone(),
+                need_compaction.clone(),
+            );
+        })
+    }
+
+    // runs compactions until the strategy has none which don't conflict with running ones
     fn merge_disk_tables(
         shards: Arc<DiskTablesShards>,
         metadata: Arc<Mutex<StorageMetadata>>,
+        manifest: Arc<Mutex<Manifest>>,
         storage_path: PathBuf,
+        need_compaction: Arc<AtomicBool>,
     ) {
-        for merging_level in
-            disk_tables_shard::SEGMENTS_MIN_LEVEL..=disk_tables_shard::SEGMENTS_MAX_LEVEL
-        {
-            trace!("call merge_disk_tables, merging_level={}", merging_level);
+        while let Some(compaction) = shards.pick_compaction() {
+            // idle workers look for compactions which can run along with this one
+            need_compaction.store(true, Ordering::SeqCst);
+
+            trace!(
+                "call merge_disk_tables, level={}, output_level={}, {} tables",
+                compaction.level,
+                compaction.output_level,
+                compaction.inputs.len()
+            );
 
-            let level_for_new_disk_table = if merging_level != disk_tables_shard::SEGMENTS_MAX_LEVEL
+            // the failed compaction is picked again after the next flush
+            if let Err(er) =
+                Self::run_compaction(&shards, &metadata, &manifest, &storage_path, &compaction)
             {
-                merging_level + 1
-            } else {
-                merging_level
-            };
+                error!(
+                    "Failed compaction of level {}: error={}",
+                    compaction.level, er
+                );
+                return;
+            }
+        }
+    }
 
-            let merged_disk_table = match Self::create_merged_disk_table(
-                &shards,
-                merging_level,
-                metadata.clone(),
-                storage_path.as_path(),
-            ) {
-                Some(new_disk_table) => new_disk_table,
-                None => {
-                    debug!("no merge");
-                    break;
-                }
-            };
+    // merges tables of compaction and replaces them in manifest and shards, returns merged tables.
+    // Tables are kept if some of them isn't read
+    fn run_compaction(
+        shards: &DiskTablesShards,
+        metadata: &Arc<Mutex<StorageMetadata>>,
+        manifest: &Arc<Mutex<Manifest>>,
+        storage_path: &Path,
+        compaction: &Compaction,
+    ) -> Result<Vec<ReaderDiskTablePtr>, Error> {
+        let merged_disk_tables = match shards.compact(compaction, || {
+            Self::new_disk_table_path(metadata.clone(), storage_path, compaction.output_level)
+        }) {
+            Ok(merged_disk_tables) => merged_disk_tables,
+            Err(er) => {
+                shards.abort_compaction(compaction);
+                return Err(er);
+            }
+        };
 
-            if let Err(er) = shards.remove_level_and_put(
-                merging_level,
-                level_for_new_disk_table,
-                merged_disk_table,
-            ) {
-                panic!("failed remove merging disk table: error={}", er)
+        // files of the merged tables are removed only after the edit is durable
+        let edit = {
+            let metadata = metadata.lock().unwrap();
+
+            let mut edit =
+                VersionEdit::new(metadata.next_disk_table_id(), metadata.last_sequence());
+            for disk_table_name in compaction.input_names() {
+                edit.remove_table(&disk_table_name);
+            }
+            for disk_table in &merged_disk_tables {
+                edit.add_table(compaction.output_level, disk_table.get_name());
             }
+            edit
+        };
+        if let Err(er) = manifest.lock().unwrap().log_edit(&edit) {
+            panic!("Failed log merge to manifest. {}", er)
+        }
 
-            debug!(
-                "merged_disk_table was finished: merging_level={}",
-                merging_level
-            );
+        if let Err(er) = shards.apply_compaction(compaction, merged_disk_tables.clone()) {
+            panic!("failed remove merging disk table: error={}", er)
         }
+
+        debug!(
+            "merged_disk_table was finished: level={}, output_level={}",
+            compaction.level, compaction.output_level
+        );
+
+        Ok(merged_disk_tables)
     }
 
-    fn create_merged_disk_table(
-        shards: &Arc<DiskTablesShards>,
-        merging_level: disk_tables_shard::Levels,
+    fn new_disk_table_path(
         metadata: Arc<Mutex<StorageMetadata>>,
         storage_path: &Path,
-    ) -> Option<ReaderDiskTablePtr> {
-        if !shards.is_ready_to_merge(merging_level) {
-            return None;
+        level: disk_tables_shard::Levels,
+    ) -> (PathBuf, PathBuf) {
+        let disk_table_id = metadata.lock().unwrap().get_new_disk_table_id();
+        let (disk_table_name, index_table_name) =
+            get_disk_table_name_by_level(disk_table_id, level);
+
+        get_disk_table_path(storage_path, &disk_table_name, &index_table_name)
+    }
+
+    fn append_entries(&self, entries: &[FlexibleUserEntry]) -> Result<(), Error> {
+        if self.shutdown.load(Ordering::SeqCst) {
+            return Err(Error::IO("Storage is dropping".to_string()));
         }
+        if entries.is_empty() {
+            return Ok(());
+        }
+        // the entry would fail the flush, so it isn't written
+        for entry in entries {
+            DiskTableBuilder::check_entry(entry)?;
+        }
+        let mut lock = self.m_mem_table.write().unwrap();
 
-        let level_for_new_sg = if merging_level != disk_tables_shard::SEGMENTS_MAX_LEVEL {
-            merging_level + 1
-        } else {
-            merging_level
+        self.append_entries_locked(&mut lock, entries)
+    }
+
+    fn append_entries_locked(
+        &self,
+        lock: &mut MemoryTable,
+        entries: &[FlexibleUserEntry],
+    ) -> Result<(), Error> {
+        // contiguous sequence numbers in the order of entries
+        let first_sequence = self
+            .last_sequence
+            .fetch_add(entries.len() as SequenceNumber, Ordering::SeqCst)
+            + 1;
+        let entries = entries
+            .iter()
+            .zip(first_sequence..)
+            .map(|(entry, sequence)| {
+                let mut entry = entry.clone();
+                entry.set_sequence(sequence);
+                entry
+            })
+            .collect::<Vec<_>>();
+
+        self.wal.lock().unwrap().append(&entries)?;
+        for entry in &entries {
+            lock.append(entry);
+        }
+
+        // refactoring
+        if lock.need_flush() {
+            // cas
+            self.need_flush.store(true, Ordering::SeqCst);
+        }
+
+        Ok(())
+    }
+
+    // point-in-time view, it sees all writes done before the call
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        // writes change the last sequence number under the write lock
+        let _lock = self.m_mem_table.read().unwrap();
+
+        let sequence = self.last_sequence.load(Ordering::SeqCst);
+        self.shards.snapshots().acquire(sequence);
+
+        Snapshot::new(self, sequence)
+    }
+
+    /// Rewrites live values of the oldest sealed value log file into storage and removes
+    /// the file, returns the count of rewritten values. Older versions of keys can point
+    /// into the file while snapshots are alive, so nothing is collected then.
+    pub fn collect_value_log_garbage(&self) -> Result<usize, Error> {
+        if self.shutdown.load(Ordering::SeqCst) {
+            return Err(Error::IO("Storage is dropping".to_string()));
+        }
+        let Some(value_log) = self.shards.value_log() else {
+            return Ok(0);
         };
 
-        let disk_table_id = metadata.lock().unwrap().get_new_disk_table_id();
-        let (disk_table_name, index_table_name) =
-            get_disk_table_name_by_level(disk_table_id, level_for_new_sg);
-        let (disk_table_path, index_table_path) =
-            get_disk_table_path(storage_path, &disk_table_name, &index_table_name);
+        // writes and new snapshots wait, so the newest versions of keys stay newest
+        let mut lock = self.m_mem_table.write().unwrap();
+
+        // the flushing table can point into sealed files after it's written
+        if !self.shards.snapshots().sequences().is_empty()
+            || self.i_mem_table.read().unwrap().is_some()
+        {
+            return Ok(0);
+        }
+        let Some(file_number) = value_log.sealed_files().first().copied() else {
+            return Ok(0);
+        };
+
+        let mut live = Vec::new();
+        for (pointer, key, value) in value_log.read_file(file_number)? {
+            // the key was written again
+            if lock.get_entry(&key).is_some() {
+                continue;
+            }
+
+            let Some(entry) = self.shards.get_entry_at(&key, SequenceNumber::MAX)? else {
+                continue;
+            };
+            if entry.get_kind() == EntryKind::ValuePointer
+                && ValuePointer::from(entry.get_value().data())? == pointer
+            {
+                live.push(FlexibleUserEntry::new(key, value));
+            }
+        }
 
-        let merged_disk_table = shards.merge_level(
-            merging_level,
-            disk_table_path.as_path(),
-            index_table_path.as_path(),
+        // rewritten values must be durable before the file is gone
+        self.append_entries_locked(&mut lock, &live)?;
+        self.wal.lock().unwrap().sync()?;
+        value_log.remove_file(file_number)?;
+
+        debug!(
+            "value log file {} was collected, {} values were rewritten",
+            file_number,
+            live.len()
+        );
+
+        Ok(live.len())
+    }
+
+    /// Flushes the memory table and merges all tables which can have keys in [start, end]
+    /// down to the last level, so deleted keys of the range are gone from disk.
+    /// It waits for running compactions of these tables and returns sizes of
+    /// merged data blocks.
+    pub fn compact_range(
+        &self,
+        start: &FlexibleField,
+        end: &FlexibleField,
+    ) -> Result<CompactionSummary, Error> {
+        if self.shutdown.load(Ordering::SeqCst) {
+            return Err(Error::IO("Storage is dropping".to_string()));
+        }
+        if start > end {
+            return Err(Error::LogicError(
+                "start of range is greater than its end".to_string(),
+            ));
+        }
+
+        {
+            let _flush_lock = self.flush_lock.lock().unwrap();
+            Self::save_mem_table(
+                self.m_mem_table.clone(),
+                self.i_mem_table.clone(),
+                self.wal.clone(),
+                self.metadata.clone(),
+                self.manifest.clone(),
+                self.storage_path.clone(),
+                &mut self.shards.clone(),
+            );
+        }
+
+        let mut summary = CompactionSummary::default();
+        // tables of the last level which are merged already
+        let mut merged_names = Vec::new();
+        for level in disk_tables_shard::SEGMENTS_MIN_LEVEL..=disk_tables_shard::SEGMENTS_MAX_LEVEL {
+            let Some(compaction) =
+                self.shards
+                    .pick_range_compaction(level, start, end, &merged_names)
+            else {
+                continue;
+            };
+
+            let merged_disk_tables = Self::run_compaction(
+                &self.shards,
+                &self.metadata,
+                &self.manifest,
+                &self.storage_path,
+                &compaction,
+            )?;
+            summary.add(&compaction, &merged_disk_tables);
+            if compaction.is_last_level() {
+                merged_names.extend(
+                    merged_disk_tables
+                        .iter()
+                        .map(|disk_table| disk_table.get_name().to_string()),
+                );
+            }
+        }
+
+        debug!(
+            "range was compacted: {} tables were read, {} tables were written",
+            summary.tables_read, summary.tables_written
         );
 
-        Some(merged_disk_table)
+        Ok(summary)
+    }
+
+    // compaction workers which are running, all of them live until the storage is dropped
+    pub fn count_compaction_workers(&self) -> usize {
+        self.compaction_workers
+            .iter()
+            .filter(|worker| !worker.is_finished())
+            .count()
+    }
+
+    pub fn bloom_filter_stats(&self) -> &BloomFilterStats {
+        self.shards.bloom_filter_stats()
+    }
+
+    pub(crate) fn release_snapshot(&self, sequence: SequenceNumber) {
+        self.shards.snapshots().release(sequence);
+    }
+
+    pub(crate) fn get_at(
+        &self,
+        key: &FlexibleField,
+        sequence: SequenceNumber,
+    ) -> Result<Option<FlexibleField>, Error> {
+        if self.shutdown.load(Ordering::SeqCst) {
+            debug!("Storage was shutdowned. None.");
+            return Ok(None);
+        }
+        // a file collected after this point is still readable
+        let value_log_files = self.shards.value_log_files();
+
+        // from the newest entries: active table, immutable table, disk tables
+        if let Some(entry) = self.m_mem_table.read().unwrap().get_entry_at(key, sequence) {
+            return Ok(Self::entry_value(entry));
+        }
+
+        let i_mem_table = self.i_mem_table.read().unwrap().clone();
+        if let Some(entry) = i_mem_table
+            .as_ref()
+            .and_then(|i_mem_table| i_mem_table.get_entry_at(key, sequence))
+        {
+            return Ok(Self::entry_value(entry));
+        }
+
+        match self.shards.get_entry_at(key, sequence)? {
+            Some(entry) => Self::disk_entry_value(entry, &value_log_files),
+            None => Ok(None),
+        }
+    }
+
+    fn entry_value(entry: &FlexibleUserEntry) -> Option<FlexibleField> {
+        if entry.is_tombstone() {
+            return None;
+        }
+        Some(entry.get_value().clone())
+    }
+
+    // a large value of disk table is read from the value log
+    fn disk_entry_value(
+        entry: FlexibleUserEntry,
+        value_log_files: &ValueLogFiles,
+    ) -> Result<Option<FlexibleField>, Error> {
+        if entry.is_tombstone() {
+            return Ok(None);
+        }
+        Ok(Some(value_log_files.resolve(entry)?.get_value().clone()))
+    }
+
+    pub(crate) fn make_iterator(
+        &self,
+        start: Option<FlexibleField>,
+        end: Option<FlexibleField>,
+        sequence: SequenceNumber,
+    ) -> StorageIterator {
+        let value_log_files = self.shards.value_log_files();
+
+        let mut mem_tables_entries: Vec<Vec<FlexibleUserEntry>> = vec![self
+            .m_mem_table
+            .read()
+            .unwrap()
+            .range(start.as_ref(), end.as_ref())
+            .cloned()
+            .collect()];
+
+        let i_mem_table = self.i_mem_table.read().unwrap().clone();
+        if let Some(i_mem_table) = i_mem_table {
+            mem_tables_entries.push(
+                i_mem_table
+                    .range(start.as_ref(), end.as_ref())
+                    .cloned()
+                    .collect(),
+            );
+        }
+
+        // memory tables are read first, so entries flushed meanwhile are in disk tables,
+        // tables out of the range are skipped
+        let disk_tables = self
+            .shards
+            .disk_tables()
+            .into_iter()
+            .filter(|disk_table| {
+                disk_table
+                    .properties()
+                    .is_none_or(|properties| properties.overlaps(start.as_ref(), end.as_ref()))
+            })
+            .collect();
+
+        StorageIterator::new(
+            mem_tables_entries,
+            disk_tables,
+            start,
+            end,
+            sequence,
+            value_log_files,
+        )
     }
 }
 
@@ -260,6 +773,17 @@ impl Drop for OrderedStorage {
             Ok(_) => info!("Flush worker was joined"),
             Err(er) => error!("Drop storage: failed join flush worker with error={:?}", er),
         }
+
+        self.stop_compaction.store(true, Ordering::SeqCst);
+        for worker in self.compaction_workers.drain(..) {
+            match worker.join() {
+                Ok(_) => info!("Compaction worker was joined"),
+                Err(er) => error!(
+                    "Drop storage: failed join compaction worker with error={:?}",
+                    er
+                ),
+            }
+        }
     }
 }
 
@@ -267,31 +791,27 @@ unsafe impl Sync for OrderedStorage {}
 
 impl Storage for OrderedStorage {
     fn put(&self, entry: &FlexibleUserEntry) -> Result<(), Error> {
-        if self.shutdown.load(Ordering::SeqCst) {
-            return Err(Error::IO("Storage is dropping".to_string()));
-        }
-        let mut lock = self.m_mem_table.write().unwrap();
-        lock.append(entry);
+        self.append_entries(std::slice::from_ref(entry))
+    }
 
-        // refactoring
-        if lock.need_flush() {
-            // cas
-            self.need_flush.store(true, Ordering::SeqCst);
-        }
+    fn get(&self, key: &FlexibleField) -> Result<Option<FlexibleField>, Error> {
+        self.get_at(key, SequenceNumber::MAX)
+    }
 
-        Ok(())
+    fn delete(&self, key: &FlexibleField) -> Result<(), Error> {
+        self.append_entries(&[FlexibleUserEntry::new_tombstone(key.clone())])
     }
 
-    fn get(&self, key: &FlexibleField) -> Result<Option<FlexibleField>, Error> {
-        if self.shutdown.load(Ordering::SeqCst) {
-            debug!("Storage was shutdowned. None.");
-            return Ok(None);
-        }
-        if let Some(value) = self.m_mem_table.read().unwrap().get_value(key) {
-            return Ok(Some(value));
-        }
+    fn write(&self, batch: &WriteBatch) -> Result<(), Error> {
+        self.append_entries(batch.entries())
+    }
+
+    fn scan(&self, range: Range<FlexibleField>) -> Result<StorageIterator, Error> {
+        Ok(self.make_iterator(Some(range.start), Some(range.end), SequenceNumber::MAX))
+    }
 
-        self.shards.get(key)
+    fn iter(&self) -> Result<StorageIterator, Error> {
+        Ok(self.make_iterator(None, None, SequenceNumber::MAX))
     }
 }
 
@@ -0,0 +1,222 @@
+use std::{collections::BTreeMap, ops::Range, sync::Mutex};
+
+use crate::core::{
+    entry::{flexible_user_entry::FlexibleUserEntry, user_entry::SequenceNumber},
+    field::FlexibleField,
+    storage::{ordered_storage::OrderedStorage, storage_iterator::StorageIterator},
+};
+use crate::errors::Result;
+
+// Point-in-time view of storage: only entries with sequence numbers
+// at or below the snapshot are visible.
+pub struct Snapshot<'a> {
+    storage: &'a OrderedStorage,
+    sequence: SequenceNumber,
+}
+
+impl<'a> Snapshot<'a> {
+    pub(crate) fn new(storage: &'a OrderedStorage, sequence: SequenceNumber) -> Self {
+        Self { storage, sequence }
+    }
+
+    pub fn sequence(&self) -> SequenceNumber {
+        self.sequence
+    }
+
+    pub fn get(&self, key: &FlexibleField) -> Result<Option<FlexibleField>> {
+        self.storage.get_at(key, self.sequence)
+    }
+
+    // key-ordered entries with keys in [range.start, range.end)
+    pub fn scan(&self, range: Range<FlexibleField>) -> Result<StorageIterator> {
+        Ok(self
+            .storage
+            .make_iterator(Some(range.start), Some(range.end), self.sequence))
+    }
+
+    // key-ordered all entries
+    pub fn iter(&self) -> Result<StorageIterator> {
+        Ok(self.storage.make_iterator(None, None, self.sequence))
+    }
+}
+
+impl Drop for Snapshot<'_> {
+    fn drop(&mut self) {
+        self.storage.release_snapshot(self.sequence);
+    }
+}
+
+// Sequence numbers of live snapshots, some snapshots can share a number.
+pub struct SnapshotList {
+    sequences: Mutex<BTreeMap<SequenceNumber, usize>>,
+}
+
+impl SnapshotList {
+    pub fn new() -> Self {
+        Self {
+            sequences: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn acquire(&self, sequence: SequenceNumber) {
+        *self.sequences.lock().unwrap().entry(sequence).or_insert(0) += 1;
+    }
+
+    pub fn release(&self, sequence: SequenceNumber) {
+        let mut sequences = self.sequences.lock().unwrap();
+
+        let Some(count) = sequences.get_mut(&sequence) else {
+            panic!("Something wrong: snapshot {} was not acquired", sequence)
+        };
+
+        *count -= 1;
+        if *count == 0 {
+            sequences.remove(&sequence);
+        }
+    }
+
+    // sorted sequence numbers of live snapshots
+    pub fn sequences(&self) -> Vec<SequenceNumber> {
+        self.sequences.lock().unwrap().keys().copied().collect()
+    }
+}
+
+impl Default for SnapshotList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Decides which versions survive a flush or a merge. Entries must come sorted
+// by key and from the newest version of key.
+//
+// The newest version of key is always kept, an older version is kept only if
+// some snapshot reads it: entry.seq <= snapshot < newer_entry.seq.
+pub struct VersionFilter {
+    // sorted sequence numbers of live snapshots
+    snapshots: Vec<SequenceNumber>,
+    // there are no older tables, so deleted keys can be dropped
+    drop_tombstones: bool,
+    last_key: Option<FlexibleField>,
+    // sequence number of the previous (newer) version of the last key
+    last_sequence: SequenceNumber,
+}
+
+impl VersionFilter {
+    pub fn new(snapshots: Vec<SequenceNumber>, drop_tombstones: bool) -> Self {
+        Self {
+            snapshots,
+            drop_tombstones,
+            last_key: None,
+            last_sequence: SequenceNumber::MAX,
+        }
+    }
+
+    pub fn keep(&mut self, entry: &FlexibleUserEntry) -> bool {
+        let sequence = entry.get_sequence();
+
+        let newest = self.last_key.as_ref() != Some(entry.get_key());
+        let newer_sequence = if newest {
+            self.last_key = Some(entry.get_key().clone());
+            SequenceNumber::MAX
+        } else {
+            self.last_sequence
+        };
+        self.last_sequence = sequence;
+
+        if !newest && !self.is_read_by_snapshot(sequence, newer_sequence) {
+            return false;
+        }
+
+        // a tombstone is needed only while some snapshot can read older versions
+        !(self.drop_tombstones
+            && entry.is_tombstone()
+            && self
+                .snapshots
+                .first()
+                .is_none_or(|oldest| *oldest >= sequence))
+    }
+
+    fn is_read_by_snapshot(
+        &self,
+        sequence: SequenceNumber,
+        newer_sequence: SequenceNumber,
+    ) -> bool {
+        let index = self
+            .snapshots
+            .partition_point(|snapshot| *snapshot < sequence);
+
+        self.snapshots
+            .get(index)
+            .is_some_and(|snapshot| *snapshot < newer_sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::field::Field;
+
+    fn make_entry(key: u8, sequence: SequenceNumber, tombstone: bool) -> FlexibleUserEntry {
+        let mut entry = if tombstone {
+            FlexibleUserEntry::new_tombstone(FlexibleField::new(vec![key]))
+        } else {
+            FlexibleUserEntry::new(FlexibleField::new(vec![key]), FlexibleField::new(vec![key]))
+        };
+        entry.set_sequence(sequence);
+        entry
+    }
+
+    #[test]
+    fn test_version_filter() {
+        let mut filter = VersionFilter::new(vec![3, 7], false);
+
+        // key 1: versions 9, 8, 6, 5, 2, 1
+        assert!(filter.keep(&make_entry(1, 9, false)));
+        // not read by any snapshot
+        assert!(!filter.keep(&make_entry(1, 8, false)));
+        // read by snapshot 7
+        assert!(filter.keep(&make_entry(1, 6, false)));
+        assert!(!filter.keep(&make_entry(1, 5, false)));
+        // read by snapshot 3
+        assert!(filter.keep(&make_entry(1, 2, false)));
+        assert!(!filter.keep(&make_entry(1, 1, false)));
+
+        // the newest version of other key
+        assert!(filter.keep(&make_entry(2, 1, false)));
+    }
+
+    #[test]
+    fn test_version_filter_tombstones() {
+        let mut filter = VersionFilter::new(vec![4], true);
+
+        // snapshot 4 reads the older version, so the tombstone is needed
+        assert!(filter.keep(&make_entry(1, 6, true)));
+        assert!(filter.keep(&make_entry(1, 2, false)));
+
+        // no snapshot before the tombstone
+        assert!(!filter.keep(&make_entry(2, 3, true)));
+        assert!(!filter.keep(&make_entry(2, 1, false)));
+
+        let mut filter = VersionFilter::new(Vec::new(), true);
+        assert!(!filter.keep(&make_entry(1, 6, true)));
+        assert!(!filter.keep(&make_entry(1, 2, false)));
+    }
+
+    #[test]
+    fn test_snapshot_list() {
+        let snapshots = SnapshotList::new();
+
+        snapshots.acquire(5);
+        snapshots.acquire(2);
+        snapshots.acquire(5);
+        assert_eq!(snapshots.sequences(), vec![2, 5]);
+
+        snapshots.release(5);
+        assert_eq!(snapshots.sequences(), vec![2, 5]);
+
+        snapshots.release(5);
+        snapshots.release(2);
+        assert!(snapshots.sequences().is_empty());
+    }
+}
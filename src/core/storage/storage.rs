@@ -1,8 +1,19 @@
+use std::ops::Range;
+
 use crate::core::entry::flexible_user_entry::FlexibleUserEntry;
 use crate::core::field::FlexibleField;
+use crate::core::storage::storage_iterator::StorageIterator;
+use crate::core::storage::write_batch::WriteBatch;
 use crate::errors::Error;
 
 pub trait Storage {
     fn put(&self, entry: &FlexibleUserEntry) -> Result<(), Error>;
     fn get(&self, key: &FlexibleField) -> Result<Option<FlexibleField>, Error>;
+    fn delete(&self, key: &FlexibleField) -> Result<(), Error>;
+    // all entries of batch become visible at once
+    fn write(&self, batch: &WriteBatch) -> Result<(), Error>;
+    // key-ordered entries with keys in [range.start, range.end)
+    fn scan(&self, range: Range<FlexibleField>) -> Result<StorageIterator, Error>;
+    // key-ordered all entries
+    fn iter(&self) -> Result<StorageIterator, Error>;
 }
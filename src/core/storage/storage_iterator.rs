@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use crate::core::{
+    disk_table::{
+        disk_table::ReaderDiskTableIterator, local::reader_local_disk_table::ReaderDiskTablePtr,
+    },
+    entry::{flexible_user_entry::FlexibleUserEntry, user_entry::SequenceNumber},
+    field::FlexibleField,
+    storage::value_log::ValueLogFiles,
+};
+use crate::errors::{Error, Result};
+
+// Sorted by key source of entries.
+trait Source: Iterator<Item = FlexibleUserEntry> {
+    // the next entry will be the first one with key >= `key`
+    fn seek(&mut self, key: &FlexibleField);
+
+    // the error which ended the source
+    fn take_error(&mut self) -> Option<Error> {
+        None
+    }
+}
+
+struct MemoryTableSource {
+    entries: Vec<FlexibleUserEntry>,
+    pos: usize,
+}
+
+impl Iterator for MemoryTableSource {
+    type Item = FlexibleUserEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.get(self.pos)?.clone();
+        self.pos += 1;
+        Some(entry)
+    }
+}
+
+impl Source for MemoryTableSource {
+    fn seek(&mut self, key: &FlexibleField) {
+        self.pos = self.entries.partition_point(|entry| entry.get_key() < key);
+    }
+}
+
+impl Source for ReaderDiskTableIterator<'static, FlexibleField, FlexibleField> {
+    fn seek(&mut self, key: &FlexibleField) {
+        ReaderDiskTableIterator::seek(self, key);
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        ReaderDiskTableIterator::take_error(self)
+    }
+}
+
+// the next entry of source, an error of source is kept until it's returned by iterator
+fn next_entry(source: &mut dyn Source, error: &mut Option<Error>) -> Option<FlexibleUserEntry> {
+    let entry = source.next();
+    if entry.is_none() && error.is_none() {
+        *error = source.take_error();
+    }
+    entry
+}
+
+// Merges the memory table and disk tables into one key-ordered sequence.
+// If a key is met in some sources, the version with the greatest sequence number
+// wins and others versions are skipped. Deleted keys are skipped as well.
+// Versions newer than the sequence number of iterator are invisible.
+// A failed read of a disk table or the value log is returned as the last item.
+pub struct StorageIterator {
+    // sorted from the newest source
+    sources: Vec<Box<dyn Source>>,
+    // the current entry of every source
+    heads: Vec<Option<FlexibleUserEntry>>,
+    start: Option<FlexibleField>,
+    end: Option<FlexibleField>,
+    sequence: SequenceNumber,
+    // large values of disk tables are read from the value log
+    value_log_files: Arc<ValueLogFiles>,
+    // entries after a failed read would miss versions of the failed source
+    error: Option<Error>,
+}
+
+impl StorageIterator {
+    // `disk_tables` must be sorted from the newest table
+    pub(crate) fn new(
+        mem_tables_entries: Vec<Vec<FlexibleUserEntry>>,
+        disk_tables: Vec<ReaderDiskTablePtr>,
+        start: Option<FlexibleField>,
+        end: Option<FlexibleField>,
+        sequence: SequenceNumber,
+        value_log_files: Arc<ValueLogFiles>,
+    ) -> Self {
+        let mut sources: Vec<Box<dyn Source>> =
+            Vec::with_capacity(mem_tables_entries.len() + disk_tables.len());
+
+        for entries in mem_tables_entries {
+            sources.push(Box::new(MemoryTableSource { entries, pos: 0 }));
+        }
+        for disk_table in disk_tables {
+            sources.push(Box::new(ReaderDiskTableIterator::new(disk_table)));
+        }
+
+        let mut it = Self {
+            heads: (0..sources.len()).map(|_| None).collect(),
+            sources,
+            start,
+            end,
+            sequence,
+            value_log_files,
+            error: None,
+        };
+
+        match it.start.clone() {
+            Some(start) => it.seek(&start),
+            None => it.refill_heads(),
+        }
+
+        it
+    }
+
+    // the next entry will be the first one with key >= `key` in the range of iterator
+    pub fn seek(&mut self, key: &FlexibleField) {
+        let key = match &self.start {
+            Some(start) if start > key => start.clone(),
+            _ => key.clone(),
+        };
+
+        for source in self.sources.iter_mut() {
+            source.seek(&key);
+        }
+
+        self.refill_heads();
+    }
+
+    fn refill_heads(&mut self) {
+        for (source, head) in self.sources.iter_mut().zip(self.heads.iter_mut()) {
+            *head = next_entry(source.as_mut(), &mut self.error);
+        }
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        let error = self.error.take()?;
+        self.heads.iter_mut().for_each(|head| *head = None);
+        Some(error)
+    }
+}
+
+impl Iterator for StorageIterator {
+    type Item = Result<FlexibleUserEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(er) = self.take_error() {
+                return Some(Err(er));
+            }
+
+            // entries of the same key are ordered from the newest version
+            let (index, _) = self
+                .heads
+                .iter()
+                .enumerate()
+                .filter_map(|(index, head)| head.as_ref().map(|entry| (index, entry)))
+                .min_by(|lhs, rhs| lhs.1.cmp(rhs.1))?;
+
+            let entry = self.heads[index]
+                .take()
+                .expect("head was chosen as minimum");
+            self.heads[index] = next_entry(self.sources[index].as_mut(), &mut self.error);
+
+            // an older version of key can be visible
+            if entry.get_sequence() > self.sequence {
+                continue;
+            }
+
+            // skip older versions of key
+            for (source, head) in self.sources.iter_mut().zip(self.heads.iter_mut()) {
+                while head
+                    .as_ref()
+                    .is_some_and(|older| older.get_key() == entry.get_key())
+                {
+                    *head = next_entry(source.as_mut(), &mut self.error);
+                }
+            }
+            if let Some(er) = self.take_error() {
+                return Some(Err(er));
+            }
+
+            if let Some(end) = &self.end {
+                if entry.get_key() >= end {
+                    self.heads.iter_mut().for_each(|head| *head = None);
+                    return None;
+                }
+            }
+
+            if entry.is_tombstone() {
+                continue;
+            }
+
+            // the value isn't read like a block of disk table, so iteration ends as well
+            let entry = self.value_log_files.resolve(entry);
+            if entry.is_err() {
+                self.heads.iter_mut().for_each(|head| *head = None);
+            }
+            return Some(entry);
+        }
+    }
+}
@@ -0,0 +1,456 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use log::{info, warn};
+
+use crate::core::disk_table::local::block::checksum::{checksum, CHECKSUM_SIZE};
+use crate::core::entry::{flexible_user_entry::FlexibleUserEntry, user_entry::EntryKind};
+use crate::core::field::{Field, FlexibleField};
+use crate::core::marshal::{read_u32, read_u64, write_u32, write_u64};
+use crate::errdata;
+use crate::errors::{Error, Result};
+
+const VALUE_LOG_DIR: &str = "vlog";
+const VALUE_LOG_EXTENSION: &str = "vlog";
+const RECORD_HEADER_SIZE: usize = 2 * size_of::<u32>();
+
+// [ file_number offset size ]
+pub const VALUE_POINTER_SIZE: usize = 2 * size_of::<u64>() + size_of::<u32>();
+
+// Position of record in the value log, it's the value of `EntryKind::ValuePointer` entries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValuePointer {
+    pub file_number: u64,
+    pub offset: u64,
+    // size of the whole record
+    pub size: u32,
+}
+
+impl ValuePointer {
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; VALUE_POINTER_SIZE];
+
+        let mut offset = write_u64(&mut buffer, self.file_number)?;
+        offset += write_u64(&mut buffer[offset..], self.offset)?;
+        write_u32(&mut buffer[offset..], self.size)?;
+
+        Ok(buffer)
+    }
+
+    pub fn from(src: &[u8]) -> Result<Self> {
+        if src.len() != VALUE_POINTER_SIZE {
+            return errdata!("value pointer of {} bytes", src.len());
+        }
+
+        Ok(Self {
+            file_number: read_u64(src)?,
+            offset: read_u64(&src[size_of::<u64>()..])?,
+            size: read_u32(&src[2 * size_of::<u64>()..])?,
+        })
+    }
+}
+
+// Record:
+// [ key_size value_size key value checksum ]
+//
+// The key is kept for garbage collection, checksum is CRC32C of all previous bytes.
+fn encode_record(key: &FlexibleField, value: &FlexibleField) -> Result<Vec<u8>> {
+    let size = RECORD_HEADER_SIZE + key.size() + value.size() + CHECKSUM_SIZE;
+    let mut buffer = vec![0u8; size];
+
+    let mut offset = write_u32(&mut buffer, key.size() as u32)?;
+    offset += write_u32(&mut buffer[offset..], value.size() as u32)?;
+    buffer[offset..offset + key.size()].copy_from_slice(key.data());
+    offset += key.size();
+    buffer[offset..offset + value.size()].copy_from_slice(value.data());
+    offset += value.size();
+
+    let crc = checksum(&buffer[..offset]);
+    write_u32(&mut buffer[offset..], crc)?;
+
+    Ok(buffer)
+}
+
+// the key and the value of record, None if the record is broken or truncated
+fn decode_record(record: &[u8]) -> Option<(FlexibleField, FlexibleField)> {
+    if record.len() < RECORD_HEADER_SIZE + CHECKSUM_SIZE {
+        return None;
+    }
+
+    let key_size = read_u32(record).ok()? as usize;
+    let value_size = read_u32(&record[size_of::<u32>()..]).ok()? as usize;
+    let data_end = RECORD_HEADER_SIZE + key_size + value_size;
+    if data_end + CHECKSUM_SIZE > record.len()
+        || read_u32(&record[data_end..]).ok()? != checksum(&record[..data_end])
+    {
+        return None;
+    }
+
+    let key = &record[RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + key_size];
+    let value = &record[RECORD_HEADER_SIZE + key_size..data_end];
+
+    Some((FlexibleField::new(key), FlexibleField::new(value)))
+}
+
+fn file_path(dir: &Path, file_number: u64) -> PathBuf {
+    dir.join(format!("vlog_{:07}.{}", file_number, VALUE_LOG_EXTENSION))
+}
+
+fn extract_file_number(path: &Path) -> Option<u64> {
+    // vlog_0000012.vlog
+
+    if path.extension()? != VALUE_LOG_EXTENSION {
+        return None;
+    }
+
+    let name = path.file_stem()?.to_str()?;
+    name.strip_prefix("vlog_")?.parse::<u64>().ok()
+}
+
+// Files of the value log at some moment. Readers take them before they look for
+// the key, so a file removed by garbage collection meanwhile is still readable.
+// Files aren't removed before all pointers to them are shadowed by newer writes.
+#[derive(Clone, Default)]
+pub struct ValueLogFiles {
+    dir: PathBuf,
+    files: BTreeMap<u64, Arc<File>>,
+}
+
+impl ValueLogFiles {
+    fn read_record(&self, pointer: &ValuePointer) -> Result<(FlexibleField, FlexibleField)> {
+        let path = file_path(self.dir.as_path(), pointer.file_number);
+        // a file created after files were taken is opened by its path
+        let file = match self.files.get(&pointer.file_number) {
+            Some(file) => file.clone(),
+            None => Arc::new(File::open(path.as_path())?),
+        };
+
+        let mut record = vec![0u8; pointer.size as usize];
+        file.read_exact_at(&mut record, pointer.offset)?;
+
+        decode_record(&record).ok_or(Error::Corruption {
+            path,
+            offset: pointer.offset,
+        })
+    }
+
+    // the entry with its value instead of the pointer to value log
+    pub fn resolve(&self, entry: FlexibleUserEntry) -> Result<FlexibleUserEntry> {
+        if entry.get_kind() != EntryKind::ValuePointer {
+            return Ok(entry);
+        }
+
+        let pointer = ValuePointer::from(entry.get_value().data())?;
+        let (key, value) = self.read_record(&pointer)?;
+        if key != *entry.get_key() {
+            return Err(Error::Corruption {
+                path: file_path(self.dir.as_path(), pointer.file_number),
+                offset: pointer.offset,
+            });
+        }
+
+        Ok(FlexibleUserEntry::from_parts(
+            key,
+            value,
+            EntryKind::Value,
+            entry.get_sequence(),
+        ))
+    }
+}
+
+struct ActiveFile {
+    file_number: u64,
+    file: File,
+    size: u64,
+}
+
+struct Writer {
+    // the file of new values is created by the first append after start
+    active: Option<ActiveFile>,
+    next_file_number: u64,
+}
+
+// Value log:
+// [ vlog_0000001.vlog ... vlog_000000N.vlog ]
+//
+// Large values of flushed entries are appended to the active file, disk tables keep
+// pointers to them, so merges of disk tables don't rewrite values. A file is sealed
+// when it exceeds the size limit, garbage collection rewrites live values of sealed
+// files into storage and removes the files.
+pub struct ValueLog {
+    dir: PathBuf,
+    max_file_size: u64,
+    writer: Mutex<Writer>,
+    files: RwLock<Arc<ValueLogFiles>>,
+}
+
+impl ValueLog {
+    pub fn make_path<P: AsRef<Path>>(storage_path: P) -> PathBuf {
+        storage_path.as_ref().join(VALUE_LOG_DIR)
+    }
+
+    /// Opens all files of the value log, they are sealed until garbage collection.
+    pub fn open<P: AsRef<Path>>(storage_path: P, max_file_size: usize) -> Result<Self> {
+        let dir = ValueLog::make_path(storage_path);
+        fs::create_dir_all(dir.as_path())?;
+
+        let mut files = BTreeMap::new();
+        for entry in fs::read_dir(dir.as_path())? {
+            let path = entry?.path();
+            if let Some(file_number) = extract_file_number(path.as_path()) {
+                files.insert(file_number, Arc::new(File::open(path.as_path())?));
+            }
+        }
+
+        let next_file_number = files.keys().last().map_or(1, |file_number| file_number + 1);
+
+        Ok(Self {
+            max_file_size: max_file_size as u64,
+            writer: Mutex::new(Writer {
+                active: None,
+                next_file_number,
+            }),
+            files: RwLock::new(Arc::new(ValueLogFiles {
+                dir: dir.clone(),
+                files,
+            })),
+            dir,
+        })
+    }
+
+    pub fn files(&self) -> Arc<ValueLogFiles> {
+        self.files.read().unwrap().clone()
+    }
+
+    // the value is written, but isn't durable until sync
+    pub fn append(&self, key: &FlexibleField, value: &FlexibleField) -> Result<ValuePointer> {
+        let mut writer = self.writer.lock().unwrap();
+
+        if writer
+            .active
+            .as_ref()
+            .is_none_or(|active| active.size >= self.max_file_size)
+        {
+            if let Some(sealed) = writer.active.take() {
+                sealed.file.sync_all()?;
+            }
+            writer.active = Some(self.create_file(&mut writer)?);
+        }
+
+        let active = writer.active.as_mut().expect("active file was created");
+        let record = encode_record(key, value)?;
+        let pointer = ValuePointer {
+            file_number: active.file_number,
+            offset: active.size,
+            size: record.len() as u32,
+        };
+
+        active.file.write_all(&record)?;
+        active.size += record.len() as u64;
+
+        Ok(pointer)
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        if let Some(active) = self.writer.lock().unwrap().active.as_ref() {
+            active.file.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    // files which aren't written anymore, from the oldest one
+    pub fn sealed_files(&self) -> Vec<u64> {
+        let active = self
+            .writer
+            .lock()
+            .unwrap()
+            .active
+            .as_ref()
+            .map(|active| active.file_number);
+
+        self.files()
+            .files
+            .keys()
+            .copied()
+            .filter(|file_number| Some(*file_number) != active)
+            .collect()
+    }
+
+    /// Records of the file with their pointers. A broken tail is left by a crash
+    /// before the file was synced, no disk table points there.
+    pub fn read_file(
+        &self,
+        file_number: u64,
+    ) -> Result<Vec<(ValuePointer, FlexibleField, FlexibleField)>> {
+        let path = file_path(self.dir.as_path(), file_number);
+        let data = fs::read(path.as_path())?;
+
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let record = &data[offset..];
+            let Some((key, value)) = decode_record(record) else {
+                warn!("broken record at {} in {}", offset, path.display());
+                break;
+            };
+
+            let size = RECORD_HEADER_SIZE + key.size() + value.size() + CHECKSUM_SIZE;
+            records.push((
+                ValuePointer {
+                    file_number,
+                    offset: offset as u64,
+                    size: size as u32,
+                },
+                key,
+                value,
+            ));
+            offset += size;
+        }
+
+        Ok(records)
+    }
+
+    /// Removes the sealed file, readers which took files before keep reading it.
+    pub fn remove_file(&self, file_number: u64) -> Result<()> {
+        {
+            let mut files = self.files.write().unwrap();
+            let mut updated = ValueLogFiles::clone(&files);
+            updated.files.remove(&file_number);
+            *files = Arc::new(updated);
+        }
+
+        let path = file_path(self.dir.as_path(), file_number);
+        info!("remove value log file {}", path.display());
+        fs::remove_file(path.as_path())?;
+        File::open(self.dir.as_path())?.sync_all()?;
+
+        Ok(())
+    }
+
+    fn create_file(&self, writer: &mut Writer) -> Result<ActiveFile> {
+        let file_number = writer.next_file_number;
+        writer.next_file_number += 1;
+
+        let path = file_path(self.dir.as_path(), file_number);
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(path.as_path())?;
+        File::open(self.dir.as_path())?.sync_all()?;
+
+        let mut files = self.files.write().unwrap();
+        let mut updated = ValueLogFiles::clone(&files);
+        updated
+            .files
+            .insert(file_number, Arc::new(File::open(path.as_path())?));
+        *files = Arc::new(updated);
+
+        Ok(ActiveFile {
+            file_number,
+            file,
+            size: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::core::storage::config::DEFAULT_TEST_TABLES_PATH;
+
+    fn make_field(index: u8, size: usize) -> FlexibleField {
+        FlexibleField::new(vec![index; size])
+    }
+
+    #[test]
+    fn test_append_and_read() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        let value_log = ValueLog::open(tmp_dir.path(), 4096).unwrap();
+        let pointers = (0..8u8)
+            .map(|index| {
+                value_log
+                    .append(&make_field(index, 4), &make_field(index, 1024))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        value_log.sync().unwrap();
+
+        // a file is sealed after it exceeds the limit
+        assert_eq!(value_log.sealed_files(), vec![1]);
+
+        let files = value_log.files();
+        for (index, pointer) in (0..8u8).zip(&pointers) {
+            let pointer = ValuePointer::from(&pointer.serialize().unwrap()).unwrap();
+            let entry = FlexibleUserEntry::from_parts(
+                make_field(index, 4),
+                FlexibleField::new(pointer.serialize().unwrap()),
+                EntryKind::ValuePointer,
+                index as u64,
+            );
+            assert_eq!(
+                files.resolve(entry).unwrap(),
+                FlexibleUserEntry::from_parts(
+                    make_field(index, 4),
+                    make_field(index, 1024),
+                    EntryKind::Value,
+                    index as u64,
+                )
+            );
+        }
+
+        let records = value_log.read_file(1).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[3].0, pointers[3]);
+
+        // the removed file is readable through files taken before
+        value_log.remove_file(1).unwrap();
+        assert!(value_log.sealed_files().is_empty());
+        assert!(files.read_record(&pointers[0]).is_ok());
+        assert!(value_log.files().read_record(&pointers[0]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broken_record() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        let pointer = {
+            let value_log = ValueLog::open(tmp_dir.path(), 4096).unwrap();
+            let pointer = value_log
+                .append(&make_field(1, 4), &make_field(1, 64))
+                .unwrap();
+            value_log
+                .append(&make_field(2, 4), &make_field(2, 64))
+                .unwrap();
+            pointer
+        };
+
+        let path = file_path(ValueLog::make_path(tmp_dir.path()).as_path(), 1);
+        let mut data = fs::read(path.as_path())?;
+        data[pointer.size as usize + 10] ^= 1;
+        fs::write(path.as_path(), data)?;
+
+        let value_log = ValueLog::open(tmp_dir.path(), 4096).unwrap();
+        assert_eq!(value_log.read_file(1).unwrap().len(), 1);
+
+        let mut broken = pointer;
+        broken.offset += pointer.size as u64;
+        assert!(matches!(
+            value_log.files().read_record(&broken),
+            Err(Error::Corruption { .. })
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+
+use crate::core::disk_table::local::block::{
+    checksum::{checksum, CHECKSUM_SIZE},
+    data_block_buffer::ENTRY_METADATA_SIZE,
+};
+use crate::core::entry::flexible_user_entry::FlexibleUserEntry;
+use crate::core::marshal::{read_u32, write_u32};
+use crate::core::mem_table::MemoryTable;
+use crate::errors::Result;
+
+const WAL_DIR: &str = "wal";
+const WAL_EXTENSION: &str = "log";
+const RECORD_HEADER_SIZE: usize = size_of::<u32>();
+const RECORD_COUNT_SIZE: usize = size_of::<u32>();
+
+// Log file:
+// [ record_size record ... record_size record ]
+//
+// Record:
+// [ count_entries entry1 ... entryN checksum ]
+//
+// Every record keeps serialized entries of one write, checksum is CRC32C of
+// all previous bytes with record_size. A record is written with a single write call
+// and replay stops at a truncated or broken record, so a crash can only lose
+// the tail of the active log and never a part of write.
+pub struct WriteAheadLog {
+    wal_dir: PathBuf,
+    log_number: u64,
+    active: File,
+    // logs whose entries are in the memory table, but not in disk tables yet
+    sealed: Vec<PathBuf>,
+}
+
+impl WriteAheadLog {
+    pub fn make_path<P: AsRef<Path>>(storage_path: P) -> PathBuf {
+        storage_path.as_ref().join(WAL_DIR)
+    }
+
+    /// Replays every existing log into `mem_table` and starts a new active log.
+    /// The replayed logs stay on disk until the memory table is flushed.
+    pub fn open<P: AsRef<Path>>(storage_path: P, mem_table: &mut MemoryTable) -> Result<Self> {
+        let wal_dir = WriteAheadLog::make_path(storage_path);
+        fs::create_dir_all(wal_dir.as_path())?;
+
+        let mut logs = fs::read_dir(wal_dir.as_path())?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let log_number = WriteAheadLog::extract_log_number(path.as_path())?;
+                Some((log_number, path))
+            })
+            .collect::<Vec<_>>();
+        logs.sort();
+
+        for (_log_number, path) in &logs {
+            let replayed = WriteAheadLog::replay(path.as_path(), mem_table)?;
+            debug!("replayed {} entries from {}", replayed, path.display());
+        }
+
+        let log_number = logs.last().map_or(1, |(log_number, _)| log_number + 1);
+        let active = WriteAheadLog::create_log(wal_dir.as_path(), log_number)?;
+
+        Ok(Self {
+            wal_dir,
+            log_number,
+            active,
+            sealed: logs.into_iter().map(|(_log_number, path)| path).collect(),
+        })
+    }
+
+    // entries are logged as one record
+    pub fn append(&mut self, entries: &[FlexibleUserEntry]) -> Result<()> {
+        let record_size = RECORD_COUNT_SIZE
+            + entries
+                .iter()
+                .map(|entry| ENTRY_METADATA_SIZE as usize + entry.size())
+                .sum::<usize>()
+            + CHECKSUM_SIZE;
+        let mut buffer = vec![0u8; RECORD_HEADER_SIZE + record_size];
+
+        let mut offset = write_u32(&mut buffer, record_size as u32)?;
+        offset += write_u32(&mut buffer[offset..], entries.len() as u32)?;
+        for entry in entries {
+            offset += entry.serialize_to(&mut buffer[offset..])? as usize;
+        }
+        let crc = checksum(&buffer[..offset]);
+        write_u32(&mut buffer[offset..], crc)?;
+
+        self.active.write_all(&buffer)?;
+
+        Ok(())
+    }
+
+    // written records survive a power loss
+    pub fn sync(&mut self) -> Result<()> {
+        self.active.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Seals the active log and continues writing into a new one.
+    pub fn rotate(&mut self) -> Result<()> {
+        self.active.sync_all()?;
+
+        let sealed_path = WriteAheadLog::log_path(self.wal_dir.as_path(), self.log_number);
+
+        self.log_number += 1;
+        self.active = WriteAheadLog::create_log(self.wal_dir.as_path(), self.log_number)?;
+        self.sealed.push(sealed_path);
+
+        Ok(())
+    }
+
+    /// Removes sealed logs. Must be called only after their entries were durably
+    /// written to a disk table.
+    pub fn remove_sealed(&mut self) -> Result<()> {
+        for path in self.sealed.drain(..) {
+            fs::remove_file(path.as_path())?;
+        }
+
+        File::open(self.wal_dir.as_path())?.sync_all()?;
+
+        Ok(())
+    }
+
+    fn replay(path: &Path, mem_table: &mut MemoryTable) -> Result<usize> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        let mut offset = 0;
+        let mut replayed = 0;
+
+        while offset < data.len() {
+            if offset + RECORD_HEADER_SIZE > data.len() {
+                warn!("truncated record header in {}", path.display());
+                break;
+            }
+
+            let record_size = read_u32(&data[offset..])? as usize;
+            let record_start = offset;
+            offset += RECORD_HEADER_SIZE;
+
+            if offset + record_size > data.len() {
+                warn!("truncated record in {}", path.display());
+                break;
+            }
+
+            // a zero-filled or garbage tail isn't a record
+            let checksum_offset = offset + record_size;
+            if record_size < RECORD_COUNT_SIZE + CHECKSUM_SIZE
+                || read_u32(&data[checksum_offset - CHECKSUM_SIZE..])?
+                    != checksum(&data[record_start..checksum_offset - CHECKSUM_SIZE])
+            {
+                warn!("broken record in {}", path.display());
+                break;
+            }
+
+            let record = &data[offset..checksum_offset - CHECKSUM_SIZE];
+            let count_entries = read_u32(record)?;
+
+            let mut entry_offset = RECORD_COUNT_SIZE;
+            for _ in 0..count_entries {
+                let entry = FlexibleUserEntry::from(&record[entry_offset..]);
+                entry_offset += ENTRY_METADATA_SIZE as usize + entry.size();
+
+                mem_table.append(&entry);
+                replayed += 1;
+            }
+            offset += record_size;
+        }
+
+        Ok(replayed)
+    }
+
+    fn create_log(wal_dir: &Path, log_number: u64) -> Result<File> {
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(WriteAheadLog::log_path(wal_dir, log_number))?;
+
+        File::open(wal_dir)?.sync_all()?;
+
+        Ok(log)
+    }
+
+    fn log_path(wal_dir: &Path, log_number: u64) -> PathBuf {
+        wal_dir.join(format!("wal_{:07}.{}", log_number, WAL_EXTENSION))
+    }
+
+    fn extract_log_number(path: &Path) -> Option<u64> {
+        // wal_0000012.log
+
+        if path.extension()? != WAL_EXTENSION {
+            return None;
+        }
+
+        let name = path.file_stem()?.to_str()?;
+        name.strip_prefix("wal_")?.parse::<u64>().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::{self, Write};
+
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::core::field::{Field, FlexibleField};
+    use crate::core::storage::config::DEFAULT_TEST_TABLES_PATH;
+
+    fn make_entry(index: u8) -> FlexibleUserEntry {
+        FlexibleUserEntry::new(
+            FlexibleField::new(vec![index, 1, 2]),
+            FlexibleField::new(vec![index, 10, 20]),
+        )
+    }
+
+    #[test]
+    fn test_replay() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        {
+            let mut mem_table = MemoryTable::new(16);
+            let mut wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+            assert_eq!(mem_table.current_size(), 0);
+
+            for index in 0..4 {
+                wal.append(&[make_entry(index)]).unwrap();
+            }
+        }
+
+        let mut mem_table = MemoryTable::new(16);
+        let _wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+
+        assert_eq!(mem_table.current_size(), 4);
+        for index in 0..4 {
+            let expected = make_entry(index);
+            assert_eq!(
+                mem_table.get_value(expected.get_key()),
+                Some(expected.get_value().clone())
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_tail() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        {
+            let mut mem_table = MemoryTable::new(16);
+            let mut wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+            wal.append(&[make_entry(1)]).unwrap();
+            wal.append(&[make_entry(2)]).unwrap();
+
+            let log_path = WriteAheadLog::log_path(wal.wal_dir.as_path(), wal.log_number);
+            let mut log = OpenOptions::new().append(true).open(log_path)?;
+            log.write_all(&64u32.to_le_bytes())?;
+            log.write_all(&[1, 2, 3])?;
+        }
+
+        let mut mem_table = MemoryTable::new(16);
+        let _wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+
+        assert_eq!(mem_table.current_size(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_batch() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        {
+            let mut mem_table = MemoryTable::new(16);
+            let mut wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+            wal.append(&[make_entry(1)]).unwrap();
+            wal.append(&[make_entry(2), make_entry(3), make_entry(4)])
+                .unwrap();
+
+            // crash in the middle of the last record
+            let log_path = WriteAheadLog::log_path(wal.wal_dir.as_path(), wal.log_number);
+            let log = OpenOptions::new().write(true).open(log_path)?;
+            let size = log.metadata()?.len();
+            log.set_len(size - 4)?;
+        }
+
+        let mut mem_table = MemoryTable::new(16);
+        let _wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+
+        assert_eq!(mem_table.current_size(), 1);
+        assert_eq!(mem_table.get_value(make_entry(2).get_key()), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broken_tail() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        {
+            let mut mem_table = MemoryTable::new(16);
+            let mut wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+            wal.append(&[make_entry(1)]).unwrap();
+            wal.append(&[make_entry(2), make_entry(3)]).unwrap();
+
+            // the size of tail fits the log, but its bytes weren't written
+            let log_path = WriteAheadLog::log_path(wal.wal_dir.as_path(), wal.log_number);
+            let mut log = OpenOptions::new().append(true).open(log_path)?;
+            log.write_all(&32u32.to_le_bytes())?;
+            log.write_all(&[0u8; 32])?;
+            log.write_all(&[0u8; 64])?;
+        }
+
+        let mut mem_table = MemoryTable::new(16);
+        let _wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+        assert_eq!(mem_table.current_size(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broken_batch() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        {
+            let mut mem_table = MemoryTable::new(16);
+            let mut wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+            wal.append(&[make_entry(1)]).unwrap();
+            wal.append(&[make_entry(2), make_entry(3), make_entry(4)])
+                .unwrap();
+
+            // a byte of the last entry of batch is lost
+            let log_path = WriteAheadLog::log_path(wal.wal_dir.as_path(), wal.log_number);
+            let mut data = fs::read(log_path.as_path())?;
+            let offset = data.len() - CHECKSUM_SIZE - 1;
+            data[offset] ^= 0xff;
+            fs::write(log_path.as_path(), data)?;
+        }
+
+        let mut mem_table = MemoryTable::new(16);
+        let _wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+
+        assert_eq!(mem_table.current_size(), 1);
+        assert_eq!(mem_table.get_value(make_entry(2).get_key()), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_sealed() -> io::Result<()> {
+        let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+        {
+            let mut mem_table = MemoryTable::new(16);
+            let mut wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+            wal.append(&[make_entry(1)]).unwrap();
+
+            wal.rotate().unwrap();
+            wal.remove_sealed().unwrap();
+
+            wal.append(&[make_entry(2)]).unwrap();
+        }
+
+        let mut mem_table = MemoryTable::new(16);
+        let _wal = WriteAheadLog::open(tmp_dir.path(), &mut mem_table).unwrap();
+
+        assert_eq!(mem_table.current_size(), 1);
+        assert_eq!(mem_table.get_value(make_entry(1).get_key()), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use crate::core::{entry::flexible_user_entry::FlexibleUserEntry, field::FlexibleField};
+
+// Puts and deletes which are applied atomically by `Storage::write`.
+// Later writes of the same key in batch win.
+#[derive(Default, Clone)]
+pub struct WriteBatch {
+    entries: Vec<FlexibleUserEntry>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, entry: &FlexibleUserEntry) {
+        self.entries.push(entry.clone());
+    }
+
+    pub fn delete(&mut self, key: &FlexibleField) {
+        self.entries
+            .push(FlexibleUserEntry::new_tombstone(key.clone()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn entries(&self) -> &[FlexibleUserEntry] {
+        &self.entries
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use nix::errno::Errno;
 
 #[derive(Debug, PartialEq)]
@@ -5,6 +7,8 @@ pub enum Error {
     IO(String),
     InvalidData(String),
     LogicError(String),
+    // checksum mismatch of the data at `offset` in file
+    Corruption { path: PathBuf, offset: u64 },
 }
 
 impl std::error::Error for Error {}
@@ -15,6 +19,9 @@ impl std::fmt::Display for Error {
             Error::InvalidData(msg) => write!(f, "Invalid data: {msg}"),
             Error::IO(msg) => write!(f, "IO error: {msg}"),
             Error::LogicError(msg) => write!(f, "Logic error: {msg}"),
+            Error::Corruption { path, offset } => {
+                write!(f, "Corruption: path={}, offset={offset}", path.display())
+            }
         }
     }
 }
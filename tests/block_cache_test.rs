@@ -0,0 +1,138 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    disk_table::disk_tables_shard::DiskTablesShards,
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::config::{StorageConfig, DEFAULT_DATA_BLOCK_SIZE, DEFAULT_TEST_TABLES_PATH},
+};
+
+fn fill_level(shards: &DiskTablesShards, tmp_dir: &std::path::Path, count_tables: u32) {
+    // 2 data blocks in every table
+    for table_index in 0..count_tables {
+        let disk_table_path = tmp_dir.join(format!("segment_{}_1.bin", table_index));
+        let index_table_path = tmp_dir.join(format!("segment_{}_1.idx", table_index));
+
+        let mut builder =
+            shards.new_disk_table_builder(disk_table_path.as_path(), index_table_path.as_path());
+        for index in 0..4u32 {
+            let key = table_index * 4 + index;
+            builder.append_entry(&FlexibleUserEntry::new(
+                make_key(key),
+                make_value(key, 0, 1024),
+            ));
+        }
+        shards.put_disk_table_by_level(1, builder.build().unwrap());
+    }
+}
+
+#[test]
+fn test_blocks_are_cached() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let shards = DiskTablesShards::new();
+    fill_level(&shards, tmp_dir.path(), 2);
+
+    assert!(shards.block_cache().is_empty());
+
+    for key in 0..8u32 {
+        assert_eq!(
+            shards.get(&make_key(key)).unwrap(),
+            Some(make_value(key, 0, 1024))
+        );
+    }
+    assert_eq!(shards.block_cache().len(), 4);
+    assert_eq!(shards.block_cache().usage(), 4 * DEFAULT_DATA_BLOCK_SIZE);
+
+    // served from cache
+    for key in 0..8u32 {
+        assert_eq!(
+            shards.get(&make_key(key)).unwrap(),
+            Some(make_value(key, 0, 1024))
+        );
+    }
+    assert_eq!(shards.block_cache().len(), 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_capacity_of_block_cache() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let mut config = StorageConfig::default_config();
+    config.block_cache_size = 3 * DEFAULT_DATA_BLOCK_SIZE;
+
+    let shards = DiskTablesShards::from_config(config);
+    fill_level(&shards, tmp_dir.path(), 2);
+
+    for key in 0..8u32 {
+        assert_eq!(
+            shards.get(&make_key(key)).unwrap(),
+            Some(make_value(key, 0, 1024))
+        );
+    }
+    assert_eq!(shards.block_cache().len(), 3);
+    assert_eq!(shards.block_cache().usage(), 3 * DEFAULT_DATA_BLOCK_SIZE);
+
+    // disabled cache
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let mut config = StorageConfig::default_config();
+    config.block_cache_size = 0;
+
+    let shards = DiskTablesShards::from_config(config);
+    fill_level(&shards, tmp_dir.path(), 2);
+
+    for key in 0..8u32 {
+        assert_eq!(
+            shards.get(&make_key(key)).unwrap(),
+            Some(make_value(key, 0, 1024))
+        );
+    }
+    assert!(shards.block_cache().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_removed_tables_are_evicted() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let shards = DiskTablesShards::new();
+    fill_level(&shards, tmp_dir.path(), 2);
+
+    for key in 0..8u32 {
+        assert_eq!(
+            shards.get(&make_key(key)).unwrap(),
+            Some(make_value(key, 0, 1024))
+        );
+    }
+
+    let disk_table_path = tmp_dir.path().join("segment_2_2.bin");
+    let index_table_path = tmp_dir.path().join("segment_2_2.idx");
+
+    // merge reads all blocks of the level through cache
+    let merged = shards
+        .merge_level(1, disk_table_path.as_path(), index_table_path.as_path())
+        .unwrap();
+    assert!(!shards.block_cache().is_empty());
+
+    shards.remove_level_and_put(1, 2, merged).unwrap();
+    assert!(shards.block_cache().is_empty());
+
+    for key in 0..8u32 {
+        assert_eq!(
+            shards.get(&make_key(key)).unwrap(),
+            Some(make_value(key, 0, 1024))
+        );
+    }
+    // the merged table packs 3 entries in block
+    assert_eq!(shards.block_cache().len(), 3);
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::io;
+
+use tempfile::Builder;
+
+use kvs::core::{
+    disk_table::local::{block::compression::Compression, disk_table_builder::DiskTableBuilder},
+    entry::{flexible_user_entry::FlexibleUserEntry, user_entry::EntryKind},
+    field::{Field, FlexibleField},
+    storage::config::DEFAULT_TEST_TABLES_PATH,
+};
+
+fn make_key(index: u32) -> Vec<u8> {
+    format!("key-{:08}", index).into_bytes()
+}
+
+fn make_value(index: u32) -> Vec<u8> {
+    vec![index as u8; 32 + index as usize % 7]
+}
+
+#[test]
+fn test_borrowed_entries_of_block() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    for (name, compression) in [
+        ("segment_1_1", Compression::None),
+        ("segment_2_1", Compression::Lz4),
+    ] {
+        let mut builder = DiskTableBuilder::new(
+            tmp_dir.path().join(format!("{}.bin", name)),
+            tmp_dir.path().join(format!("{}.idx", name)),
+        );
+        builder.set_compression(compression);
+        for index in 0..256u32 {
+            let mut entry = FlexibleUserEntry::new(
+                FlexibleField::new(make_key(index)),
+                FlexibleField::new(make_value(index)),
+            );
+            entry.set_sequence(index as u64 + 1);
+            builder.append_entry(&entry);
+        }
+        let reader = builder.build().unwrap();
+
+        let mut index = 0u32;
+        let mut block_index = 0;
+        while let Some(block) = reader.read_block(block_index).unwrap() {
+            let first = index;
+
+            let mut iter = block.iter();
+            while let Some(entry) = iter.next() {
+                assert_eq!(entry.key, make_key(index).as_slice());
+                assert_eq!(entry.value, make_value(index).as_slice());
+                assert_eq!(entry.kind, EntryKind::Value);
+                assert_eq!(entry.sequence, index as u64 + 1);
+                index += 1;
+            }
+
+            // seek continues iteration from the found entry
+            let middle = (first + index) / 2;
+            let mut iter = block.iter();
+            let entry = iter.seek(&make_key(middle), u64::MAX).unwrap();
+            assert_eq!(entry.key, make_key(middle).as_slice());
+            if middle + 1 < index {
+                assert_eq!(iter.next().unwrap().key, make_key(middle + 1).as_slice());
+            }
+
+            // the version is newer than requested
+            let entry = block
+                .iter()
+                .seek(&make_key(middle), middle as u64)
+                .map(|entry| entry.to_entry());
+            assert_ne!(
+                entry.map(|entry: FlexibleUserEntry| entry.get_key().clone()),
+                Some(FlexibleField::new(make_key(middle)))
+            );
+
+            block_index += 1;
+        }
+
+        assert!(block_index > 1);
+        assert_eq!(index, 256);
+    }
+
+    Ok(())
+}
@@ -30,7 +30,7 @@ fn test_read_data_blocks_by_index() -> io::Result<()> {
     let reader = builder.build().unwrap();
 
     for index in 0..16u32 {
-        let r = reader.read_block(index as usize);
+        let r = reader.read_block(index as usize).unwrap();
         assert!(r.is_some());
         let block = r.unwrap();
 
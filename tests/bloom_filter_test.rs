@@ -0,0 +1,90 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    disk_table::local::disk_table_builder::DiskTableBuilder,
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+#[test]
+fn test_bloom_filter_skips_tables() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_bloom_filter_skips_tables");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 16;
+
+    let count = 128u32;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 0..count {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 0, 4),
+                ))
+                .unwrap();
+        }
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    let stats = table.bloom_filter_stats();
+    assert_eq!((stats.hits(), stats.misses()), (0, 0));
+
+    for index in 0..count {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, 0, 4))
+        );
+    }
+    // every key is found in some table
+    assert!(stats.hits() >= count as u64);
+
+    let misses = stats.misses();
+    let hits = stats.hits();
+    for index in count..2 * count {
+        assert_eq!(table.get(&make_key(index)).unwrap(), None);
+    }
+    assert!(stats.misses() > misses);
+    // a few false positives are possible
+    assert!(stats.hits() - hits < count as u64 / 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_disk_table_without_bloom_filter() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let disk_table_path = tmp_dir.path().join("segment_1_1.bin");
+    let index_table_path = tmp_dir.path().join("segment_1_1.idx");
+
+    let mut builder = DiskTableBuilder::new(disk_table_path, index_table_path);
+    builder.set_bloom_bits_per_key(0);
+    for index in 0..64u32 {
+        builder.append_entry(&FlexibleUserEntry::new(
+            make_key(index),
+            make_value(index, 0, 4),
+        ));
+    }
+    let reader = builder.build().unwrap();
+
+    assert!(reader.may_contain(&make_key(1000)));
+    assert_eq!(reader.read(&make_key(1000)).unwrap(), None);
+    assert_eq!(
+        reader.read(&make_key(10)).unwrap(),
+        Some(make_value(10, 0, 4))
+    );
+
+    Ok(())
+}
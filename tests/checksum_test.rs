@@ -0,0 +1,280 @@
+mod common;
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::{
+    core::{
+        disk_table::{
+            disk_tables_shard::DiskTablesShards,
+            local::{
+                block::{
+                    compression::Compression, data_block_buffer::BLOCK_TRAILER_SIZE,
+                    footer::INDEX_FOOTER_SIZE,
+                },
+                disk_table_builder::DiskTableBuilder,
+            },
+        },
+        entry::flexible_user_entry::FlexibleUserEntry,
+        storage::{
+            config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+            ordered_storage::OrderedStorage,
+            storage::Storage,
+        },
+    },
+    errors::Error,
+};
+
+fn flip_byte(path: &Path, offset: u64) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+    let mut byte = [0u8; 1];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut byte)?;
+
+    byte[0] ^= 0xff;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(&byte)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+fn build_disk_table(disk_table_path: &Path, index_table_path: &Path) {
+    let mut builder = DiskTableBuilder::new(disk_table_path, index_table_path);
+    // blocks have the same size on disk
+    builder.set_compression(Compression::None);
+    for index in 0..128u32 {
+        builder.append_entry(&FlexibleUserEntry::new(
+            make_key(index),
+            make_value(index, 0, 64),
+        ));
+    }
+    builder.build().unwrap();
+}
+
+#[test]
+fn test_corrupted_data_block() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let disk_table_path = tmp_dir.path().join("segment_1_1.bin");
+    let index_table_path = tmp_dir.path().join("segment_1_1.idx");
+
+    build_disk_table(disk_table_path.as_path(), index_table_path.as_path());
+
+    // the value of the first entry in the second block
+    let block_size = (4096 + BLOCK_TRAILER_SIZE) as u64;
+    flip_byte(disk_table_path.as_path(), block_size + 40)?;
+
+    let reader = DiskTableBuilder::from(disk_table_path.as_path(), index_table_path.as_path())
+        .build()
+        .unwrap();
+
+    // the first block is fine
+    assert_eq!(
+        reader.read(&make_key(0)).unwrap(),
+        Some(make_value(0, 0, 64))
+    );
+
+    let key = make_key(60);
+    assert_eq!(
+        reader.read(&key),
+        Err(Error::Corruption {
+            path: disk_table_path.clone(),
+            offset: block_size,
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_truncated_data_table() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let disk_table_path = tmp_dir.path().join("segment_1_1.bin");
+    let index_table_path = tmp_dir.path().join("segment_1_1.idx");
+
+    build_disk_table(disk_table_path.as_path(), index_table_path.as_path());
+    let reader = DiskTableBuilder::from(disk_table_path.as_path(), index_table_path.as_path())
+        .build()
+        .unwrap();
+
+    // the file ends within the second block, blocks are read by aligned pages
+    let block_size = (4096 + BLOCK_TRAILER_SIZE) as u64;
+    OpenOptions::new()
+        .write(true)
+        .open(disk_table_path.as_path())?
+        .set_len(2 * 4096)?;
+
+    assert_eq!(
+        reader.read(&make_key(0)).unwrap(),
+        Some(make_value(0, 0, 64))
+    );
+    assert_eq!(
+        reader.read(&make_key(60)),
+        Err(Error::Corruption {
+            path: disk_table_path.clone(),
+            offset: block_size,
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_corrupted_index_table() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let disk_table_path = tmp_dir.path().join("segment_1_1.bin");
+    let index_table_path = tmp_dir.path().join("segment_1_1.idx");
+
+    build_disk_table(disk_table_path.as_path(), index_table_path.as_path());
+
+    // the filter section
+    flip_byte(index_table_path.as_path(), 16)?;
+
+    let result =
+        DiskTableBuilder::from(disk_table_path.as_path(), index_table_path.as_path()).build();
+    assert_eq!(
+        result.err(),
+        Some(Error::Corruption {
+            path: index_table_path.clone(),
+            offset: 0,
+        })
+    );
+
+    // the count of index entries
+    build_disk_table(
+        tmp_dir.path().join("segment_2_1.bin").as_path(),
+        tmp_dir.path().join("segment_2_1.idx").as_path(),
+    );
+    let index_table_path = tmp_dir.path().join("segment_2_1.idx");
+    let size = fs::metadata(index_table_path.as_path())?.len();
+    flip_byte(
+        index_table_path.as_path(),
+        size - INDEX_FOOTER_SIZE as u64 - 1,
+    )?;
+
+    let result = DiskTableBuilder::from(
+        tmp_dir.path().join("segment_2_1.bin").as_path(),
+        index_table_path.as_path(),
+    )
+    .build();
+    assert!(matches!(result.err(), Some(Error::Corruption { .. })));
+
+    Ok(())
+}
+
+#[test]
+fn test_corruption_from_storage_get() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_corruption_from_storage_get");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 4;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 0..4u32 {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 0, 64),
+                ))
+                .unwrap();
+        }
+    }
+
+    let segment_dir = table_path.join("segment");
+    let disk_table_path = fs::read_dir(segment_dir.as_path())?
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().unwrap() == "bin")
+        .unwrap();
+    flip_byte(disk_table_path.as_path(), 40)?;
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    assert_eq!(
+        table.get(&make_key(0)),
+        Err(Error::Corruption {
+            path: disk_table_path,
+            offset: 0,
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_corruption_from_storage_scan() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_corruption_from_storage_scan");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 4;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 0..4u32 {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 0, 64),
+                ))
+                .unwrap();
+        }
+    }
+
+    let segment_dir = table_path.join("segment");
+    let disk_table_path = fs::read_dir(segment_dir.as_path())?
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().unwrap() == "bin")
+        .unwrap();
+    flip_byte(disk_table_path.as_path(), 40)?;
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    let mut it = table.scan(make_key(0)..make_key(4)).unwrap();
+    assert_eq!(
+        it.next(),
+        Some(Err(Error::Corruption {
+            path: disk_table_path,
+            offset: 0,
+        }))
+    );
+    assert_eq!(it.next(), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_corruption_from_merge() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let shards = DiskTablesShards::new();
+
+    for table_id in 1..=2 {
+        let disk_table_path = tmp_dir.path().join(format!("segment_{}_1.bin", table_id));
+        let index_table_path = tmp_dir.path().join(format!("segment_{}_1.idx", table_id));
+        build_disk_table(disk_table_path.as_path(), index_table_path.as_path());
+        if table_id == 2 {
+            flip_byte(disk_table_path.as_path(), 40)?;
+        }
+
+        let reader = DiskTableBuilder::from(disk_table_path.as_path(), index_table_path.as_path())
+            .build()
+            .unwrap();
+        shards.put_disk_table_by_level(1, reader);
+    }
+
+    // the merged table isn't left on disk
+    let disk_table_path = tmp_dir.path().join("segment_3_2.bin");
+    let index_table_path = tmp_dir.path().join("segment_3_2.idx");
+    let merged = shards.merge_level(1, disk_table_path.as_path(), index_table_path.as_path());
+    assert!(matches!(merged, Err(Error::Corruption { .. })));
+    assert!(!disk_table_path.exists());
+    assert!(!index_table_path.exists());
+
+    Ok(())
+}
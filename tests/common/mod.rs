@@ -0,0 +1,69 @@
+// helpers which are shared by tests, every test uses only a part of them
+#![allow(dead_code)]
+
+use std::{fs, io, path::Path};
+
+use kvs::core::{
+    disk_table::local::{
+        block::properties::TableProperties, disk_table_builder::DiskTableBuilder,
+        reader_local_disk_table::ReaderDiskTablePtr,
+    },
+    entry::flexible_user_entry::FlexibleUserEntry,
+    field::{Field, FlexibleField},
+    storage::{config::StorageConfig, ordered_storage::OrderedStorage, storage::Storage},
+};
+
+pub fn make_key(index: u32) -> FlexibleField {
+    FlexibleField::new(index.to_be_bytes())
+}
+
+// values aren't compressed, so sizes of tables are predictable
+pub fn make_value(index: u32, version: u32, size: usize) -> FlexibleField {
+    let mut state = ((index as u64) << 32 | version as u64) + 1;
+    FlexibleField::new(
+        (0..size)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+// projections of properties and readers of tables in storage
+pub fn read_tables<T>(
+    table_path: &Path,
+    project: impl Fn(&TableProperties, &ReaderDiskTablePtr) -> T,
+) -> io::Result<Vec<T>> {
+    let mut tables = Vec::new();
+    for entry in fs::read_dir(table_path.join("segment"))? {
+        let path = entry?.path();
+        if path.extension().unwrap() != "bin" {
+            continue;
+        }
+
+        let reader = DiskTableBuilder::from(path.clone(), path.with_extension("idx"))
+            .build()
+            .unwrap();
+        tables.push(project(reader.properties().unwrap(), &reader));
+    }
+
+    Ok(tables)
+}
+
+// every round is a new session of storage, it ends with a flush
+pub fn write_entries(table_path: &Path, config: &StorageConfig, rounds: u32, value_size: usize) {
+    for round in 0..rounds {
+        let table = OrderedStorage::new(table_path, config.clone());
+        for index in (round * 64)..(round * 64 + 64) {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 0, value_size),
+                ))
+                .unwrap();
+        }
+    }
+}
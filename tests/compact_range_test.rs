@@ -0,0 +1,111 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value, read_tables};
+use kvs::core::{
+    disk_table::{
+        compaction::CompactionSummary,
+        disk_tables_shard::{SEGMENTS_MAX_LEVEL, SEGMENTS_MIN_LEVEL},
+    },
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+fn make_config() -> StorageConfig {
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 16;
+    config.target_table_size = 8 * 1024;
+    config.level_base_size = 32 * 1024;
+    config
+}
+
+#[test]
+fn test_compact_range_after_deletes() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_compact_range_after_deletes");
+    let config = make_config();
+
+    for round in 0..4u32 {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in (round * 128)..(round * 128 + 128) {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 0, 256),
+                ))
+                .unwrap();
+        }
+    }
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 100..200u32 {
+            table.delete(&make_key(index)).unwrap();
+        }
+
+        // deletes are still in the memory table
+        let summary = table.compact_range(&make_key(100), &make_key(199)).unwrap();
+        assert!(summary.tables_read > 0 && summary.tables_written > 0);
+        assert!(summary.bytes_read > summary.bytes_written);
+
+        for index in 0..512u32 {
+            let value = (!(100..200).contains(&index)).then(|| make_value(index, 0, 256));
+            assert_eq!(table.get(&make_key(index)).unwrap(), value);
+        }
+        assert_eq!(table.iter().unwrap().count(), 412);
+    }
+
+    // tables of the range are in the last level without deleted keys
+    let (start, end) = (make_key(100), make_key(199));
+    let tables = read_tables(table_path.as_path(), |properties, _reader| {
+        (
+            properties.level,
+            properties.smallest_key.clone(),
+            properties.largest_key.clone(),
+            properties.count_tombstones,
+        )
+    })?;
+    for (level, smallest, largest, count_tombstones) in tables {
+        if smallest <= end && start <= largest {
+            assert_eq!(level, SEGMENTS_MAX_LEVEL);
+        }
+        assert_eq!(count_tombstones, 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compact_range_without_tables() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_compact_range_without_tables");
+
+    let table = OrderedStorage::new(table_path.as_path(), make_config());
+    assert_eq!(
+        table.compact_range(&make_key(0), &make_key(10)),
+        Ok(CompactionSummary::default())
+    );
+    assert!(table.compact_range(&make_key(10), &make_key(0)).is_err());
+
+    // a single flushed table goes through every level down to the last one
+    table
+        .put(&FlexibleUserEntry::new(make_key(5), make_value(5, 0, 256)))
+        .unwrap();
+    let summary = table.compact_range(&make_key(0), &make_key(10)).unwrap();
+    let count_merges = (SEGMENTS_MAX_LEVEL - SEGMENTS_MIN_LEVEL) as usize;
+    assert_eq!(summary.tables_read, count_merges);
+    assert_eq!(summary.tables_written, count_merges);
+    assert_eq!(
+        table.get(&make_key(5)).unwrap(),
+        Some(make_value(5, 0, 256))
+    );
+
+    Ok(())
+}
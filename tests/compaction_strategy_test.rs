@@ -0,0 +1,358 @@
+mod common;
+
+use std::{io, thread, time::Duration};
+
+use tempfile::Builder;
+
+use common::{make_key, make_value, read_tables, write_entries};
+use kvs::core::{
+    disk_table::{
+        compaction::{Compaction, CompactionStrategy, CompactionStyle, LevelTables},
+        disk_tables_shard::{DiskTablesShards, SEGMENTS_MAX_LEVEL, SEGMENTS_MIN_LEVEL},
+        local::disk_table_builder::DiskTableBuilder,
+    },
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+#[test]
+fn test_size_tiered_compaction() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_size_tiered_compaction");
+
+    let mut config = StorageConfig::default_config();
+    config.compaction_style = CompactionStyle::SizeTiered;
+    config.disk_tables_limit_by_level = 2;
+    config.mem_table_size = 4;
+
+    write_entries(table_path.as_path(), &config, 8, 16);
+
+    // a tier is merged into one table of the next tier
+    let tables = read_tables(table_path.as_path(), |properties, _reader| {
+        (properties.level, properties.source_tables.len())
+    })?;
+    assert!(tables.iter().any(|table| table.0 > SEGMENTS_MIN_LEVEL));
+    for (level, count_sources) in tables {
+        if level == SEGMENTS_MIN_LEVEL + 1 {
+            assert_eq!(count_sources, config.disk_tables_limit_by_level);
+        }
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config);
+    for index in 0..512u32 {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, 0, 16))
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_fifo_compaction() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_fifo_compaction");
+
+    let mut config = StorageConfig::default_config();
+    config.compaction_style = CompactionStyle::Fifo;
+    config.mem_table_size = 16;
+    config.fifo_max_size = 128 * 1024;
+
+    write_entries(table_path.as_path(), &config, 8, 1024);
+
+    // tables aren't merged
+    let tables = read_tables(table_path.as_path(), |properties, _reader| {
+        (properties.level, properties.source_tables.len())
+    })?;
+    assert!(tables.iter().all(|table| *table == (SEGMENTS_MIN_LEVEL, 0)));
+
+    let table = OrderedStorage::new(table_path.as_path(), config);
+    assert_eq!(table.get(&make_key(0)).unwrap(), None);
+    assert_eq!(
+        table.get(&make_key(511)).unwrap(),
+        Some(make_value(511, 0, 1024))
+    );
+    assert!(table.iter().unwrap().count() < 512);
+
+    Ok(())
+}
+
+// merges the first level straight into the last one
+struct FirstToLastStrategy;
+
+impl CompactionStrategy for FirstToLastStrategy {
+    fn pick(&self, levels: &[LevelTables], _config: &StorageConfig) -> Option<Compaction> {
+        levels
+            .iter()
+            .find(|level| level.level == SEGMENTS_MIN_LEVEL && level.disk_tables.len() > 1)
+            .map(|level| {
+                Compaction::new(
+                    SEGMENTS_MIN_LEVEL,
+                    SEGMENTS_MAX_LEVEL,
+                    level.disk_tables.clone(),
+                )
+            })
+    }
+}
+
+#[test]
+fn test_custom_compaction_strategy() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let mut shards = DiskTablesShards::from_config(StorageConfig::default_config());
+    shards.set_compaction_strategy(Box::new(FirstToLastStrategy));
+
+    for table_id in 1..=2u32 {
+        let mut builder = DiskTableBuilder::new(
+            tmp_dir.path().join(format!("segment_{}_1.bin", table_id)),
+            tmp_dir.path().join(format!("segment_{}_1.idx", table_id)),
+        );
+        for index in (table_id * 10)..(table_id * 10 + 10) {
+            builder.append_entry(&FlexibleUserEntry::new(
+                make_key(index),
+                make_value(index, 0, 16),
+            ));
+        }
+        shards.put_disk_table_by_level(SEGMENTS_MIN_LEVEL, builder.build().unwrap());
+    }
+
+    let compaction = shards.pick_compaction().unwrap();
+    let merged = shards
+        .compact(&compaction, || {
+            (
+                tmp_dir.path().join("segment_3_3.bin"),
+                tmp_dir.path().join("segment_3_3.idx"),
+            )
+        })
+        .unwrap();
+    shards.apply_compaction(&compaction, merged).unwrap();
+    assert!(shards.pick_compaction().is_none());
+
+    let levels = shards.level_tables();
+    assert_eq!(levels.len(), 1);
+    assert_eq!(levels[0].level, SEGMENTS_MAX_LEVEL);
+    assert_eq!(levels[0].disk_tables.len(), 1);
+
+    for index in 10..30u32 {
+        assert_eq!(
+            shards.get(&make_key(index)),
+            Ok(Some(make_value(index, 0, 16)))
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_size_tiered_compaction_keeps_tombstones_over_last_tier() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let mut config = StorageConfig::default_config();
+    config.compaction_style = CompactionStyle::SizeTiered;
+    config.disk_tables_limit_by_level = 1;
+    let shards = DiskTablesShards::from_config(config);
+
+    // an old value of the last tier and a newer delete of it in the previous tier
+    let mut value = FlexibleUserEntry::new(make_key(1), make_value(1, 0, 16));
+    value.set_sequence(1);
+    let mut tombstone = FlexibleUserEntry::new_tombstone(make_key(1));
+    tombstone.set_sequence(5);
+    let level = SEGMENTS_MAX_LEVEL - 1;
+    for (table_id, level, entry) in [(1, SEGMENTS_MAX_LEVEL, value), (2, level, tombstone)] {
+        let mut builder = DiskTableBuilder::new(
+            tmp_dir
+                .path()
+                .join(format!("segment_{}_{}.bin", table_id, level)),
+            tmp_dir
+                .path()
+                .join(format!("segment_{}_{}.idx", table_id, level)),
+        );
+        builder.set_level(level).append_entry(&entry);
+        shards.put_disk_table_by_level(level, builder.build().unwrap());
+    }
+    assert_eq!(shards.get(&make_key(1)), Ok(None));
+
+    let compaction = shards.pick_compaction().unwrap();
+    assert_eq!(compaction.level, level);
+    assert!(!compaction.drop_tombstones);
+    let merged = shards
+        .compact(&compaction, || {
+            (
+                tmp_dir.path().join("segment_3_3.bin"),
+                tmp_dir.path().join("segment_3_3.idx"),
+            )
+        })
+        .unwrap();
+    shards.apply_compaction(&compaction, merged).unwrap();
+    assert_eq!(shards.get(&make_key(1)), Ok(None));
+
+    // the last tier is merged fully, the deleted key is dropped
+    let compaction = shards.pick_compaction().unwrap();
+    assert_eq!(compaction.level, SEGMENTS_MAX_LEVEL);
+    assert!(compaction.drop_tombstones);
+    let merged = shards
+        .compact(&compaction, || {
+            (
+                tmp_dir.path().join("segment_4_3.bin"),
+                tmp_dir.path().join("segment_4_3.idx"),
+            )
+        })
+        .unwrap();
+    shards.apply_compaction(&compaction, merged).unwrap();
+    assert_eq!(shards.get(&make_key(1)), Ok(None));
+
+    Ok(())
+}
+
+#[test]
+fn test_parallel_compactions() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let mut config = StorageConfig::default_config();
+    config.compaction_style = CompactionStyle::SizeTiered;
+    config.disk_tables_limit_by_level = 2;
+    let shards = DiskTablesShards::from_config(config);
+
+    // the second tier is older than the first one
+    for (table_id, level) in [(1, 2), (2, 2), (3, 1), (4, 1)] {
+        let mut builder = DiskTableBuilder::new(
+            tmp_dir
+                .path()
+                .join(format!("segment_{}_{}.bin", table_id, level)),
+            tmp_dir
+                .path()
+                .join(format!("segment_{}_{}.idx", table_id, level)),
+        );
+        for index in (table_id * 10)..(table_id * 10 + 10) {
+            builder.append_entry(&FlexibleUserEntry::new(
+                make_key(index),
+                make_value(index, 0, 16),
+            ));
+        }
+        shards.put_disk_table_by_level(level, builder.build().unwrap());
+    }
+
+    // tiers are merged into different levels by different tables
+    let first = shards.pick_compaction().unwrap();
+    assert_eq!((first.level, first.output_level), (1, 2));
+    let second = shards.pick_compaction().unwrap();
+    assert_eq!((second.level, second.output_level), (2, 3));
+    assert!(shards.pick_compaction().is_none());
+    assert!(shards
+        .level_tables()
+        .iter()
+        .all(|level| level.compacting.len() == level.disk_tables.len()));
+
+    let merged = shards
+        .compact(&second, || {
+            (
+                tmp_dir.path().join("segment_5_3.bin"),
+                tmp_dir.path().join("segment_5_3.idx"),
+            )
+        })
+        .unwrap();
+    shards.apply_compaction(&second, merged).unwrap();
+    let merged = shards
+        .compact(&first, || {
+            (
+                tmp_dir.path().join("segment_6_2.bin"),
+                tmp_dir.path().join("segment_6_2.idx"),
+            )
+        })
+        .unwrap();
+    shards.apply_compaction(&first, merged).unwrap();
+
+    assert!(shards.pick_compaction().is_none());
+    let levels = shards.level_tables();
+    assert_eq!(levels.len(), 2);
+    assert!(levels.iter().all(|level| !level.has_compacting()));
+
+    for index in 10..50u32 {
+        assert_eq!(
+            shards.get(&make_key(index)),
+            Ok(Some(make_value(index, 0, 16)))
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compaction_threads() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    for compaction_threads in [1, 4] {
+        let table_path = tmp_dir
+            .path()
+            .join(format!("test_compaction_threads_{}", compaction_threads));
+
+        let mut config = StorageConfig::default_config();
+        config.compaction_threads = compaction_threads;
+        config.mem_table_size = 16;
+        config.target_table_size = 8 * 1024;
+        config.level_base_size = 32 * 1024;
+
+        write_entries(table_path.as_path(), &config, 8, 256);
+
+        let tables = read_tables(table_path.as_path(), |properties, _reader| {
+            (properties.level, properties.source_tables.len())
+        })?;
+        assert!(tables.iter().any(|table| table.0 > SEGMENTS_MIN_LEVEL));
+
+        let table = OrderedStorage::new(table_path.as_path(), config);
+        for index in 0..512u32 {
+            assert_eq!(
+                table.get(&make_key(index)).unwrap(),
+                Some(make_value(index, 0, 256))
+            );
+        }
+        assert_eq!(table.iter().unwrap().count(), 512);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compaction_workers_survive_flushes() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir
+        .path()
+        .join("test_compaction_workers_survive_flushes");
+
+    let mut config = StorageConfig::default_config();
+    config.compaction_threads = 4;
+    config.mem_table_size = 16;
+    config.target_table_size = 8 * 1024;
+    config.level_base_size = 32 * 1024;
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    assert_eq!(table.count_compaction_workers(), config.compaction_threads);
+
+    // every burst is flushed and wakes all idle workers
+    for round in 0..8u32 {
+        for index in (round * 64)..(round * 64 + 64) {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 0, 256),
+                ))
+                .unwrap();
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+
+    assert_eq!(table.count_compaction_workers(), config.compaction_threads);
+    for index in 0..512u32 {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, 0, 256))
+        );
+    }
+
+    Ok(())
+}
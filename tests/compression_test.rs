@@ -0,0 +1,150 @@
+mod common;
+
+use std::{fs, io, path::Path};
+
+use tempfile::Builder;
+
+use common::make_key;
+use kvs::core::{
+    disk_table::local::{
+        block::compression::Compression, disk_table_builder::DiskTableBuilder,
+        reader_local_disk_table::ReaderDiskTablePtr,
+    },
+    entry::flexible_user_entry::FlexibleUserEntry,
+    field::{Field, FlexibleField},
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+fn make_value(index: u32) -> FlexibleField {
+    FlexibleField::new(
+        format!(
+            "{{\"id\":{},\"tenant\":\"default\",\"state\":\"active\"}}",
+            index
+        )
+        .repeat(8)
+        .into_bytes(),
+    )
+}
+
+// values of odd ranges of keys aren't compressed well
+fn make_mixed_value(index: u32) -> FlexibleField {
+    if index & 16 == 0 {
+        return make_value(index);
+    }
+    common::make_value(index, 0, 256)
+}
+
+fn build_disk_table(
+    tmp_dir: &Path,
+    name: &str,
+    compression: Compression,
+    make_value: fn(u32) -> FlexibleField,
+) -> ReaderDiskTablePtr {
+    let mut builder = DiskTableBuilder::new(
+        tmp_dir.join(format!("{}.bin", name)),
+        tmp_dir.join(format!("{}.idx", name)),
+    );
+    builder.set_compression(compression);
+    for index in 0..256u32 {
+        builder.append_entry(&FlexibleUserEntry::new(make_key(index), make_value(index)));
+    }
+    builder.build().unwrap()
+}
+
+#[test]
+fn test_compressed_table_is_smaller() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let plain = build_disk_table(tmp_dir.path(), "plain", Compression::None, make_value);
+    let compressed = build_disk_table(tmp_dir.path(), "lz4", Compression::Lz4, make_value);
+
+    let plain_size = fs::metadata(tmp_dir.path().join("plain.bin"))?.len();
+    let compressed_size = fs::metadata(tmp_dir.path().join("lz4.bin"))?.len();
+    assert!(
+        compressed_size * 2 < plain_size,
+        "compressed {} plain {}",
+        compressed_size,
+        plain_size
+    );
+
+    for index in 0..256u32 {
+        let key = make_key(index);
+        assert_eq!(plain.read(&key).unwrap(), Some(make_value(index)));
+        assert_eq!(compressed.read(&key).unwrap(), Some(make_value(index)));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_table_with_mixed_blocks() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    // incompressible blocks are stored without compression
+    let reader = build_disk_table(tmp_dir.path(), "mixed", Compression::Lz4, make_mixed_value);
+
+    for index in 0..256u32 {
+        assert_eq!(
+            reader.read(&make_key(index)).unwrap(),
+            Some(make_mixed_value(index))
+        );
+        assert_eq!(
+            reader.read_entry_by_index(index).unwrap().unwrap(),
+            FlexibleUserEntry::new(make_key(index), make_mixed_value(index))
+        );
+    }
+
+    for (entry, index) in reader.into_iter().zip(0..) {
+        assert_eq!(
+            entry,
+            FlexibleUserEntry::new(make_key(index), make_mixed_value(index))
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_storage_with_compression() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_storage_with_compression");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 16;
+    config.compression = Compression::None;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 0..128u32 {
+            table
+                .put(&FlexibleUserEntry::new(make_key(index), make_value(index)))
+                .unwrap();
+        }
+    }
+
+    // tables of both codecs are merged together
+    config.compression = Compression::Lz4;
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 128..512u32 {
+            table
+                .put(&FlexibleUserEntry::new(make_key(index), make_value(index)))
+                .unwrap();
+        }
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    for index in 0..512u32 {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index))
+        );
+    }
+    assert_eq!(table.iter().unwrap().count(), 512);
+
+    Ok(())
+}
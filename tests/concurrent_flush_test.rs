@@ -0,0 +1,76 @@
+mod common;
+
+use std::{
+    io,
+    sync::atomic::{AtomicU32, Ordering},
+    thread,
+    time::Duration,
+};
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+#[test]
+fn test_reads_during_flushes() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_reads_during_flushes");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 16;
+
+    let count = 1024u32;
+
+    let table = OrderedStorage::new(table_path, config);
+    // all keys below are written
+    let written = AtomicU32::new(0);
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for index in 0..count {
+                table
+                    .put(&FlexibleUserEntry::new(
+                        make_key(index),
+                        make_value(index, 0, 128),
+                    ))
+                    .unwrap();
+                written.store(index + 1, Ordering::SeqCst);
+
+                // let the flush worker swap memory tables some times
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        for _ in 0..2 {
+            scope.spawn(|| loop {
+                let limit = written.load(Ordering::SeqCst);
+
+                // written keys never disappear while memory tables are swapped and flushed
+                for index in (0..limit).rev().step_by(7) {
+                    assert_eq!(
+                        table.get(&make_key(index)).unwrap(),
+                        Some(make_value(index, 0, 128)),
+                        "key {} of {} written",
+                        index,
+                        limit
+                    );
+                }
+                assert!(table.iter().unwrap().count() >= limit as usize);
+
+                if limit == count {
+                    break;
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
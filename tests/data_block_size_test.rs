@@ -0,0 +1,112 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    disk_table::local::disk_table_builder::DiskTableBuilder,
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_DATA_BLOCK_ALIGN, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+fn count_blocks(data_block_size: usize) -> io::Result<usize> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let mut builder = DiskTableBuilder::new(
+        tmp_dir.path().join("segment_1_1.bin"),
+        tmp_dir.path().join("segment_1_1.idx"),
+    );
+    builder.set_data_block_size(data_block_size);
+    for index in 0..64u32 {
+        builder.append_entry(&FlexibleUserEntry::new(
+            make_key(index),
+            make_value(index, 0, 512),
+        ));
+    }
+    let reader = builder.build().unwrap();
+
+    for index in 0..64u32 {
+        assert_eq!(
+            reader.read(&make_key(index)).unwrap(),
+            Some(make_value(index, 0, 512))
+        );
+    }
+    assert_eq!(reader.into_iter().count(), 64);
+
+    Ok((0..)
+        .take_while(|index| reader.read_block(*index).unwrap().is_some())
+        .count())
+}
+
+#[test]
+fn test_table_with_large_blocks() -> io::Result<()> {
+    let small_blocks = count_blocks(DEFAULT_DATA_BLOCK_ALIGN)?;
+    let large_blocks = count_blocks(4 * DEFAULT_DATA_BLOCK_ALIGN)?;
+
+    assert!(large_blocks * 3 < small_blocks);
+
+    Ok(())
+}
+
+#[test]
+fn test_storage_with_different_block_sizes() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir
+        .path()
+        .join("test_storage_with_different_block_sizes");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 16;
+
+    // tables of every size are merged together
+    for (version, data_block_size) in [2, 1, 8].into_iter().enumerate() {
+        config.data_block_size = data_block_size * DEFAULT_DATA_BLOCK_ALIGN;
+
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in (version as u32 * 128)..((version as u32 + 1) * 128) {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 0, 512),
+                ))
+                .unwrap();
+        }
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    for index in 0..384u32 {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, 0, 512))
+        );
+    }
+    assert_eq!(table.iter().unwrap().count(), 384);
+
+    Ok(())
+}
+
+#[test]
+fn test_data_block_size_is_aligned() {
+    let mut config = StorageConfig::default_config();
+
+    config.data_block_size = 32;
+    assert_eq!(config.aligned_data_block_size(), DEFAULT_DATA_BLOCK_ALIGN);
+
+    config.data_block_size = 3 * DEFAULT_DATA_BLOCK_ALIGN;
+    assert_eq!(
+        config.aligned_data_block_size(),
+        3 * DEFAULT_DATA_BLOCK_ALIGN
+    );
+
+    config.data_block_size = 2 * DEFAULT_DATA_BLOCK_ALIGN + 1;
+    assert_eq!(
+        config.aligned_data_block_size(),
+        3 * DEFAULT_DATA_BLOCK_ALIGN
+    );
+}
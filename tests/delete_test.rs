@@ -0,0 +1,186 @@
+use std::io;
+
+use tempfile::Builder;
+
+use kvs::core::{
+    disk_table::{
+        disk_tables_shard::{DiskTablesShards, SEGMENTS_MAX_LEVEL},
+        local::disk_table_builder::DiskTableBuilder,
+    },
+    entry::flexible_user_entry::FlexibleUserEntry,
+    field::{Field, FlexibleField},
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+#[test]
+fn test_delete_from_mem_table() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_delete_from_mem_table");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 64;
+
+    let table = OrderedStorage::new(table_path, config);
+
+    let key = FlexibleField::new([1, 2, 3]);
+    table
+        .put(&FlexibleUserEntry::new(
+            key.clone(),
+            FlexibleField::new([10, 20, 30]),
+        ))
+        .unwrap();
+    assert!(table.get(&key).unwrap().is_some());
+
+    table.delete(&key).unwrap();
+    assert_eq!(table.get(&key).unwrap(), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_delete_from_disk_tables() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_delete_from_disk_tables");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 4;
+
+    let count = 128u32;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+        for index in 0..count {
+            let entry = FlexibleUserEntry::new(
+                FlexibleField::new(index.to_be_bytes()),
+                FlexibleField::new((index * 7).to_be_bytes()),
+            );
+            table.put(&entry).unwrap();
+        }
+
+        for index in (0..count).step_by(2) {
+            table
+                .delete(&FlexibleField::new(index.to_be_bytes()))
+                .unwrap();
+        }
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+    for index in 0..count {
+        let result = table.get(&FlexibleField::new(index.to_be_bytes())).unwrap();
+        if index % 2 == 0 {
+            assert_eq!(result, None, "key {} was deleted", index);
+        } else {
+            assert_eq!(
+                result.unwrap(),
+                FlexibleField::new((index * 7).to_be_bytes())
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_drop_tombstones_on_max_level() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let shards = DiskTablesShards::new();
+
+    let disk_table_path = tmp_dir.path().join("segment_1_3.bin");
+    let index_table_path = tmp_dir.path().join("segment_1_3.idx");
+
+    let mut builder = DiskTableBuilder::new(disk_table_path, index_table_path);
+    for i in 0..16u32 {
+        builder.append_entry(&FlexibleUserEntry::new(
+            FlexibleField::new(i.to_be_bytes()),
+            FlexibleField::new(vec![i as u8; 32]),
+        ));
+    }
+    shards.put_disk_table_by_level(SEGMENTS_MAX_LEVEL, builder.build().unwrap());
+
+    let disk_table_path = tmp_dir.path().join("segment_2_3.bin");
+    let index_table_path = tmp_dir.path().join("segment_2_3.idx");
+
+    let mut builder = DiskTableBuilder::new(disk_table_path, index_table_path);
+    for i in 0..8u32 {
+        builder.append_entry(&FlexibleUserEntry::new_tombstone(FlexibleField::new(
+            i.to_be_bytes(),
+        )));
+    }
+    shards.put_disk_table_by_level(SEGMENTS_MAX_LEVEL, builder.build().unwrap());
+
+    for i in 0..16u32 {
+        let result = shards.get(&FlexibleField::new(i.to_be_bytes())).unwrap();
+        assert_eq!(result.is_none(), i < 8);
+    }
+
+    let disk_table_path = tmp_dir.path().join("segment_3_3.bin");
+    let index_table_path = tmp_dir.path().join("segment_3_3.idx");
+
+    let reader = shards
+        .merge_level(
+            SEGMENTS_MAX_LEVEL,
+            disk_table_path.as_path(),
+            index_table_path.as_path(),
+        )
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(reader.count_entries(), 8);
+    for i in 0..16u32 {
+        let result = reader
+            .read_entry(&FlexibleField::new(i.to_be_bytes()))
+            .unwrap();
+        if i < 8 {
+            assert_eq!(result, None);
+        } else {
+            assert_eq!(
+                result.unwrap().get_value(),
+                &FlexibleField::new(vec![i as u8; 32])
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_only_tombstones_on_max_level() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let shards = DiskTablesShards::new();
+
+    let disk_table_path = tmp_dir.path().join("segment_1_3.bin");
+    let index_table_path = tmp_dir.path().join("segment_1_3.idx");
+
+    let mut builder = DiskTableBuilder::new(disk_table_path, index_table_path);
+    for i in 0..8u32 {
+        builder.append_entry(&FlexibleUserEntry::new_tombstone(FlexibleField::new(
+            i.to_be_bytes(),
+        )));
+    }
+    shards.put_disk_table_by_level(SEGMENTS_MAX_LEVEL, builder.build().unwrap());
+
+    let disk_table_path = tmp_dir.path().join("segment_2_3.bin");
+    let index_table_path = tmp_dir.path().join("segment_2_3.idx");
+
+    let merged = shards
+        .merge_level(
+            SEGMENTS_MAX_LEVEL,
+            disk_table_path.as_path(),
+            index_table_path.as_path(),
+        )
+        .unwrap();
+
+    assert!(merged.is_none());
+    assert!(!disk_table_path.exists());
+    assert!(!index_table_path.exists());
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::{fs, io};
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    disk_table::local::disk_table_builder::DiskTableBuilder,
+    entry::flexible_user_entry::FlexibleUserEntry, storage::config::DEFAULT_TEST_TABLES_PATH,
+};
+
+#[test]
+fn test_read_entry_by_index() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let index_table_path = tmp_dir.path().join("segment_1_1.idx");
+
+    let count = 100_000u32;
+    let mut builder = DiskTableBuilder::new(
+        tmp_dir.path().join("segment_1_1.bin"),
+        index_table_path.clone(),
+    );
+    for index in 0..count {
+        builder.append_entry(&FlexibleUserEntry::new(
+            make_key(index),
+            make_value(index, 0, 8),
+        ));
+    }
+    let reader = builder.build().unwrap();
+
+    assert_eq!(reader.count_entries(), count);
+    for index in (0..count).step_by(7).chain([count - 1]) {
+        assert_eq!(
+            reader.read_entry_by_index(index).unwrap().unwrap(),
+            FlexibleUserEntry::new(make_key(index), make_value(index, 0, 8))
+        );
+    }
+    assert!(reader.read_entry_by_index(count).unwrap().is_none());
+
+    // the index table has the filter and index blocks, but no offset of every entry
+    let index_table_size = fs::metadata(index_table_path)?.len();
+    assert!(
+        index_table_size < 2 * count as u64,
+        "index table {} bytes",
+        index_table_size
+    );
+
+    Ok(())
+}
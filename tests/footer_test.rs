@@ -0,0 +1,105 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+use tempfile::Builder;
+
+use kvs::{
+    core::{
+        disk_table::local::disk_table_builder::DiskTableBuilder,
+        entry::flexible_user_entry::FlexibleUserEntry,
+        field::{Field, FlexibleField},
+        storage::config::DEFAULT_TEST_TABLES_PATH,
+    },
+    errors::Error,
+};
+
+fn build_disk_table(tmp_dir: &Path, name: &str, count: u32) -> (PathBuf, PathBuf) {
+    let disk_table_path = tmp_dir.join(format!("{}.bin", name));
+    let index_table_path = tmp_dir.join(format!("{}.idx", name));
+
+    let mut builder = DiskTableBuilder::new(disk_table_path.as_path(), index_table_path.as_path());
+    for index in 0..count {
+        builder.append_entry(&FlexibleUserEntry::new(
+            FlexibleField::new(index.to_be_bytes()),
+            FlexibleField::new(vec![index as u8; 512]),
+        ));
+    }
+    builder.build().unwrap();
+
+    (disk_table_path, index_table_path)
+}
+
+fn open(disk_table_path: &Path, index_table_path: &Path) -> Option<Error> {
+    DiskTableBuilder::from(disk_table_path, index_table_path)
+        .build()
+        .err()
+}
+
+fn truncate(path: &Path, bytes: u64) -> io::Result<()> {
+    let file = OpenOptions::new().write(true).open(path)?;
+    let size = file.metadata()?.len();
+    file.set_len(size - bytes)
+}
+
+#[test]
+fn test_foreign_files() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let (disk_table_path, index_table_path) = build_disk_table(tmp_dir.path(), "segment_1_1", 64);
+
+    assert!(open(disk_table_path.as_path(), index_table_path.as_path()).is_none());
+
+    // data and index tables are swapped
+    assert!(matches!(
+        open(index_table_path.as_path(), disk_table_path.as_path()),
+        Some(Error::InvalidData(_))
+    ));
+
+    let foreign_path = tmp_dir.path().join("foreign.idx");
+    fs::write(foreign_path.as_path(), vec![7u8; 1024])?;
+    assert!(matches!(
+        open(disk_table_path.as_path(), foreign_path.as_path()),
+        Some(Error::InvalidData(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_truncated_files() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let (disk_table_path, index_table_path) = build_disk_table(tmp_dir.path(), "segment_1_1", 64);
+    truncate(index_table_path.as_path(), 10)?;
+    assert!(matches!(
+        open(disk_table_path.as_path(), index_table_path.as_path()),
+        Some(Error::InvalidData(_))
+    ));
+
+    // the last page of data file is lost
+    let (disk_table_path, index_table_path) = build_disk_table(tmp_dir.path(), "segment_2_1", 64);
+    truncate(disk_table_path.as_path(), 4096)?;
+    assert!(matches!(
+        open(disk_table_path.as_path(), index_table_path.as_path()),
+        Some(Error::InvalidData(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_data_table_of_other_index() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let (disk_table_path, _) = build_disk_table(tmp_dir.path(), "segment_1_1", 64);
+    let (_, index_table_path) = build_disk_table(tmp_dir.path(), "segment_2_1", 128);
+
+    assert!(matches!(
+        open(disk_table_path.as_path(), index_table_path.as_path()),
+        Some(Error::InvalidData(_))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    disk_table::local::disk_table_builder::DiskTableBuilder,
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+// every third value is larger than data block
+fn value_size(index: u32) -> usize {
+    match index % 3 {
+        0 => 20 * 1024 + index as usize,
+        _ => 100,
+    }
+}
+
+#[test]
+fn test_disk_table_with_large_entries() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let mut builder = DiskTableBuilder::new(
+        tmp_dir.path().join("segment_1_1.bin"),
+        tmp_dir.path().join("segment_1_1.idx"),
+    );
+    for index in 0..64u32 {
+        builder.append_entry(&FlexibleUserEntry::new(
+            make_key(index),
+            make_value(index, 0, value_size(index)),
+        ));
+    }
+    let reader = builder.build().unwrap();
+
+    for index in 0..64u32 {
+        assert_eq!(
+            reader.read(&make_key(index)).unwrap(),
+            Some(make_value(index, 0, value_size(index)))
+        );
+        assert_eq!(
+            reader.read_entry_by_index(index).unwrap().unwrap(),
+            FlexibleUserEntry::new(make_key(index), make_value(index, 0, value_size(index)))
+        );
+    }
+
+    for (entry, index) in reader.into_iter().zip(0..) {
+        assert_eq!(
+            entry,
+            FlexibleUserEntry::new(make_key(index), make_value(index, 0, value_size(index)))
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_storage_with_large_entries() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_storage_with_large_entries");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 8;
+
+    // overwrites go through merges of levels
+    for version in 0..3u32 {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 0..96u32 {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, version, value_size(index)),
+                ))
+                .unwrap();
+        }
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    for index in 0..96u32 {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, 2, value_size(index)))
+        );
+    }
+
+    let entries = table
+        .iter()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(entries.len(), 96);
+    for (entry, index) in entries.iter().zip(0..) {
+        assert_eq!(entry.get_value(), &make_value(index, 2, value_size(index)));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value, read_tables};
+use kvs::core::{
+    disk_table::{
+        compaction::max_level_size,
+        disk_tables_shard::{DiskTablesShards, SEGMENTS_MIN_LEVEL},
+        local::disk_table_builder::DiskTableBuilder,
+    },
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+#[test]
+fn test_levels_without_overlaps() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_levels_without_overlaps");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 64;
+    config.target_table_size = 16 * 1024;
+    config.level_base_size = 64 * 1024;
+
+    for version in 0..4u32 {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 0..1024u32 {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, version, 256),
+                ))
+                .unwrap();
+        }
+    }
+
+    // (level, smallest key, largest key, data size) of tables
+    let mut tables = read_tables(table_path.as_path(), |properties, reader| {
+        (
+            properties.level,
+            properties.smallest_key.clone(),
+            properties.largest_key.clone(),
+            reader.data_size(),
+        )
+    })?;
+    tables.sort();
+    assert!(tables.iter().any(|table| table.0 > SEGMENTS_MIN_LEVEL + 1));
+
+    for pair in tables.windows(2) {
+        let (lhs, rhs) = (&pair[0], &pair[1]);
+        if lhs.0 == rhs.0 && lhs.0 != SEGMENTS_MIN_LEVEL {
+            assert!(lhs.2 < rhs.1, "tables of level {} overlap", lhs.0);
+        }
+    }
+
+    // merged tables are split by the target size, a table can be larger by a block
+    for (level, _smallest, _largest, data_size) in &tables {
+        if *level != SEGMENTS_MIN_LEVEL {
+            assert!(*data_size < 2 * config.target_table_size as u64);
+        }
+    }
+
+    let level_size = |level| {
+        tables
+            .iter()
+            .filter(|table| table.0 == level)
+            .map(|table| table.3)
+            .sum::<u64>()
+    };
+    let max_size = max_level_size(SEGMENTS_MIN_LEVEL + 1, &config).unwrap();
+    assert!(level_size(SEGMENTS_MIN_LEVEL + 1) <= max_size);
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    for index in 0..1024u32 {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, 3, 256))
+        );
+    }
+    assert_eq!(table.iter().unwrap().count(), 1024);
+
+    Ok(())
+}
+
+#[test]
+fn test_compaction_of_overlapping_level() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let mut config = StorageConfig::default_config();
+    config.target_table_size = 4096;
+    let shards = DiskTablesShards::from_config(config);
+
+    // tables of the second level overlap like after merges of whole levels
+    for (table_id, version) in [(1, 0), (2, 1)] {
+        let mut builder = DiskTableBuilder::new(
+            tmp_dir.path().join(format!("segment_{}_2.bin", table_id)),
+            tmp_dir.path().join(format!("segment_{}_2.idx", table_id)),
+        );
+        for index in (version * 32)..(version * 32 + 64) {
+            let mut entry =
+                FlexibleUserEntry::new(make_key(index), make_value(index, version, 256));
+            entry.set_sequence(table_id as u64);
+            builder.append_entry(&entry);
+        }
+        shards.put_disk_table_by_level(2, builder.build().unwrap());
+    }
+    assert_eq!(shards.get(&make_key(40)), Ok(Some(make_value(40, 1, 256))));
+
+    let compaction = shards.pick_compaction().unwrap();
+    assert_eq!((compaction.level, compaction.output_level), (2, 3));
+    assert_eq!(compaction.inputs.len(), 2);
+
+    let mut table_id = 2;
+    let merged = shards
+        .compact(&compaction, || {
+            table_id += 1;
+            (
+                tmp_dir.path().join(format!("segment_{}_3.bin", table_id)),
+                tmp_dir.path().join(format!("segment_{}_3.idx", table_id)),
+            )
+        })
+        .unwrap();
+    assert!(merged.len() > 1);
+    shards.apply_compaction(&compaction, merged).unwrap();
+
+    assert!(shards.pick_compaction().is_none());
+    for index in 0..96u32 {
+        let version = if index < 32 { 0 } else { 1 };
+        assert_eq!(
+            shards.get(&make_key(index)),
+            Ok(Some(make_value(index, version, 256)))
+        );
+    }
+    assert_eq!(shards.get(&make_key(96)), Ok(None));
+
+    Ok(())
+}
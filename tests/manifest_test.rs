@@ -0,0 +1,167 @@
+mod common;
+
+use std::{fs, io, path::Path};
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    disk_table::local::disk_table_builder::DiskTableBuilder,
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        manifest::Manifest,
+        metadata::StorageMetadata,
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+fn fill(table_path: &Path, config: &StorageConfig, count: u32) {
+    let table = OrderedStorage::new(table_path, config.clone());
+    for index in 0..count {
+        table
+            .put(&FlexibleUserEntry::new(
+                make_key(index),
+                make_value(index, 0, 8),
+            ))
+            .unwrap();
+    }
+}
+
+fn count_disk_tables(table_path: &Path) -> usize {
+    fs::read_dir(table_path.join("segment"))
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension().unwrap() == "bin")
+        .count()
+}
+
+#[test]
+fn test_unreferenced_tables_are_removed() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_unreferenced_tables_are_removed");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 4;
+
+    fill(table_path.as_path(), &config, 64);
+    let count_disk_tables_before = count_disk_tables(table_path.as_path());
+
+    // output of merge which wasn't logged to manifest before crash
+    {
+        let segment_dir = table_path.join("segment");
+        let mut builder = DiskTableBuilder::new(
+            segment_dir.join("segment_9999999_1.bin"),
+            segment_dir.join("segment_9999999_1.idx"),
+        );
+        let mut entry = FlexibleUserEntry::new(make_key(1), make_value(1, 1, 8));
+        entry.set_sequence(u64::MAX - 1);
+        builder.append_entry(&entry);
+        builder.build().unwrap();
+    }
+    assert_eq!(
+        count_disk_tables(table_path.as_path()),
+        count_disk_tables_before + 1
+    );
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+    assert_eq!(
+        count_disk_tables(table_path.as_path()),
+        count_disk_tables_before
+    );
+    assert!(!table_path.join("segment/segment_9999999_1.idx").exists());
+
+    for index in 0..64 {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, 0, 8))
+        );
+    }
+    assert_eq!(table.iter().unwrap().count(), 64);
+
+    Ok(())
+}
+
+#[test]
+fn test_open_storage_without_manifest() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_open_storage_without_manifest");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 4;
+
+    fill(table_path.as_path(), &config, 64);
+    let count_disk_tables_before = count_disk_tables(table_path.as_path());
+
+    // disk tables are found by file names, metadata before sequence numbers keeps only id
+    fs::remove_file(Manifest::make_path(table_path.as_path()))?;
+    let metadata_path = StorageMetadata::make_path(table_path.as_path());
+    let metadata = fs::read_to_string(metadata_path.as_path())?;
+    fs::write(
+        metadata_path.as_path(),
+        metadata.split_whitespace().next().unwrap(),
+    )?;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 0..64 {
+            assert_eq!(
+                table.get(&make_key(index)).unwrap(),
+                Some(make_value(index, 0, 8))
+            );
+        }
+    }
+
+    assert!(Manifest::exists(table_path.as_path()));
+    assert_eq!(
+        Manifest::replay(table_path.as_path())
+            .unwrap()
+            .tables()
+            .count(),
+        count_disk_tables_before
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_manifest_through_restarts() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_manifest_through_restarts");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 4;
+
+    for version in 0..4u32 {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 0..32 {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, version, 8),
+                ))
+                .unwrap();
+        }
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    for index in 0..32 {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, 3, 8))
+        );
+    }
+
+    // files on disk are exactly the tables of manifest
+    let version = Manifest::replay(table_path.as_path()).unwrap();
+    assert_eq!(
+        version.tables().count(),
+        count_disk_tables(table_path.as_path())
+    );
+    for (disk_table_name, _level) in version.tables() {
+        assert!(table_path.join("segment").join(disk_table_name).exists());
+    }
+
+    Ok(())
+}
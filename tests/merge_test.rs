@@ -120,10 +120,13 @@ fn test_simple_merge() -> io::Result<()> {
     let disk_table_path = tmp_dir.path().join("segment_4_2.bin");
     let index_table_path = tmp_dir.path().join("segment_4_2.idx");
 
-    let reader = shards.merge_level(1, disk_table_path.as_path(), index_table_path.as_path());
+    let reader = shards
+        .merge_level(1, disk_table_path.as_path(), index_table_path.as_path())
+        .unwrap()
+        .unwrap();
 
     for index in 0..64u32 {
-        let r = reader.read_block(index as usize);
+        let r = reader.read_block(index as usize).unwrap();
         assert!(r.is_some());
         let block = r.unwrap();
 
@@ -0,0 +1,177 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    disk_table::{
+        disk_tables_shard::DiskTablesShards, local::disk_table_builder::DiskTableBuilder,
+    },
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+#[test]
+fn test_overwrite_in_mem_table() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_overwrite_in_mem_table");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 64;
+
+    let table = OrderedStorage::new(table_path, config);
+
+    // the later value sorts before the earlier one
+    for version in (0..4u32).rev() {
+        table
+            .put(&FlexibleUserEntry::new(
+                make_key(1),
+                make_value(1, version, 8),
+            ))
+            .unwrap();
+    }
+
+    assert_eq!(table.get(&make_key(1)).unwrap(), Some(make_value(1, 0, 8)));
+    assert_eq!(table.iter().unwrap().count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_overwrite_through_flushes_and_merges() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir
+        .path()
+        .join("test_overwrite_through_flushes_and_merges");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 4;
+
+    let count = 16u32;
+    let versions = 12u32;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+        for version in 0..versions {
+            for index in 0..count {
+                table
+                    .put(&FlexibleUserEntry::new(
+                        make_key(index),
+                        make_value(index, version, 8),
+                    ))
+                    .unwrap();
+            }
+        }
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+    for index in 0..count {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, versions - 1, 8))
+        );
+    }
+    assert_eq!(table.iter().unwrap().count(), count as usize);
+
+    Ok(())
+}
+
+#[test]
+fn test_overwrite_after_restart() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_overwrite_after_restart");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 4;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 0..8u32 {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 0, 8),
+                ))
+                .unwrap();
+        }
+    }
+
+    // sequence numbers continue after restart, so new writes win over disk tables
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+        for index in 0..8u32 {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 1, 8),
+                ))
+                .unwrap();
+        }
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    for index in 0..8u32 {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, 1, 8))
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_keeps_greatest_sequence() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let shards = DiskTablesShards::new();
+
+    // versions of key are spread over tables
+    for (table_index, sequences) in [[1u64, 6], [5, 2], [3, 4]].iter().enumerate() {
+        let disk_table_path = tmp_dir
+            .path()
+            .join(format!("segment_{}_1.bin", table_index));
+        let index_table_path = tmp_dir
+            .path()
+            .join(format!("segment_{}_1.idx", table_index));
+
+        let mut builder = DiskTableBuilder::new(disk_table_path, index_table_path);
+        for (index, sequence) in sequences.iter().enumerate() {
+            let mut entry = FlexibleUserEntry::new(
+                make_key(index as u32),
+                make_value(index as u32, *sequence as u32, 8),
+            );
+            entry.set_sequence(*sequence);
+            builder.append_entry(&entry);
+        }
+        shards.put_disk_table_by_level(1, builder.build().unwrap());
+    }
+
+    let disk_table_path = tmp_dir.path().join("segment_3_2.bin");
+    let index_table_path = tmp_dir.path().join("segment_3_2.idx");
+
+    let reader = shards
+        .merge_level(1, disk_table_path.as_path(), index_table_path.as_path())
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(reader.count_entries(), 2);
+
+    let entry = reader.read_entry(&make_key(0)).unwrap().unwrap();
+    assert_eq!(entry.get_sequence(), 5);
+    assert_eq!(entry.get_value(), &make_value(0, 5, 8));
+
+    let entry = reader.read_entry(&make_key(1)).unwrap().unwrap();
+    assert_eq!(entry.get_sequence(), 6);
+    assert_eq!(entry.get_value(), &make_value(1, 6, 8));
+
+    Ok(())
+}
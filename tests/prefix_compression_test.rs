@@ -0,0 +1,111 @@
+use std::io;
+
+use tempfile::Builder;
+
+use kvs::core::{
+    disk_table::local::{
+        block::compression::Compression, disk_table_builder::DiskTableBuilder,
+        reader_local_disk_table::ReaderDiskTablePtr,
+    },
+    entry::flexible_user_entry::FlexibleUserEntry,
+    field::{Field, FlexibleField},
+    storage::config::DEFAULT_TEST_TABLES_PATH,
+};
+
+// tenant id and entity type are shared by all keys
+fn make_key(index: u32) -> FlexibleField {
+    FlexibleField::new(format!("tenant-00000000000042/entity-user/{:08}", index).into_bytes())
+}
+
+fn make_value(index: u32, sequence: u64) -> FlexibleField {
+    FlexibleField::new([index.to_be_bytes(), (sequence as u32).to_be_bytes()].concat())
+}
+
+fn make_entry(index: u32, sequence: u64) -> FlexibleUserEntry {
+    let mut entry = FlexibleUserEntry::new(make_key(index), make_value(index, sequence));
+    entry.set_sequence(sequence);
+    entry
+}
+
+fn count_blocks(reader: &ReaderDiskTablePtr) -> usize {
+    (0..)
+        .take_while(|index| reader.read_block(*index).unwrap().is_some())
+        .count()
+}
+
+#[test]
+fn test_shared_prefixes_of_keys() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let mut builder = DiskTableBuilder::new(
+        tmp_dir.path().join("segment_1_1.bin"),
+        tmp_dir.path().join("segment_1_1.idx"),
+    );
+    builder.set_compression(Compression::None);
+    for index in 0..512u32 {
+        builder.append_entry(&make_entry(index, 1));
+    }
+    let reader = builder.build().unwrap();
+
+    // full keys would take 14 blocks
+    assert!(
+        count_blocks(&reader) <= 6,
+        "{} blocks",
+        count_blocks(&reader)
+    );
+
+    for index in 0..512u32 {
+        assert_eq!(
+            reader.read(&make_key(index)).unwrap(),
+            Some(make_value(index, 1))
+        );
+        assert_eq!(
+            reader.read_entry_by_index(index).unwrap(),
+            Some(make_entry(index, 1))
+        );
+    }
+    assert_eq!(
+        reader.read(&FlexibleField::new(
+            b"tenant-00000000000042/entity-user/".to_vec()
+        )),
+        Ok(None)
+    );
+
+    for (entry, index) in reader.into_iter().zip(0..) {
+        assert_eq!(entry, make_entry(index, 1));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_versions_across_restart_points() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let mut builder = DiskTableBuilder::new(
+        tmp_dir.path().join("segment_1_1.bin"),
+        tmp_dir.path().join("segment_1_1.idx"),
+    );
+    for index in 0..128u32 {
+        for sequence in (1..=5u64).rev() {
+            builder.append_entry(&make_entry(index, sequence));
+        }
+    }
+    let reader = builder.build().unwrap();
+
+    for index in 0..128u32 {
+        let key = make_key(index);
+        assert_eq!(reader.read_entry(&key).unwrap(), Some(make_entry(index, 5)));
+        for sequence in 1..=5u64 {
+            assert_eq!(
+                reader.read_entry_at(&key, sequence).unwrap(),
+                Some(make_entry(index, sequence))
+            );
+        }
+        assert_eq!(reader.read_entry_at(&key, 0).unwrap(), None);
+    }
+
+    assert_eq!(reader.into_iter().count(), 128 * 5);
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    disk_table::{
+        disk_tables_shard::DiskTablesShards, local::disk_table_builder::DiskTableBuilder,
+    },
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+};
+
+#[test]
+fn test_table_properties() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let disk_table_path = tmp_dir.path().join("segment_3_2.bin");
+    let index_table_path = tmp_dir.path().join("segment_3_2.idx");
+
+    let sources = vec!["segment_1_1.bin".to_string(), "segment_2_1.bin".to_string()];
+
+    let mut builder = DiskTableBuilder::new(disk_table_path.as_path(), index_table_path.as_path());
+    builder.set_level(2).set_source_tables(sources.clone());
+    for index in 10..42u32 {
+        match index % 4 {
+            0 => builder.append_entry(&FlexibleUserEntry::new_tombstone(make_key(index))),
+            _ => builder.append_entry(&FlexibleUserEntry::new(
+                make_key(index),
+                make_value(index, 0, 1024),
+            )),
+        };
+    }
+    let reader = builder.build().unwrap();
+
+    let properties = reader.properties().unwrap().clone();
+    assert_eq!(properties.smallest_key, make_key(10));
+    assert_eq!(properties.largest_key, make_key(41));
+    assert_eq!(properties.count_entries, 32);
+    assert_eq!(properties.count_tombstones, 8);
+    assert_eq!(properties.raw_key_size, 32 * 4);
+    assert_eq!(properties.raw_value_size, 24 * 1024);
+    assert_eq!(properties.level, 2);
+    assert_eq!(properties.source_tables, sources);
+    assert_ne!(properties.creation_time, 0);
+
+    // keys out of the range aren't looked for
+    assert_eq!(reader.read(&make_key(9)).unwrap(), None);
+    assert_eq!(reader.read(&make_key(42)).unwrap(), None);
+    assert_eq!(
+        reader.read(&make_key(41)).unwrap(),
+        Some(make_value(41, 0, 1024))
+    );
+
+    let reader = DiskTableBuilder::from(disk_table_path.as_path(), index_table_path.as_path())
+        .build()
+        .unwrap();
+    assert_eq!(reader.properties(), Some(&properties));
+
+    Ok(())
+}
+
+#[test]
+fn test_lookup_skips_tables_by_key_range() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    // every table would be read without filters
+    let mut config = StorageConfig::default_config();
+    config.bloom_bits_per_key = 0;
+    let shards = DiskTablesShards::from_config(config);
+
+    for table_index in 0..4u32 {
+        let disk_table_path = tmp_dir
+            .path()
+            .join(format!("segment_{}_1.bin", table_index));
+        let index_table_path = tmp_dir
+            .path()
+            .join(format!("segment_{}_1.idx", table_index));
+
+        let mut builder =
+            shards.new_disk_table_builder(disk_table_path.as_path(), index_table_path.as_path());
+        for index in 0..4u32 {
+            let key = table_index * 4 + index;
+            builder.append_entry(&FlexibleUserEntry::new(
+                make_key(key),
+                make_value(key, 0, 1024),
+            ));
+        }
+        shards.put_disk_table_by_level(1, builder.build().unwrap());
+    }
+
+    // 2 data blocks of the oldest table
+    for key in 0..4u32 {
+        assert_eq!(
+            shards.get(&make_key(key)).unwrap(),
+            Some(make_value(key, 0, 1024))
+        );
+    }
+    assert_eq!(shards.block_cache().len(), 2);
+
+    assert_eq!(shards.get(&make_key(16)).unwrap(), None);
+    assert_eq!(shards.block_cache().len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_merged_table_properties() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let shards = DiskTablesShards::new();
+    for table_index in 0..2u32 {
+        let disk_table_path = tmp_dir
+            .path()
+            .join(format!("segment_{}_1.bin", table_index));
+        let index_table_path = tmp_dir
+            .path()
+            .join(format!("segment_{}_1.idx", table_index));
+
+        let mut builder =
+            shards.new_disk_table_builder(disk_table_path.as_path(), index_table_path.as_path());
+        for index in 0..8u32 {
+            let key = table_index * 4 + index;
+            builder.append_entry(&FlexibleUserEntry::new(
+                make_key(key),
+                make_value(key, 0, 1024),
+            ));
+        }
+        shards.put_disk_table_by_level(1, builder.build().unwrap());
+    }
+
+    let disk_table_path = tmp_dir.path().join("segment_2_2.bin");
+    let index_table_path = tmp_dir.path().join("segment_2_2.idx");
+    let merged = shards
+        .merge_level(1, disk_table_path.as_path(), index_table_path.as_path())
+        .unwrap()
+        .unwrap();
+
+    let properties = merged.properties().unwrap();
+    assert_eq!(properties.level, 2);
+    assert_eq!(
+        properties.source_tables,
+        vec!["segment_1_1.bin".to_string(), "segment_0_1.bin".to_string()]
+    );
+    assert_eq!(
+        (
+            properties.smallest_key.clone(),
+            properties.largest_key.clone()
+        ),
+        (make_key(0), make_key(11))
+    );
+    // versions of overlapped keys are merged
+    assert_eq!(properties.count_entries, 12);
+
+    Ok(())
+}
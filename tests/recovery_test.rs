@@ -0,0 +1,88 @@
+use std::io;
+
+use tempfile::Builder;
+
+use kvs::core::{
+    entry::flexible_user_entry::FlexibleUserEntry,
+    field::{Field, FlexibleField},
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+#[test]
+fn test_recovery_without_flush() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_recovery_without_flush");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 64;
+
+    let count = config.mem_table_size as u32 / 2;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+        for index in 0..count {
+            let entry = FlexibleUserEntry::new(
+                FlexibleField::new(index.to_be_bytes()),
+                FlexibleField::new((index * 3).to_be_bytes()),
+            );
+            table.put(&entry).unwrap();
+        }
+
+        // crash: the memory table is never flushed
+        std::mem::forget(table);
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+    for index in 0..count {
+        let result = table.get(&FlexibleField::new(index.to_be_bytes())).unwrap();
+        assert_eq!(
+            result.unwrap(),
+            FlexibleField::new((index * 3).to_be_bytes())
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_recovery_after_some_crashes() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_recovery_after_some_crashes");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 64;
+
+    let ranges = [0..10u32, 10..20u32, 20..30u32];
+
+    for range in ranges.clone() {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+        for index in range {
+            let entry = FlexibleUserEntry::new(
+                FlexibleField::new(index.to_be_bytes()),
+                FlexibleField::new((index * 3).to_be_bytes()),
+            );
+            table.put(&entry).unwrap();
+        }
+
+        std::mem::forget(table);
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+    for index in 0..ranges.last().unwrap().end {
+        let result = table.get(&FlexibleField::new(index.to_be_bytes())).unwrap();
+        assert_eq!(
+            result.unwrap(),
+            FlexibleField::new((index * 3).to_be_bytes())
+        );
+    }
+
+    Ok(())
+}
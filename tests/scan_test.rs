@@ -0,0 +1,159 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+#[test]
+fn test_scan_mem_table() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_scan_mem_table");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 128;
+
+    let table = OrderedStorage::new(table_path, config);
+
+    for index in (0..32u32).rev() {
+        table
+            .put(&FlexibleUserEntry::new(
+                make_key(index),
+                make_value(index, 0, 8),
+            ))
+            .unwrap();
+    }
+
+    let keys = table
+        .scan(make_key(10)..make_key(20))
+        .unwrap()
+        .map(|entry| entry.unwrap().get_key().clone())
+        .collect::<Vec<_>>();
+
+    assert_eq!(keys, (10..20u32).map(make_key).collect::<Vec<_>>());
+    assert_eq!(table.iter().unwrap().count(), 32);
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_disk_tables() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_scan_disk_tables");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 4;
+
+    let count = 256u32;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+        for index in 0..count {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 0, 8),
+                ))
+                .unwrap();
+        }
+
+        // newer versions of some keys
+        for index in (0..count).step_by(3) {
+            table
+                .put(&FlexibleUserEntry::new(
+                    make_key(index),
+                    make_value(index, 1, 8),
+                ))
+                .unwrap();
+        }
+
+        for index in (0..count).step_by(5) {
+            table.delete(&make_key(index)).unwrap();
+        }
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+    let expected = (0..count)
+        .filter(|index| index % 5 != 0)
+        .map(|index| {
+            let version = if index % 3 == 0 { 1 } else { 0 };
+            (make_key(index), make_value(index, version, 8))
+        })
+        .collect::<Vec<_>>();
+
+    let actual = table
+        .iter()
+        .unwrap()
+        .map(Result::unwrap)
+        .map(|entry| (entry.get_key().clone(), entry.get_value().clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(actual, expected);
+
+    let actual = table
+        .scan(make_key(100)..make_key(150))
+        .unwrap()
+        .map(Result::unwrap)
+        .map(|entry| (entry.get_key().clone(), entry.get_value().clone()))
+        .collect::<Vec<_>>();
+    let expected_range = expected
+        .iter()
+        .filter(|(key, _value)| *key >= make_key(100) && *key < make_key(150))
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(actual, expected_range);
+
+    Ok(())
+}
+
+#[test]
+fn test_seek() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_seek");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 8;
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+    for index in (0..128u32).step_by(2) {
+        table
+            .put(&FlexibleUserEntry::new(
+                make_key(index),
+                make_value(index, 0, 8),
+            ))
+            .unwrap();
+    }
+
+    let mut it = table.scan(make_key(20)..make_key(100)).unwrap();
+    assert_eq!(it.next().unwrap().unwrap().get_key(), &make_key(20));
+
+    // between keys
+    it.seek(&make_key(51));
+    assert_eq!(it.next().unwrap().unwrap().get_key(), &make_key(52));
+    assert_eq!(it.next().unwrap().unwrap().get_key(), &make_key(54));
+
+    // backward, but before the start of range
+    it.seek(&make_key(0));
+    assert_eq!(it.next().unwrap().unwrap().get_key(), &make_key(20));
+
+    it.seek(&make_key(98));
+    assert_eq!(it.next().unwrap().unwrap().get_key(), &make_key(98));
+    assert!(it.next().is_none());
+
+    let mut it = table.iter().unwrap();
+    it.seek(&make_key(200));
+    assert!(it.next().is_none());
+
+    Ok(())
+}
@@ -154,6 +154,32 @@ fn test_get_from_some_data_blocks() -> io::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_discard_built_table() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let disk_table_path = tmp_dir.path().join("segment_1_1.bin");
+    let index_table_path = tmp_dir.path().join("segment_1_1.idx");
+
+    let mut builder = DiskTableBuilder::new(disk_table_path.clone(), index_table_path.clone());
+    for index in 0..16u32 {
+        builder.append_entry(&FlexibleUserEntry::new(
+            FlexibleField::new(index.to_be_bytes()),
+            FlexibleField::new(index.to_le_bytes()),
+        ));
+    }
+    builder.build().unwrap();
+
+    // writers are taken by build, as after a failed open of the built table
+    builder.discard().unwrap();
+    assert!(!disk_table_path.exists());
+    assert!(!index_table_path.exists());
+
+    // files which are already removed aren't an error
+    builder.discard().unwrap();
+
+    Ok(())
+}
+
 #[test]
 fn test_some_put() -> io::Result<()> {
     let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
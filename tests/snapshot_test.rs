@@ -0,0 +1,206 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    disk_table::{
+        disk_tables_shard::{DiskTablesShards, SEGMENTS_MAX_LEVEL},
+        local::disk_table_builder::DiskTableBuilder,
+    },
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+    },
+};
+
+fn make_entry(index: u32, version: u32) -> FlexibleUserEntry {
+    let mut entry = FlexibleUserEntry::new(make_key(index), make_value(index, version, 8));
+    entry.set_sequence(version as u64);
+    entry
+}
+
+#[test]
+fn test_snapshot_in_mem_table() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_snapshot_in_mem_table");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 64;
+
+    let table = OrderedStorage::new(table_path, config);
+
+    for index in 0..4u32 {
+        table
+            .put(&FlexibleUserEntry::new(
+                make_key(index),
+                make_value(index, 0, 8),
+            ))
+            .unwrap();
+    }
+
+    let snapshot = table.snapshot();
+
+    table
+        .put(&FlexibleUserEntry::new(make_key(1), make_value(1, 1, 8)))
+        .unwrap();
+    table.delete(&make_key(2)).unwrap();
+    table
+        .put(&FlexibleUserEntry::new(make_key(8), make_value(8, 0, 8)))
+        .unwrap();
+
+    assert_eq!(
+        snapshot.get(&make_key(1)).unwrap(),
+        Some(make_value(1, 0, 8))
+    );
+    assert_eq!(
+        snapshot.get(&make_key(2)).unwrap(),
+        Some(make_value(2, 0, 8))
+    );
+    assert_eq!(snapshot.get(&make_key(8)).unwrap(), None);
+
+    let keys = snapshot
+        .iter()
+        .unwrap()
+        .map(|entry| entry.unwrap().get_key().clone())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, (0..4u32).map(make_key).collect::<Vec<_>>());
+
+    assert_eq!(table.get(&make_key(1)).unwrap(), Some(make_value(1, 1, 8)));
+    assert_eq!(table.get(&make_key(2)).unwrap(), None);
+    assert_eq!(table.iter().unwrap().count(), 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_through_flushes_and_merges() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir
+        .path()
+        .join("test_snapshot_through_flushes_and_merges");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 4;
+
+    let count = 16u32;
+    let versions = 12u32;
+
+    let table = OrderedStorage::new(table_path, config);
+
+    for index in 0..count {
+        table
+            .put(&FlexibleUserEntry::new(
+                make_key(index),
+                make_value(index, 0, 8),
+            ))
+            .unwrap();
+    }
+
+    let snapshot = table.snapshot();
+
+    for version in 1..versions {
+        for index in 0..count {
+            if version % 4 == 0 {
+                table.delete(&make_key(index)).unwrap();
+            } else {
+                table
+                    .put(&FlexibleUserEntry::new(
+                        make_key(index),
+                        make_value(index, version, 8),
+                    ))
+                    .unwrap();
+            }
+        }
+    }
+
+    for index in 0..count {
+        assert_eq!(
+            snapshot.get(&make_key(index)).unwrap(),
+            Some(make_value(index, 0, 8))
+        );
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, versions - 1, 8))
+        );
+    }
+
+    let actual = snapshot
+        .scan(make_key(4)..make_key(8))
+        .unwrap()
+        .map(|entry| entry.unwrap().get_value().clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        actual,
+        (4..8u32)
+            .map(|index| make_value(index, 0, 8))
+            .collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_keeps_versions_for_snapshots() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+
+    let shards = DiskTablesShards::new();
+
+    // key 0 has versions 1..=6 in different tables, key 1 is deleted at 6
+    for version in 1..=6u32 {
+        let disk_table_path = tmp_dir.path().join(format!("segment_{}_3.bin", version));
+        let index_table_path = tmp_dir.path().join(format!("segment_{}_3.idx", version));
+
+        let mut builder = DiskTableBuilder::new(disk_table_path, index_table_path);
+        builder.append_entry(&make_entry(0, version));
+        if version == 6 {
+            let mut tombstone = FlexibleUserEntry::new_tombstone(make_key(1));
+            tombstone.set_sequence(version as u64);
+            builder.append_entry(&tombstone);
+        } else {
+            builder.append_entry(&make_entry(1, version));
+        }
+        shards.put_disk_table_by_level(SEGMENTS_MAX_LEVEL, builder.build().unwrap());
+    }
+
+    shards.snapshots().acquire(2);
+    shards.snapshots().acquire(4);
+
+    let disk_table_path = tmp_dir.path().join("segment_7_3.bin");
+    let index_table_path = tmp_dir.path().join("segment_7_3.idx");
+
+    let reader = shards
+        .merge_level(
+            SEGMENTS_MAX_LEVEL,
+            disk_table_path.as_path(),
+            index_table_path.as_path(),
+        )
+        .unwrap()
+        .unwrap();
+
+    // versions 6, 4, 2 of both keys
+    assert_eq!(reader.count_entries(), 6);
+
+    for (sequence, version) in [(u64::MAX, 6), (5, 4), (4, 4), (3, 2), (2, 2)] {
+        let entry = reader
+            .read_entry_at(&make_key(0), sequence)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.get_value(), &make_value(0, version, 8));
+    }
+    assert!(reader.read_entry_at(&make_key(0), 1).unwrap().is_none());
+
+    assert!(reader
+        .read_entry(&make_key(1))
+        .unwrap()
+        .unwrap()
+        .is_tombstone());
+    let entry = reader.read_entry_at(&make_key(1), 4).unwrap().unwrap();
+    assert_eq!(entry.get_value(), &make_value(1, 4, 8));
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use std::{fs, io, path::Path};
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+        value_log::ValueLog,
+    },
+};
+
+// every even value is large enough for the value log
+fn value_size(index: u32) -> usize {
+    match index % 2 {
+        0 => 4096,
+        _ => 64,
+    }
+}
+
+fn make_config() -> StorageConfig {
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 8;
+    config.value_threshold = 1024;
+    config.value_log_file_size = 64 * 1024;
+    config
+}
+
+fn put_all(table_path: &Path, config: &StorageConfig, version: u32) {
+    let table = OrderedStorage::new(table_path, config.clone());
+    for index in 0..64u32 {
+        table
+            .put(&FlexibleUserEntry::new(
+                make_key(index),
+                make_value(index, version, value_size(index)),
+            ))
+            .unwrap();
+    }
+}
+
+fn check_all(table: &OrderedStorage, version: u32) {
+    for index in 0..64u32 {
+        assert_eq!(
+            table.get(&make_key(index)).unwrap(),
+            Some(make_value(index, version, value_size(index)))
+        );
+    }
+
+    let entries = table
+        .iter()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(entries.len(), 64);
+    for (entry, index) in entries.iter().zip(0..) {
+        assert_eq!(
+            entry.get_value(),
+            &make_value(index, version, value_size(index))
+        );
+    }
+}
+
+fn dir_size(path: &Path) -> io::Result<u64> {
+    fs::read_dir(path)?.try_fold(0, |size, entry| Ok(size + entry?.metadata()?.len()))
+}
+
+#[test]
+fn test_large_values_in_value_log() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_large_values_in_value_log");
+
+    let config = make_config();
+    put_all(table_path.as_path(), &config, 0);
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    check_all(&table, 0);
+
+    // disk tables keep only pointers to large values
+    let values_size = 32 * 4096;
+    assert!(dir_size(ValueLog::make_path(table_path.as_path()).as_path())? > values_size);
+    assert!(dir_size(table_path.join("segment").as_path())? < values_size);
+
+    Ok(())
+}
+
+#[test]
+fn test_value_log_garbage_collection() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_value_log_garbage_collection");
+    let value_log_path = ValueLog::make_path(table_path.as_path());
+
+    let config = make_config();
+    for version in 0..4u32 {
+        put_all(table_path.as_path(), &config, version);
+    }
+    let size_before = dir_size(value_log_path.as_path())?;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+        // old versions aren't rewritten
+        let mut rewritten = 0;
+        while fs::read_dir(value_log_path.as_path())?.count() > 1 {
+            rewritten += table.collect_value_log_garbage().unwrap();
+        }
+        assert!(rewritten <= 32);
+
+        check_all(&table, 3);
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    check_all(&table, 3);
+    assert!(dir_size(value_log_path.as_path())? * 2 < size_before);
+
+    Ok(())
+}
+
+#[test]
+fn test_value_log_garbage_collection_with_snapshot() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir
+        .path()
+        .join("test_value_log_garbage_collection_with_snapshot");
+
+    let config = make_config();
+    put_all(table_path.as_path(), &config, 0);
+    put_all(table_path.as_path(), &config, 1);
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+    // the snapshot can read values of collected files
+    {
+        let _snapshot = table.snapshot();
+        assert_eq!(table.collect_value_log_garbage().unwrap(), 0);
+    }
+
+    let count_files = fs::read_dir(ValueLog::make_path(table_path.as_path()))?.count();
+    table.collect_value_log_garbage().unwrap();
+    assert_eq!(
+        fs::read_dir(ValueLog::make_path(table_path.as_path()))?.count(),
+        count_files - 1
+    );
+    check_all(&table, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_corrupted_value_log_in_scan() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_corrupted_value_log_in_scan");
+    let value_log_path = ValueLog::make_path(table_path.as_path());
+
+    let config = make_config();
+    put_all(table_path.as_path(), &config, 0);
+
+    // a byte in the middle of some value
+    let mut paths = fs::read_dir(value_log_path.as_path())?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+    let mut data = fs::read(paths[0].as_path())?;
+    let offset = data.len() / 2;
+    data[offset] ^= 0xff;
+    fs::write(paths[0].as_path(), data)?;
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+    let mut it = table.iter().unwrap();
+    assert!(it.by_ref().any(|entry| entry.is_err()));
+    assert!(it.next().is_none());
+
+    Ok(())
+}
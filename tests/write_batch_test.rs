@@ -0,0 +1,109 @@
+mod common;
+
+use std::io;
+
+use tempfile::Builder;
+
+use common::{make_key, make_value};
+use kvs::core::{
+    entry::flexible_user_entry::FlexibleUserEntry,
+    storage::{
+        config::{StorageConfig, DEFAULT_TEST_TABLES_PATH},
+        ordered_storage::OrderedStorage,
+        storage::Storage,
+        write_batch::WriteBatch,
+    },
+};
+
+#[test]
+fn test_write_batch() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_write_batch");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 64;
+
+    let table = OrderedStorage::new(table_path, config);
+
+    table
+        .put(&FlexibleUserEntry::new(make_key(0), make_value(0, 0, 8)))
+        .unwrap();
+
+    let before = table.snapshot();
+
+    let mut batch = WriteBatch::new();
+    batch.delete(&make_key(0));
+    for index in 1..4u32 {
+        batch.put(&FlexibleUserEntry::new(
+            make_key(index),
+            make_value(index, 0, 8),
+        ));
+    }
+    // the later write of key in batch wins
+    batch.put(&FlexibleUserEntry::new(make_key(3), make_value(3, 1, 8)));
+    assert_eq!(batch.len(), 5);
+
+    table.write(&batch).unwrap();
+
+    let after = table.snapshot();
+    assert_eq!(after.sequence(), before.sequence() + batch.len() as u64);
+
+    assert_eq!(before.get(&make_key(0)).unwrap(), Some(make_value(0, 0, 8)));
+    assert_eq!(before.iter().unwrap().count(), 1);
+
+    assert_eq!(table.get(&make_key(0)).unwrap(), None);
+    assert_eq!(table.get(&make_key(1)).unwrap(), Some(make_value(1, 0, 8)));
+    assert_eq!(table.get(&make_key(3)).unwrap(), Some(make_value(3, 1, 8)));
+    assert_eq!(table.iter().unwrap().count(), 3);
+
+    table.write(&WriteBatch::new()).unwrap();
+    assert_eq!(table.snapshot().sequence(), after.sequence());
+
+    Ok(())
+}
+
+#[test]
+fn test_write_batch_recovery() -> io::Result<()> {
+    let tmp_dir = Builder::new().prefix(DEFAULT_TEST_TABLES_PATH).tempdir()?;
+    let table_path = tmp_dir.path().join("test_write_batch_recovery");
+
+    let mut config = StorageConfig::default_config();
+    config.mem_table_size = 64;
+
+    let count = 16u32;
+
+    {
+        let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+        let mut batch = WriteBatch::new();
+        for index in 0..count {
+            batch.put(&FlexibleUserEntry::new(
+                make_key(index),
+                make_value(index, 0, 8),
+            ));
+        }
+        table.write(&batch).unwrap();
+
+        batch.clear();
+        for index in (0..count).step_by(2) {
+            batch.delete(&make_key(index));
+        }
+        table.write(&batch).unwrap();
+
+        // crash: the memory table is never flushed
+        std::mem::forget(table);
+    }
+
+    let table = OrderedStorage::new(table_path.as_path(), config.clone());
+
+    for index in 0..count {
+        let result = table.get(&make_key(index)).unwrap();
+        if index % 2 == 0 {
+            assert_eq!(result, None);
+        } else {
+            assert_eq!(result, Some(make_value(index, 0, 8)));
+        }
+    }
+
+    Ok(())
+}